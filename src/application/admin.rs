@@ -0,0 +1,177 @@
+//! # Admin
+//!
+//! Serves the admin endpoint of the node, which is reachable only from the loopback interface.
+//! Each connection carries a single JSON request line and gets back a single JSON response line
+
+use libp2p::Multiaddr;
+use std::fmt;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc::UnboundedSender, oneshot};
+
+/// Default port of the admin endpoint
+pub const DEFAULT_ADMIN_PORT: u16 = 4100;
+/// Maximum size in bytes of a request line
+const MAX_REQUEST_SIZE: u64 = 1024;
+
+/// A request sent to the admin endpoint
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AdminRequest {
+    /// Get the peers connected to the node
+    Peers,
+}
+
+/// A request received on the admin endpoint, along with the channel to send the response on
+pub type AdminCommand = (AdminRequest, oneshot::Sender<ConnectedPeers>);
+
+/// The peers connected to a node
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct ConnectedPeers {
+    /// Addresses the node can be dialed at
+    pub addresses: Vec<String>,
+    pub peers: Vec<PeerInfo>,
+}
+
+impl ConnectedPeers {
+    pub fn new(addresses: &[Multiaddr], peers: Vec<PeerInfo>) -> Self {
+        Self {
+            addresses: addresses.iter().map(|x| x.to_string()).collect(),
+            peers,
+        }
+    }
+}
+
+/// A peer connected to a node
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct PeerInfo {
+    /// Peer id
+    pub id: String,
+    /// Remote address of the connection with the peer
+    pub address: String,
+    /// Whether the peer is a registered miner
+    pub miner: bool,
+}
+
+impl PeerInfo {
+    pub fn new(id: impl ToString, address: impl ToString, miner: bool) -> Self {
+        Self {
+            id: id.to_string(),
+            address: address.to_string(),
+            miner,
+        }
+    }
+}
+
+impl fmt::Display for PeerInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.id, self.address)?;
+        if self.miner {
+            write!(f, " (miner)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Admin endpoint
+pub struct AdminServer;
+
+impl AdminServer {
+    /// Listen on the loopback interface at `port`; requests are forwarded through `command_sender`.
+    /// Returns the address the endpoint is listening on
+    pub async fn bind(
+        port: u16,
+        command_sender: UnboundedSender<AdminCommand>,
+    ) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port)).await?;
+        let address = listener.local_addr()?;
+        tokio::spawn(Self::accept(listener, command_sender));
+        Ok(address)
+    }
+
+    /// Send `request` to the admin endpoint at `address` and wait for the response
+    pub async fn request(address: SocketAddr, request: AdminRequest) -> io::Result<ConnectedPeers> {
+        let mut stream = TcpStream::connect(address).await?;
+        let mut line = serde_json::to_string(&request)?;
+        line.push('\n');
+        stream.write_all(line.as_bytes()).await?;
+        let mut response = String::new();
+        BufReader::new(stream).read_line(&mut response).await?;
+        Ok(serde_json::from_str(&response)?)
+    }
+
+    /// Accept connections until the application terminates
+    async fn accept(listener: TcpListener, command_sender: UnboundedSender<AdminCommand>) {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let command_sender = command_sender.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = Self::serve(stream, command_sender).await {
+                            error!("failed to serve admin request: {}", err);
+                        }
+                    });
+                }
+                Err(err) => error!("failed to accept admin connection: {}", err),
+            }
+        }
+    }
+
+    /// Serve the request received on `stream`
+    async fn serve(
+        stream: TcpStream,
+        command_sender: UnboundedSender<AdminCommand>,
+    ) -> io::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut line = String::new();
+        BufReader::new(reader.take(MAX_REQUEST_SIZE))
+            .read_line(&mut line)
+            .await?;
+        let request: AdminRequest = serde_json::from_str(&line)?;
+        debug!("received admin request {:?}", request);
+        let (response_sender, response) = oneshot::channel();
+        command_sender
+            .send((request, response_sender))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "application terminated"))?;
+        let response = response
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "request not served"))?;
+        let mut line = serde_json::to_string(&response)?;
+        line.push('\n');
+        writer.write_all(line.as_bytes()).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn should_serve_peers_on_loopback() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let address = AdminServer::bind(0, sender).await.unwrap();
+        assert!(address.ip().is_loopback());
+        let peers = ConnectedPeers::new(
+            &["/ip4/203.0.113.7/tcp/4000".parse().unwrap()],
+            vec![PeerInfo::new("peer", "/ip4/127.0.0.1/tcp/4000", true)],
+        );
+        let expected = peers.clone();
+        tokio::spawn(async move {
+            let (request, response) = receiver.recv().await.unwrap();
+            assert_eq!(request, AdminRequest::Peers);
+            response.send(peers).unwrap();
+        });
+        assert_eq!(
+            AdminServer::request(address, AdminRequest::Peers)
+                .await
+                .unwrap(),
+            expected
+        );
+    }
+}
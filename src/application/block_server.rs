@@ -0,0 +1,283 @@
+//! # Block server
+//!
+//! Reads the blocks requested by peers on blocking threads, so serving them never blocks the main loop
+
+use super::sync::SYNC_RANGE_SIZE;
+
+use jab::blockchain::ChainReader;
+use jab::net::message::ErrorCode;
+use jab::net::Msg;
+
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender, UnboundedSender};
+use tokio::sync::Mutex;
+
+/// Maximum amount of block requests waiting for a free worker; exceeding requests are rejected
+pub const BLOCK_REQUEST_QUEUE_SIZE: usize = 64;
+
+/// Messages to deliver for a served block request, along with their recipient
+pub type BlockResponse = (BlockRecipient, Vec<Msg>);
+
+/// Recipient of the messages of a served block request
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum BlockRecipient {
+    /// Blocks are published to all the peers, while errors are sent to the requester, if it expects a reply
+    Topic(Option<String>),
+    /// All the messages are sent to the requester only
+    Peer(String),
+}
+
+/// A request for blocks received from a peer
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum BlockRequest {
+    /// Request for block #`index`; `peer_id` is set if the requester expects a reply
+    Single { index: u64, peer_id: Option<String> },
+    /// Request for the blocks from `from` to `to` (included), sent to `peer_id` only
+    Range { from: u64, to: u64, peer_id: String },
+}
+
+impl BlockRequest {
+    /// Get the recipient of the response
+    fn recipient(&self) -> BlockRecipient {
+        match self {
+            Self::Single { peer_id, .. } => BlockRecipient::Topic(peer_id.clone()),
+            Self::Range { peer_id, .. } => BlockRecipient::Peer(peer_id.clone()),
+        }
+    }
+}
+
+/// Block serving workers
+pub struct BlockServer {
+    request_sender: Sender<BlockRequest>,
+    response_sender: UnboundedSender<BlockResponse>,
+}
+
+impl BlockServer {
+    /// Instantiate a new `BlockServer` reading blocks on `workers` long-lived workers.
+    /// At most `queue_size` requests wait for a free worker; exceeding requests are rejected.
+    /// Responses are sent through `response_sender`
+    pub fn new(
+        blockchain: ChainReader,
+        workers: usize,
+        queue_size: usize,
+        response_sender: UnboundedSender<BlockResponse>,
+    ) -> Self {
+        let (request_sender, request_receiver) = mpsc::channel(queue_size);
+        let request_receiver = Arc::new(Mutex::new(request_receiver));
+        for _ in 0..workers {
+            tokio::spawn(Self::worker(
+                blockchain.clone(),
+                request_receiver.clone(),
+                response_sender.clone(),
+            ));
+        }
+        Self {
+            request_sender,
+            response_sender,
+        }
+    }
+
+    /// Queue `request` for a worker; returns immediately.
+    /// If the queue is full, the request is dropped and the requester is sent an `Error` message
+    pub fn serve(&self, request: BlockRequest) {
+        match self.request_sender.try_send(request) {
+            Ok(()) => {}
+            Err(TrySendError::Full(request)) => {
+                warn!("block server is busy; dropping request {:?}", request);
+                let response = (
+                    request.recipient(),
+                    vec![Msg::error(
+                        "REQUEST_BLOCK",
+                        ErrorCode::BlockchainError,
+                        "too many block requests; retry later",
+                    )],
+                );
+                if let Err(err) = self.response_sender.send(response) {
+                    error!("failed to send block response: {}", err);
+                }
+            }
+            Err(TrySendError::Closed(_)) => error!("block server has been closed"),
+        }
+    }
+
+    /// Serve the requests from `requests`, reading blocks on a blocking thread, until the server is dropped
+    async fn worker(
+        blockchain: ChainReader,
+        requests: Arc<Mutex<Receiver<BlockRequest>>>,
+        response_sender: UnboundedSender<BlockResponse>,
+    ) {
+        loop {
+            let request = match requests.lock().await.recv().await {
+                Some(request) => request,
+                None => break,
+            };
+            let blockchain = blockchain.clone();
+            let response =
+                tokio::task::spawn_blocking(move || Self::response(&blockchain, request)).await;
+            match response {
+                Ok(response) => {
+                    if let Err(err) = response_sender.send(response) {
+                        error!("failed to send block response: {}", err);
+                    }
+                }
+                Err(err) => error!("block serving worker failed: {}", err),
+            }
+        }
+    }
+
+    /// Read the blocks for `request`
+    fn response(blockchain: &ChainReader, request: BlockRequest) -> BlockResponse {
+        let recipient = request.recipient();
+        let messages = match request {
+            BlockRequest::Single { index, .. } => {
+                vec![Self::block_request_response(blockchain, index)]
+            }
+            BlockRequest::Range { from, to, .. } => Self::block_range(blockchain, from, to),
+        };
+        (recipient, messages)
+    }
+
+    /// Get the blocks we have in the range, as `Block` messages. Ranges are capped to `SYNC_RANGE_SIZE` blocks
+    fn block_range(blockchain: &ChainReader, from: u64, to: u64) -> Vec<Msg> {
+        let to = to.min(from.saturating_add(SYNC_RANGE_SIZE - 1));
+        debug!("serving blocks #{}..#{}", from, to);
+        let mut blocks = Vec::new();
+        for index in from..=to {
+            match blockchain.get_block(index) {
+                Ok(Some(block)) => blocks.push(Msg::block(block)),
+                Ok(None) => break,
+                Err(err) => {
+                    error!("can't retrieve block #{} from database: {}", index, err);
+                    break;
+                }
+            }
+        }
+        blocks
+    }
+
+    /// Get the response for a request of block #`index`: either a `Block` or an `Error` message
+    pub fn block_request_response(blockchain: &ChainReader, index: u64) -> Msg {
+        match blockchain.get_block(index) {
+            Err(err) => {
+                error!("can't retrieve block #{} from database: {}", index, err);
+                Msg::error(
+                    "REQUEST_BLOCK",
+                    ErrorCode::BlockchainError,
+                    format!("could not retrieve block #{}", index),
+                )
+            }
+            Ok(None) => {
+                let code = ErrorCode::BlockNotFound(index);
+                let description = code.to_string();
+                Msg::error("REQUEST_BLOCK", code, description)
+            }
+            Ok(Some(block)) => Msg::block(block),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use jab::blockchain::{BlockchainDatabase, Chain, TransactionBuilder, TransactionVersion};
+    use jab::net::message::Error as MsgError;
+    use pretty_assertions::assert_eq;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn should_respond_with_requested_block() {
+        let chain = Chain::from_database(BlockchainDatabase::in_memory())
+            .unwrap()
+            .reader();
+        assert_eq!(
+            BlockServer::block_request_response(&chain, 0),
+            Msg::block(chain.get_genesis_block().unwrap())
+        );
+    }
+
+    #[test]
+    fn should_respond_with_error_to_out_of_range_block_request() {
+        let chain = Chain::from_database(BlockchainDatabase::in_memory())
+            .unwrap()
+            .reader();
+        assert!(matches!(
+            BlockServer::block_request_response(&chain, 5),
+            Msg::Error(MsgError {
+                code: ErrorCode::BlockNotFound(5),
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn should_serve_block_requests_without_blocking_transactions() {
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let server = BlockServer::new(chain.reader(), 2, 8, sender);
+        for peer in ["alice", "bob", "carol"] {
+            server.serve(BlockRequest::Single {
+                index: 0,
+                peer_id: Some(peer.to_string()),
+            });
+        }
+        server.serve(BlockRequest::Range {
+            from: 0,
+            to: 10,
+            peer_id: String::from("dave"),
+        });
+        // requests are queued, while the main loop keeps processing transactions
+        assert!(receiver.try_recv().is_err());
+        let transaction = TransactionBuilder::new(TransactionVersion::V1)
+            .input(chain.genesis_address(), dec!(1.0))
+            .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(1.0))
+            .finish("aaa");
+        let block = chain.generate_next_block(transaction).unwrap();
+        let mut peers = Vec::new();
+        for _ in 0..4 {
+            match receiver.recv().await.unwrap() {
+                (BlockRecipient::Peer(peer_id), messages) => {
+                    // range replies go to the requester only
+                    assert_eq!(messages.last(), Some(&Msg::block(block.clone())));
+                    peers.push(peer_id);
+                }
+                (BlockRecipient::Topic(peer_id), _) => peers.push(peer_id.unwrap()),
+            }
+        }
+        peers.sort();
+        assert_eq!(peers, vec!["alice", "bob", "carol", "dave"]);
+    }
+
+    #[tokio::test]
+    async fn should_reject_block_requests_exceeding_queue() {
+        let chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let server = BlockServer::new(chain.reader(), 1, 1, sender);
+        // workers haven't picked any request yet, so the second one exceeds the queue
+        for peer in ["alice", "bob"] {
+            server.serve(BlockRequest::Single {
+                index: 0,
+                peer_id: Some(peer.to_string()),
+            });
+        }
+        let (recipient, messages) = receiver.recv().await.unwrap();
+        assert_eq!(recipient, BlockRecipient::Topic(Some(String::from("bob"))));
+        assert!(matches!(
+            messages.as_slice(),
+            [Msg::Error(MsgError {
+                code: ErrorCode::BlockchainError,
+                ..
+            })]
+        ));
+        let (recipient, messages) = receiver.recv().await.unwrap();
+        assert_eq!(
+            recipient,
+            BlockRecipient::Topic(Some(String::from("alice")))
+        );
+        assert_eq!(
+            messages,
+            vec![Msg::block(chain.get_genesis_block().unwrap())]
+        );
+    }
+}
@@ -2,13 +2,137 @@
 //!
 //! This module contains the configuration for the application
 
+use jab::blockchain::{GenesisConfig, MIN_DIFFICULTY};
+use jab::net::MessageRate;
+use libp2p::Multiaddr;
+use rust_decimal::Decimal;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 
 #[derive(Debug, Deserialize, Serialize)]
 /// Application config
 pub struct Config {
     database_directory: PathBuf,
     wallet_secret_key: PathBuf,
+    /// Maximum amount which can be moved in a single transaction. If unset, there's no cap
+    max_transaction_amount: Option<Decimal>,
+    /// Maximum amount of wallet queries served at the same time; exceeding queries are rejected
+    #[serde(default = "default_max_concurrent_wallet_queries")]
+    max_concurrent_wallet_queries: usize,
+    /// Initial allocations credited in the genesis block, as a comma-separated list of `address:amount`
+    genesis_allocations: Option<String>,
+    /// Difficulty of the genesis block and of the blocks mined before the first retarget
+    genesis_difficulty: Option<u32>,
+    /// Interval in seconds between two requests for the next block
+    #[serde(default = "default_block_poll_interval")]
+    block_poll_interval: u64,
+    /// Maximum random delay in milliseconds added to each block poll interval
+    #[serde(default = "default_block_poll_jitter")]
+    block_poll_jitter: u64,
+    /// Interval in seconds between two miner discovery attempts
+    #[serde(default = "default_discovery_interval")]
+    discovery_interval: u64,
+    /// Time in seconds after which a miner which hasn't been seen is pruned
+    #[serde(default = "default_miner_max_age")]
+    miner_max_age: u64,
+    /// Amount of random peers new blocks are sent to; peers relay the blocks they accept.
+    /// If unset, blocks are published to the whole topic
+    block_fanout: Option<usize>,
+    /// Time in seconds a relayed block is remembered; within it, the same block is not relayed again
+    #[serde(default = "default_block_relay_ttl")]
+    block_relay_ttl: u64,
+    /// Maximum time in seconds a received block may be ahead of the local clock
+    #[serde(default = "default_future_block_tolerance")]
+    future_block_tolerance: u64,
+    /// Maximum time in seconds the timestamp of a peer message may be ahead of the local clock
+    #[serde(default = "default_max_clock_skew")]
+    max_clock_skew: u64,
+    /// Public address the node is reachable at (e.g. behind NAT), advertised to peers instead of the listener address
+    external_address: Option<String>,
+    /// Maximum amount of inputs of an accepted transaction request; can't exceed the protocol limit
+    #[serde(default = "default_max_inputs")]
+    max_inputs: usize,
+    /// Maximum amount of outputs of an accepted transaction request; can't exceed the protocol limit
+    #[serde(default = "default_max_outputs")]
+    max_outputs: usize,
+    /// Maximum amount of blocks scanned to serve a wallet query. If unset, the whole chain is scanned
+    max_scan_blocks: Option<u64>,
+    /// Amount of workers reading the blocks requested by peers
+    #[serde(default = "default_block_serving_workers")]
+    block_serving_workers: usize,
+    /// Maximum amount of miners advertised to peers in a `RegisterMiners` message
+    #[serde(default = "default_max_advertised_miners")]
+    max_advertised_miners: usize,
+    /// Whether the next block is requested on every poll tick and after every received block.
+    /// If disabled, blocks are requested only once a received block shows the node is behind
+    #[serde(default = "default_proactive_block_requests")]
+    proactive_block_requests: bool,
+    /// Maximum amount of messages per second accepted from a single peer, whatever their type. If unset, there's no limit
+    max_message_rate: Option<u32>,
+    /// Maximum amount of messages a peer may send at once. If unset, it equals the rate
+    message_rate_burst: Option<u32>,
+    /// Amount of malformed or exceeding messages after which a peer is banned. If unset, peers are never banned
+    max_peer_penalty: Option<u32>,
+    /// Port of the admin endpoint, which listens on the loopback interface only
+    #[serde(default = "default_admin_port")]
+    admin_port: u16,
+}
+
+fn default_admin_port() -> u16 {
+    super::admin::DEFAULT_ADMIN_PORT
+}
+
+fn default_max_inputs() -> usize {
+    jab::blockchain::MAX_INPUTS
+}
+
+fn default_max_outputs() -> usize {
+    jab::blockchain::MAX_OUTPUTS
+}
+
+fn default_proactive_block_requests() -> bool {
+    true
+}
+
+fn default_block_serving_workers() -> usize {
+    2
+}
+
+fn default_max_advertised_miners() -> usize {
+    jab::net::message::DEFAULT_MAX_ADVERTISED_MINERS
+}
+
+fn default_block_relay_ttl() -> u64 {
+    600
+}
+
+fn default_max_clock_skew() -> u64 {
+    jab::blockchain::DEFAULT_FUTURE_BLOCK_TOLERANCE.as_secs()
+}
+
+fn default_future_block_tolerance() -> u64 {
+    jab::blockchain::DEFAULT_FUTURE_BLOCK_TOLERANCE.as_secs()
+}
+
+fn default_block_poll_interval() -> u64 {
+    5
+}
+
+fn default_block_poll_jitter() -> u64 {
+    0
+}
+
+fn default_discovery_interval() -> u64 {
+    30
+}
+
+fn default_miner_max_age() -> u64 {
+    300
+}
+
+fn default_max_concurrent_wallet_queries() -> usize {
+    4
 }
 
 impl Config {
@@ -27,6 +151,132 @@ impl Config {
     pub fn wallet_secret_key(&self) -> &Path {
         &self.wallet_secret_key
     }
+
+    /// Get maximum amount per transaction
+    pub fn max_transaction_amount(&self) -> Option<Decimal> {
+        self.max_transaction_amount
+    }
+
+    /// Get genesis block configuration
+    pub fn genesis(&self) -> anyhow::Result<GenesisConfig> {
+        let genesis = match self.genesis_allocations.as_deref() {
+            None => GenesisConfig::default(),
+            Some(allocations) => GenesisConfig::from_str(allocations)
+                .map_err(|e| anyhow::anyhow!("invalid genesis allocations: {}", e))?,
+        };
+        match self.genesis_difficulty {
+            Some(difficulty) if difficulty < MIN_DIFFICULTY => {
+                anyhow::bail!("genesis difficulty must be at least {}", MIN_DIFFICULTY)
+            }
+            Some(difficulty) => Ok(genesis.initial_difficulty(difficulty)),
+            None => Ok(genesis),
+        }
+    }
+
+    /// Get the public address to advertise to peers
+    pub fn external_address(&self) -> anyhow::Result<Option<Multiaddr>> {
+        self.external_address
+            .as_deref()
+            .map(Multiaddr::from_str)
+            .transpose()
+            .map_err(|e| anyhow::anyhow!("invalid external address: {}", e))
+    }
+
+    /// Get interval between two requests for the next block
+    pub fn block_poll_interval(&self) -> Duration {
+        Duration::from_secs(self.block_poll_interval)
+    }
+
+    /// Get the maximum random delay added to each block poll interval
+    pub fn block_poll_jitter(&self) -> Duration {
+        Duration::from_millis(self.block_poll_jitter)
+    }
+
+    /// Get interval between two miner discovery attempts
+    pub fn discovery_interval(&self) -> Duration {
+        Duration::from_secs(self.discovery_interval)
+    }
+
+    /// Get time after which a miner which hasn't been seen is pruned
+    pub fn miner_max_age(&self) -> Duration {
+        Duration::from_secs(self.miner_max_age)
+    }
+
+    /// Get maximum time a received block may be ahead of the local clock
+    pub fn future_block_tolerance(&self) -> Duration {
+        Duration::from_secs(self.future_block_tolerance)
+    }
+
+    /// Get maximum time the timestamp of a peer message may be ahead of the local clock
+    pub fn max_clock_skew(&self) -> Duration {
+        Duration::from_secs(self.max_clock_skew)
+    }
+
+    /// Get amount of peers new blocks are sent to
+    pub fn block_fanout(&self) -> Option<usize> {
+        self.block_fanout
+    }
+
+    /// Get the time a relayed block is remembered for
+    pub fn block_relay_ttl(&self) -> Duration {
+        Duration::from_secs(self.block_relay_ttl)
+    }
+
+    /// Get maximum amount of inputs of an accepted transaction request
+    pub fn max_inputs(&self) -> usize {
+        self.max_inputs
+    }
+
+    /// Get maximum amount of outputs of an accepted transaction request
+    pub fn max_outputs(&self) -> usize {
+        self.max_outputs
+    }
+
+    /// Get maximum amount of blocks scanned to serve a wallet query
+    pub fn max_scan_blocks(&self) -> Option<u64> {
+        self.max_scan_blocks
+    }
+
+    /// Get amount of workers reading the blocks requested by peers
+    pub fn block_serving_workers(&self) -> usize {
+        self.block_serving_workers
+    }
+
+    /// Get whether the next block is requested proactively
+    pub fn proactive_block_requests(&self) -> bool {
+        self.proactive_block_requests
+    }
+
+    /// Get the maximum rate of the messages accepted from a single peer
+    pub fn max_message_rate(&self) -> Option<MessageRate> {
+        self.max_message_rate
+            .map(|rate| MessageRate::new(rate, self.message_rate_burst.unwrap_or(rate)))
+    }
+
+    /// Get the maximum amount of messages a peer may send at once
+    pub fn message_rate_burst(&self) -> Option<u32> {
+        self.message_rate_burst
+    }
+
+    /// Get the amount of malformed or exceeding messages after which a peer is banned
+    pub fn max_peer_penalty(&self) -> Option<u32> {
+        self.max_peer_penalty
+    }
+
+    /// Get maximum amount of miners advertised to peers
+    pub fn max_advertised_miners(&self) -> usize {
+        self.max_advertised_miners
+    }
+
+    /// Get maximum amount of wallet queries served concurrently
+    pub fn max_concurrent_wallet_queries(&self) -> usize {
+        self.max_concurrent_wallet_queries
+    }
+
+    /// Get port of the admin endpoint
+    pub fn admin_port(&self) -> u16 {
+        self.admin_port
+    }
 }
 
 #[cfg(test)]
@@ -40,5 +290,26 @@ mod test {
         let config = Config::try_from_env().unwrap();
         assert_eq!(config.database_dir(), Path::new("./db"));
         assert_eq!(config.wallet_secret_key(), Path::new("wallet.key"));
+        assert!(config.max_transaction_amount().is_none());
+        assert_eq!(config.max_concurrent_wallet_queries(), 4);
+        assert_eq!(config.genesis().unwrap(), GenesisConfig::default());
+        assert_eq!(config.block_poll_interval(), Duration::from_secs(5));
+        assert_eq!(config.block_poll_jitter(), Duration::ZERO);
+        assert_eq!(config.discovery_interval(), Duration::from_secs(30));
+        assert_eq!(config.miner_max_age(), Duration::from_secs(300));
+        assert!(config.block_fanout().is_none());
+        assert_eq!(config.block_relay_ttl(), Duration::from_secs(600));
+        assert_eq!(config.future_block_tolerance(), Duration::from_secs(120));
+        assert_eq!(config.max_clock_skew(), Duration::from_secs(120));
+        assert!(config.external_address().unwrap().is_none());
+        assert_eq!(config.max_inputs(), 64);
+        assert_eq!(config.max_outputs(), 64);
+        assert!(config.max_scan_blocks().is_none());
+        assert_eq!(config.max_advertised_miners(), 64);
+        assert_eq!(config.block_serving_workers(), 2);
+        assert!(config.proactive_block_requests());
+        assert!(config.max_message_rate().is_none());
+        assert!(config.max_peer_penalty().is_none());
+        assert_eq!(config.admin_port(), 4100);
     }
 }
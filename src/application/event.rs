@@ -2,12 +2,67 @@
 //!
 //! Contains application events
 
-use jab::net::{Msg, SwarmEvent};
+use super::admin::AdminCommand;
+use super::block_server::BlockRecipient;
+use super::scheduler::Tick;
+use jab::net::{message::WalletQueryResult, Msg, NodeResult, SwarmEvent};
 
 /// Application event
 #[derive(Debug)]
 pub enum AppEvent {
     Message(Msg),
     Swarm(SwarmEvent),
+    /// A wallet query has been served; the result must be sent to the peer, along with the id of the request
+    WalletQueryResult(String, u64, WalletQueryResult),
+    /// A block request has been served; the messages must be delivered to the recipient
+    BlockResponse(BlockRecipient, Vec<Msg>),
+    /// A request has been received on the admin endpoint
+    Admin(AdminCommand),
+    /// A scheduled task must be run
+    Tick(Tick),
+    /// The application has been requested to terminate
+    Shutdown,
     None,
 }
+
+impl From<Option<NodeResult<Msg>>> for AppEvent {
+    /// Convert an item received from the node event receiver into an `AppEvent`.
+    /// If the stream has ended, the application must be terminated, since no message will ever be received again
+    fn from(message: Option<NodeResult<Msg>>) -> Self {
+        match message {
+            Some(Ok(message)) => Self::Message(message),
+            Some(Err(err)) => {
+                warn!("received an invalid message: {}", err);
+                Self::None
+            }
+            None => {
+                error!("node event channel has been closed; shutting down");
+                Self::Shutdown
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn should_convert_received_message_into_event() {
+        assert!(matches!(
+            AppEvent::from(Some(Ok(Msg::request_registered_miners()))),
+            AppEvent::Message(Msg::RequestRegisteredMiners)
+        ));
+        let err = serde_json::from_str::<Msg>("{}").unwrap_err();
+        assert!(matches!(
+            AppEvent::from(Some(Err(err.into()))),
+            AppEvent::None
+        ));
+    }
+
+    #[test]
+    fn should_shutdown_when_event_channel_is_closed() {
+        assert!(matches!(AppEvent::from(None), AppEvent::Shutdown));
+    }
+}
@@ -3,97 +3,248 @@
 //! the application module is the core of the jab client
 
 // -- modules
+mod admin;
+mod block_server;
 mod config;
 mod event;
+mod peers;
+mod preflight;
+mod scheduler;
+mod seen_blocks;
+mod sync;
 mod transaction_helper;
 mod wallet_helper;
+mod wallet_queries;
 
+pub use admin::{AdminRequest, AdminServer, DEFAULT_ADMIN_PORT};
 pub use config::Config;
-use rust_decimal::Decimal;
+pub use preflight::ConfigProblem;
 
+use crate::mode::start_node;
+use admin::{AdminCommand, ConnectedPeers, PeerInfo};
+use block_server::{
+    BlockRecipient, BlockRequest, BlockResponse, BlockServer, BLOCK_REQUEST_QUEUE_SIZE,
+};
 use event::AppEvent;
-use jab::blockchain::{Block, Chain, Transaction};
+use jab::blockchain::{Block, BlockchainError, Chain};
 use jab::mining::{Miner, MiningDatabase};
 use jab::net::{
-    message::{Transaction as MsgTransaction, WalletQuery, WalletQueryError},
-    InnerSwarmEvent, Msg, Node, SwarmEvent,
+    message::{
+        ErrorCode, Transaction as MsgTransaction, WalletQuery, WalletQueryError, WalletQueryResult,
+    },
+    InnerSwarmEvent, Msg, Node, NodeConfig, SwarmEvent,
 };
-use jab::wallet::Wallet;
+use jab::wallet::{VerificationCache, Wallet};
+use peers::Peers;
+use scheduler::{Scheduler, Tick};
+use seen_blocks::SeenBlocks;
+use sync::{SyncAction, SyncTracker};
 use transaction_helper::{TransactionHelper, TransactionOptions, TransactionRejected};
 use wallet_helper::WalletHelper;
+use wallet_queries::{WalletQueries, WalletQueryKind, WalletQueryResponse};
 
 use futures::StreamExt;
-use tokio::time::{interval, Duration, Interval};
+use libp2p::PeerId;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+/// Maximum amount of transactions sent in a single wallet details message
+const WALLET_TRANSACTIONS_PAGE_SIZE: usize = 64;
+/// Maximum time to wait for the node to bind its listener
+const READY_TIMEOUT: Duration = Duration::from_secs(30);
 
 /// Jab client application
 pub struct Application {
+    admin_receiver: UnboundedReceiver<AdminCommand>,
     blockchain: Chain,
+    block_server: BlockServer,
+    block_response_receiver: UnboundedReceiver<BlockResponse>,
+    config: Config,
     miners: MiningDatabase,
     node: Node,
-    poll_interval: Interval,
+    peers: Peers,
+    scheduler: Scheduler,
+    seen_blocks: SeenBlocks,
+    sync: SyncTracker,
+    verification_cache: VerificationCache,
     wallet: Wallet,
+    wallet_queries: WalletQueries,
+    wallet_query_receiver: UnboundedReceiver<WalletQueryResponse>,
 }
 
 impl Application {
+    /// Validate `config` before starting the application, reporting all the problems found
+    pub fn preflight(config: &Config) -> Result<(), Vec<ConfigProblem>> {
+        preflight::preflight(config)
+    }
+
+    /// Maintenance operation: drop and recompute the blockchain indexes from the blocks
+    pub fn rebuild_indexes(config: &Config) -> anyhow::Result<()> {
+        let mut blockchain = Chain::open(config.database_dir(), &config.genesis()?)?;
+        info!("rebuilding blockchain indexes...");
+        blockchain.rebuild_indexes()?;
+        info!("blockchain indexes rebuilt");
+        Ok(())
+    }
+
     /// Initialize new `Application`
     pub async fn init(config: Config) -> anyhow::Result<Self> {
+        if let Err(problems) = Self::preflight(&config) {
+            for problem in problems.iter() {
+                error!("configuration problem: {}", problem);
+            }
+            anyhow::bail!(
+                "found {} problems in configuration: {}",
+                problems.len(),
+                problems
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<String>>()
+                    .join("; ")
+            );
+        }
         // setup blockchain
-        let blockchain = Chain::try_from(config.database_dir())?;
+        let mut blockchain = Chain::open(config.database_dir(), &config.genesis()?)?
+            .future_block_tolerance(config.future_block_tolerance());
+        if let Some(max_scan_blocks) = config.max_scan_blocks() {
+            blockchain = blockchain.max_scan_blocks(max_scan_blocks);
+        }
         info!(
             "blockchain ready! Found {} blocks",
             blockchain.get_latest_block()?.index() + 1
         );
         // setup node
-        let node = match Node::init().await {
-            Ok(node) => node,
-            Err(err) => {
-                anyhow::bail!("Failed to initialize node: {}", err.to_string());
-            }
-        };
+        let mut node_config = NodeConfig::default().max_clock_skew(config.max_clock_skew());
+        if let Some(address) = config.external_address()? {
+            node_config = node_config.external_address(address);
+        }
+        if let Some(rate) = config.max_message_rate() {
+            node_config = node_config.max_message_rate(rate);
+        }
+        if let Some(penalty) = config.max_peer_penalty() {
+            node_config = node_config.max_penalty(penalty);
+        }
+        let mut node = start_node(node_config).await?;
         info!("node successfully initialized (id: {})", node.id());
-        Ok(Self {
+        let listen_address = node.ready(READY_TIMEOUT).await?;
+        let wallet = WalletHelper::open_wallet(config.wallet_secret_key()).await?;
+        let (admin_sender, admin_receiver) = mpsc::unbounded_channel();
+        let admin_address = AdminServer::bind(config.admin_port(), admin_sender)
+            .await
+            .map_err(|e| anyhow::anyhow!("could not start admin endpoint: {}", e))?;
+        info!("admin endpoint listening on {}", admin_address);
+        let mut app = Self::new(config, blockchain, node, wallet, admin_receiver);
+        app.peers.add_listen_address(listen_address);
+        info!(
+            "node ready; listening on {:?}",
+            app.peers.listen_addresses()
+        );
+        Ok(app)
+    }
+
+    /// Assemble the `Application` on top of an open `blockchain` and an initialized `node`
+    fn new(
+        config: Config,
+        blockchain: Chain,
+        node: Node,
+        wallet: Wallet,
+        admin_receiver: UnboundedReceiver<AdminCommand>,
+    ) -> Self {
+        let (wallet_query_sender, wallet_query_receiver) = mpsc::unbounded_channel();
+        let wallet_queries = WalletQueries::new(
+            blockchain.reader(),
+            config.max_concurrent_wallet_queries(),
+            wallet_query_sender,
+        );
+        let (block_response_sender, block_response_receiver) = mpsc::unbounded_channel();
+        let block_server = BlockServer::new(
+            blockchain.reader(),
+            config.block_serving_workers(),
+            BLOCK_REQUEST_QUEUE_SIZE,
+            block_response_sender,
+        );
+        let scheduler = Scheduler::new(config.block_poll_interval(), config.discovery_interval())
+            .poll_jitter(config.block_poll_jitter());
+        let seen_blocks = SeenBlocks::new(config.block_relay_ttl());
+        let sync = SyncTracker::default().proactive(config.proactive_block_requests());
+        Self {
+            admin_receiver,
             blockchain,
+            block_server,
+            block_response_receiver,
+            config,
             miners: MiningDatabase::new(Miner::new(node.id())),
             node,
-            poll_interval: interval(Duration::from_secs(5)),
-            wallet: WalletHelper::open_wallet(config.wallet_secret_key()).await?,
-        })
+            peers: Peers::default(),
+            scheduler,
+            seen_blocks,
+            sync,
+            verification_cache: VerificationCache::default(),
+            wallet,
+            wallet_queries,
+            wallet_query_receiver,
+        }
     }
 
     /// run application
     pub async fn run(mut self) -> anyhow::Result<()> {
-        if let Err(err) = self.node.listen() {
-            anyhow::bail!("Failed to start listener: {}", err.to_string());
-        }
-        info!("listener started");
         // main loop
         loop {
             let event: AppEvent = tokio::select! {
-                event = self.node.swarm.select_next_some() => AppEvent::Swarm(event),
-                message = self.node.event_receiver.next() => {
-                    match message {
-                        Some(Ok(message)) => AppEvent::Message(message),
-                        _ => AppEvent::None,
+                event = self.node.next_event() => AppEvent::Swarm(event),
+                message = self.node.event_receiver.next() => AppEvent::from(message),
+                tick = self.scheduler.tick() => AppEvent::Tick(tick),
+                response = self.wallet_query_receiver.recv() => {
+                    match response {
+                        Some((peer_id, request_id, result)) => {
+                            AppEvent::WalletQueryResult(peer_id, request_id, result)
+                        }
+                        None => AppEvent::None,
+                    }
+                }
+                response = self.block_response_receiver.recv() => {
+                    match response {
+                        Some((recipient, messages)) => {
+                            AppEvent::BlockResponse(recipient, messages)
+                        }
+                        None => AppEvent::None,
                     }
                 }
-                _ = self.poll_interval.tick() => {
-                    self.on_get_next_block_tick().await;
-                    // if currently there's only one known miner (which is us), send requests for discovering miners
-                    if self.miners.miners().len() == 1 {
-                        self.send_miner_requests().await;
+                command = self.admin_receiver.recv() => {
+                    match command {
+                        Some(command) => AppEvent::Admin(command),
+                        None => AppEvent::None,
                     }
-                    self.poll_interval.reset();
-                    AppEvent::None
                 }
+                _ = tokio::signal::ctrl_c() => AppEvent::Shutdown,
             };
             match event {
                 AppEvent::Message(message) => self.handle_message(message).await,
                 AppEvent::Swarm(event) => self.handle_swarm_event(event).await,
+                AppEvent::WalletQueryResult(peer_id, request_id, result) => {
+                    self.send_wallet_details_result(&peer_id, request_id, result)
+                        .await
+                }
+                AppEvent::BlockResponse(recipient, messages) => {
+                    self.send_block_response(recipient, messages).await
+                }
+                AppEvent::Admin(command) => self.on_admin_request(command),
+                AppEvent::Tick(Tick::PollBlock) => self.on_get_next_block_tick().await,
+                AppEvent::Tick(Tick::Discovery) => self.on_discovery_tick().await,
+                AppEvent::Shutdown => return self.shutdown(),
                 AppEvent::None => {}
             }
         }
     }
 
+    /// Gracefully terminate the application, persisting pending writes
+    fn shutdown(&self) -> anyhow::Result<()> {
+        info!("shutting down; flushing blockchain database");
+        self.blockchain
+            .flush()
+            .map_err(|e| anyhow::anyhow!("failed to flush blockchain database: {}", e))
+    }
+
     /// handle incoming message from peer
     async fn handle_message(&mut self, message: Msg) {
         match message {
@@ -101,8 +252,28 @@ impl Application {
                 self.on_block_received(block.block).await;
             }
             Msg::RequestBlock(block_req) => {
-                self.on_block_requested(block_req.index).await;
+                debug!("got a request for block #{}", block_req.index);
+                self.block_server.serve(BlockRequest::Single {
+                    index: block_req.index,
+                    peer_id: block_req.peer_id,
+                });
             }
+            Msg::RequestBlockRange(range) => match range.peer_id {
+                Some(peer_id) => {
+                    debug!(
+                        "got a request for blocks #{}..#{} from {}",
+                        range.from, range.to, peer_id
+                    );
+                    self.block_server.serve(BlockRequest::Range {
+                        from: range.from,
+                        to: range.to,
+                        peer_id,
+                    });
+                }
+                None => {
+                    debug!("ignoring request for blocks without a requester");
+                }
+            },
             Msg::RegisterMiners(miners) => {
                 self.on_register_miners(miners.miners).await;
             }
@@ -115,25 +286,100 @@ impl Application {
             Msg::TransactionResult(_) => {
                 debug!("ignoring transaction result");
             }
+            Msg::GetTransactionBlock(request) => {
+                self.on_transaction_block_requested(&request.peer_id, &request.txid)
+                    .await;
+            }
+            Msg::TransactionBlock(_) => {
+                debug!("ignoring transaction block response");
+            }
             Msg::WalletDetails(query) => {
-                self.on_wallet_details_query(query).await;
+                self.on_wallet_query(query, WalletQueryKind::Details).await;
+            }
+            Msg::WalletBalance(query) => {
+                self.on_wallet_query(query, WalletQueryKind::Balance).await;
             }
             Msg::WalletDetailsResult(_) => {
                 debug!("ignoring wallet details result");
             }
+            Msg::GetTip(request) => {
+                self.on_tip_requested(&request.peer_id).await;
+            }
+            Msg::Tip(_) => {
+                debug!("ignoring tip response");
+            }
+            Msg::GetBlockId(request) => {
+                self.on_block_id_requested(&request.peer_id, request.index)
+                    .await;
+            }
+            Msg::BlockId(_) => {
+                debug!("ignoring block id response");
+            }
+            Msg::Error(_) => {
+                debug!("ignoring error response");
+            }
         }
     }
 
     /// handle incoming event from swarm
     async fn handle_swarm_event(&mut self, event: SwarmEvent) {
         match event {
-            InnerSwarmEvent::ConnectionClosed { peer_id, .. } => {
+            InnerSwarmEvent::ConnectionEstablished {
+                peer_id, endpoint, ..
+            } => {
+                if self
+                    .peers
+                    .connection_established(peer_id, endpoint.get_remote_address().clone())
+                {
+                    info!(
+                        "connection established with {} ({}); connected peers: {}",
+                        peer_id,
+                        endpoint.get_remote_address(),
+                        self.peers.count()
+                    );
+                }
+                self.miners.seen(&peer_id.to_string(), SystemTime::now());
+                if Self::should_request_miners_on_connection(&self.miners, &peer_id) {
+                    info!(
+                        "{} is not a known miner; requesting registered miners",
+                        peer_id
+                    );
+                    self.send_miner_requests().await;
+                }
+            }
+            InnerSwarmEvent::ConnectionClosed {
+                peer_id,
+                num_established,
+                ..
+            } => {
+                self.peers.connection_closed(&peer_id, num_established);
                 info!(
                     "connection closed with {}; unregistering peer from miners",
                     peer_id
                 );
                 self.miners.unregister_miner(peer_id);
             }
+            InnerSwarmEvent::NewListenAddr { address, .. } => {
+                self.peers.add_listen_address(address);
+                info!("listening on {:?}", self.peers.listen_addresses());
+            }
+            InnerSwarmEvent::ExpiredListenAddr { address, .. } => {
+                info!("listen address {} expired", address);
+                self.peers.remove_listen_address(&address);
+            }
+            InnerSwarmEvent::OutgoingConnectionError { peer_id, error } => {
+                warn!("failed to dial {:?}: {}", peer_id, error);
+            }
+            InnerSwarmEvent::IncomingConnectionError {
+                send_back_addr,
+                error,
+                ..
+            } => {
+                warn!(
+                    "incoming connection from {} failed: {}",
+                    send_back_addr, error
+                );
+            }
             _ => {
                 debug!("unhandled swarm event: {:?}", event);
             }
@@ -148,41 +394,156 @@ impl Application {
             block_index,
             block.header().merkle_root_hash()
         );
-        if let Err(err) = self.blockchain.add_block(block) {
-            error!("could not add block #{}: {}", block_index, err);
+        self.sync.on_block_announced(block_index);
+        match self.blockchain.add_block(block.clone()) {
+            Ok(()) => {
+                // relay blocks we accepted, so they spread even if they were sent to a subset of the peers
+                if self.config.block_fanout().is_some()
+                    && self.seen_blocks.should_forward(&block.id(), Instant::now())
+                {
+                    self.broadcast_block(block).await;
+                }
+                self.add_pending_blocks(block_index + 1);
+            }
+            Err(BlockchainError::OrphanBlock(index, tip)) => {
+                debug!(
+                    "keeping block #{} until the blocks after #{} are received",
+                    index, tip
+                );
+                self.sync.on_block_ahead(tip + 1, block);
+            }
+            Err(err) => {
+                error!("could not add block #{}: {}", block_index, err);
+            }
         }
         // request next block
         self.get_next_block().await;
     }
 
-    /// code to run on block requested
-    async fn on_block_requested(&mut self, requested_block: u64) {
-        debug!("got a request for block #{}", requested_block);
-        match self.blockchain.get_block(requested_block) {
+    /// Add the blocks received ahead of the tip which follow it, starting from block #`next_index`, as a single batch
+    fn add_pending_blocks(&mut self, next_index: u64) {
+        let mut blocks = Vec::new();
+        while let Some(block) = self.sync.take_pending(next_index + blocks.len() as u64) {
+            blocks.push(block);
+        }
+        if blocks.is_empty() {
+            return;
+        }
+        let last_index = next_index + blocks.len() as u64 - 1;
+        if let Err(err) = self.blockchain.add_blocks(blocks) {
+            error!(
+                "could not add pending blocks #{}..#{}: {}",
+                next_index, last_index, err
+            );
+        }
+    }
+
+    /// Deliver the messages of a served block request to `recipient`
+    async fn send_block_response(&mut self, recipient: BlockRecipient, messages: Vec<Msg>) {
+        for message in messages {
+            match (message, &recipient) {
+                (Msg::Error(err), BlockRecipient::Topic(None)) => {
+                    debug!("can't serve block: {}", err.description);
+                }
+                (response, BlockRecipient::Peer(peer_id))
+                | (response @ Msg::Error(_), BlockRecipient::Topic(Some(peer_id))) => {
+                    debug!("sending {} to {}", response.name(), peer_id);
+                    if let Err(err) = self.node.send(peer_id, response).await {
+                        error!("could not send block response to {}: {}", peer_id, err);
+                    }
+                }
+                (response, BlockRecipient::Topic(_)) => {
+                    if let Err(err) = self.node.publish(response).await {
+                        error!("could not send `Block` message: {}", err);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Function to execute on a `GetTip` message; sends the latest block index and hash to the requester
+    async fn on_tip_requested(&mut self, peer_id: &str) {
+        debug!("received a request for the chain tip from {}", peer_id);
+        let response = Self::tip_response(&self.blockchain);
+        if let Err(err) = self.node.send(peer_id, response).await {
+            error!("could not send `Tip` message: {}", err);
+        }
+    }
+
+    /// Function to execute on a `GetBlockId` message; sends the id of block #`index` to the requester
+    async fn on_block_id_requested(&mut self, peer_id: &str, index: u64) {
+        debug!(
+            "received a request for the id of block #{} from {}",
+            index, peer_id
+        );
+        let response = match self.blockchain.block_id(index) {
+            Ok(id) => Msg::block_id(index, id),
             Err(err) => {
-                error!(
-                    "can't retrieve block #{} from database: {}",
-                    requested_block, err
-                );
+                error!("can't retrieve block #{} from database: {}", index, err);
+                Msg::error(
+                    "GET_BLOCK_ID",
+                    ErrorCode::BlockchainError,
+                    "could not retrieve block",
+                )
             }
-            Ok(None) => {
-                debug!("we don't currently have block #{}", requested_block);
+        };
+        if let Err(err) = self.node.send(peer_id, response).await {
+            error!("could not send `BlockId` message: {}", err);
+        }
+    }
+
+    /// Returns whether a connection established with `peer_id` must refresh the registered miners immediately:
+    /// it does if the peer is not a known miner, e.g. it has just reconnected and its miner has been unregistered
+    fn should_request_miners_on_connection(miners: &MiningDatabase, peer_id: &PeerId) -> bool {
+        !miners.miner_exists(&peer_id.to_string())
+    }
+
+    /// Get the response for a `GetTip` request: either a `Tip` or an `Error` message
+    fn tip_response(blockchain: &Chain) -> Msg {
+        match blockchain.get_latest_block() {
+            Ok(block) => Msg::tip(block.index(), block.header().merkle_root_hash()),
+            Err(err) => {
+                error!("can't retrieve latest block from database: {}", err);
+                Msg::error(
+                    "GET_TIP",
+                    ErrorCode::BlockchainError,
+                    "could not retrieve latest block",
+                )
             }
-            Ok(Some(block)) => {
-                debug!("sending block #{}", requested_block);
-                if let Err(err) = self.node.publish(Msg::block(block.clone())).await {
-                    error!("could not send `Block` message: {}", err);
-                }
+        }
+    }
+
+    /// Function to execute on a `GetTransactionBlock` message; tells the requester where the transaction has been mined
+    async fn on_transaction_block_requested(&mut self, peer_id: &str, txid: &str) {
+        debug!(
+            "received a lookup for transaction {} from {}",
+            txid, peer_id
+        );
+        let response = Self::transaction_block_response(&self.blockchain, txid);
+        if let Err(err) = self.node.send(peer_id, response).await {
+            error!("could not send `TransactionBlock` message: {}", err);
+        }
+    }
+
+    /// Get the response for a `GetTransactionBlock` request: either a `TransactionBlock` or an `Error` message
+    pub(crate) fn transaction_block_response(blockchain: &Chain, txid: &str) -> Msg {
+        match blockchain.transaction_block(txid) {
+            Ok(block_index) => Msg::transaction_block(txid, block_index),
+            Err(err) => {
+                error!("can't look up transaction {}: {}", txid, err);
+                Msg::error(
+                    "GET_TRANSACTION_BLOCK",
+                    ErrorCode::BlockchainError,
+                    "could not look up transaction",
+                )
             }
         }
     }
 
     /// Function to execute on a `RegisterMiners` message
     async fn on_register_miners(&mut self, miners: Vec<Miner>) {
-        debug!("received new miners database");
-        for miner in miners.into_iter() {
-            self.miners.register_miner(miner);
-        }
+        let added = self.miners.merge_miners(miners);
+        debug!("received miners database; {} new miners", added);
     }
 
     /// Function to execute on a `RequestRegisteredMiners` message
@@ -191,47 +552,70 @@ impl Application {
         self.send_miners_database().await;
     }
 
+    /// Function to execute on a request received on the admin endpoint
+    fn on_admin_request(&self, (request, response): AdminCommand) {
+        let peers = match request {
+            AdminRequest::Peers => ConnectedPeers::new(
+                &self.node.advertised_addresses(),
+                Self::connected_peers(&self.peers, &self.miners),
+            ),
+        };
+        if response.send(peers).is_err() {
+            warn!("admin client left before receiving the response");
+        }
+    }
+
+    /// Describe connected peers, annotating which are registered miners
+    fn connected_peers(peers: &Peers, miners: &MiningDatabase) -> Vec<PeerInfo> {
+        peers
+            .iter()
+            .map(|(peer_id, address)| {
+                let id = peer_id.to_string();
+                let miner = miners.miner_exists(&id);
+                PeerInfo::new(id, address, miner)
+            })
+            .collect()
+    }
+
     /// function to call on interval tick
     async fn on_get_next_block_tick(&mut self) {
         self.get_next_block().await;
     }
 
-    /// Function to handle a `WalletDetails` query
-    async fn on_wallet_details_query(&mut self, query: WalletQuery) {
-        debug!("received wallet query for {}", query.address);
-        let balance = match self.blockchain.wallet_amount(&query.address) {
-            Ok(Some(balance)) => balance,
-            Ok(None) => {
-                self.send_wallet_details_error(&query.peer_id, WalletQueryError::WalletNotFound)
-                    .await;
-                return;
-            }
-            Err(_) => {
-                self.send_wallet_details_error(&query.peer_id, WalletQueryError::BlockchainError)
-                    .await;
-                return;
-            }
-        };
-        // collect transactions
-        match self.blockchain.wallet_transactions(&query.address) {
-            Err(_) => {
-                self.send_wallet_details_error(&query.peer_id, WalletQueryError::BlockchainError)
-                    .await
-            }
-            Ok(None) => {
-                self.send_wallet_details_error(&query.peer_id, WalletQueryError::WalletNotFound)
-                    .await
-            }
-            Ok(Some(transactions)) => {
-                // calc wallet balance
-                debug!(
-                    "found {} transactions for wallet {}; current amount {} JAB",
-                    transactions.len(),
-                    query.address,
-                    balance
+    /// function to call on discovery interval tick
+    async fn on_discovery_tick(&mut self) {
+        // connected miners are alive; the others are pruned once they haven't been seen for too long
+        let now = SystemTime::now();
+        for (peer_id, _) in self.peers.iter() {
+            self.miners.seen(&peer_id.to_string(), now);
+        }
+        let pruned = self.miners.prune_stale(self.config.miner_max_age());
+        if pruned > 0 {
+            info!("pruned {} stale miners", pruned);
+        }
+        // if currently there's only one known miner (which is us), send requests for discovering miners
+        if self.miners.miners().len() == 1 {
+            self.send_miner_requests().await;
+        }
+    }
+
+    /// Function to handle a `WalletDetails` or `WalletBalance` query.
+    /// The query is served on a worker thread, unless too many queries are already being served
+    async fn on_wallet_query(&mut self, query: WalletQuery, kind: WalletQueryKind) {
+        debug!("received {:?} wallet query for {}", kind, query.address);
+        match self.wallet_queries.try_acquire() {
+            Some(permit) => self.wallet_queries.serve(query, kind, permit),
+            None => {
+                warn!(
+                    "too many wallet queries being served; rejecting query from {}",
+                    query.peer_id
                 );
-                self.send_wallet_details_ok(&query.peer_id, &query.address, transactions, balance)
-                    .await
+                self.send_wallet_details_result(
+                    &query.peer_id,
+                    query.request_id,
+                    WalletQueryResult::error(WalletQueryError::Overloaded),
+                )
+                .await;
             }
         }
     }
@@ -253,12 +637,37 @@ impl Application {
                 return;
             }
         };
-        match self.node.publish(Msg::request_block(next_index)).await {
-            Ok(()) => {
-                debug!("requested block #{}", next_index);
+        if !self.sync.should_request(next_index) {
+            debug!("not requesting block #{}: no peer announced it", next_index);
+            return;
+        }
+        match self.sync.on_request(next_index) {
+            SyncAction::Request(index) => {
+                match self.node.publish(Msg::request_block(index)).await {
+                    Ok(outcome) if outcome.has_recipients() => {
+                        debug!("requested block #{}", index);
+                    }
+                    Ok(_) => {
+                        debug!("no peer to request block #{} to", index);
+                    }
+                    Err(err) => {
+                        error!("failed to request block #{}: {}", index, err);
+                    }
+                }
             }
-            Err(err) => {
-                error!("failed to request block #{}: {}", next_index, err);
+            SyncAction::Escalate { from, to } => {
+                warn!(
+                    "sync stalled: block #{} is still missing; requesting blocks #{}..#{} and rediscovering peers",
+                    next_index, from, to
+                );
+                if let Err(err) = self
+                    .node
+                    .publish(Msg::request_block_range(self.node.id(), from, to))
+                    .await
+                {
+                    error!("failed to request blocks #{}..#{}: {}", from, to, err);
+                }
+                self.send_miner_requests().await;
             }
         }
     }
@@ -268,7 +677,10 @@ impl Application {
         debug!("sending miners database");
         if let Err(err) = self
             .node
-            .publish(Msg::register_miners(self.miners.miners()))
+            .publish(Msg::register_miners(
+                self.miners.miners(),
+                self.config.max_advertised_miners(),
+            ))
             .await
         {
             error!("failed to send registered miners: {}", err);
@@ -297,11 +709,16 @@ impl Application {
                 transaction_msg.output_address,
             )
             .amount(transaction_msg.amount)
+            .nonce(transaction_msg.nonce)
             .fee(rust_decimal_macros::dec!(20.0))
+            .max_amount(self.config.max_transaction_amount())
+            .max_inputs(self.config.max_inputs())
+            .max_outputs(self.config.max_outputs())
             .signature(transaction_msg.signature)
             .public_key(transaction_msg.public_key),
             &self.wallet,
             &self.blockchain,
+            &mut self.verification_cache,
         )
         .await
         {
@@ -332,28 +749,45 @@ impl Application {
             new_block.header().merkle_root_hash()
         );
         // send response OK
-        self.send_transaction_response_ok(&transaction_msg.peer_id)
+        self.send_transaction_response_ok(&transaction_msg.peer_id, &new_block)
             .await;
         // send new block to other peers
-        if let Err(err) = self.node.publish(Msg::block(new_block.clone())).await {
-            error!("failed to send new block to peers: {}", err);
-        }
+        let new_block_index = new_block.index();
+        self.broadcast_block(new_block).await;
         info!(
             "block #{} successfully broadcasted to peer",
-            new_block.index()
+            new_block_index
         );
     }
 
+    /// Send `block` to other peers.
+    /// If a fanout is configured, the block is sent only to that amount of random peers, which will relay it;
+    /// otherwise it is published to the whole topic
+    async fn broadcast_block(&mut self, block: Block) {
+        let message = Msg::block(block);
+        match self.config.block_fanout() {
+            None => {
+                if let Err(err) = self.node.publish(message).await {
+                    error!("failed to send new block to peers: {}", err);
+                }
+            }
+            Some(fanout) => {
+                for peer_id in self.peers.sample(fanout) {
+                    debug!("sending block to {}", peer_id);
+                    if let Err(err) = self.node.send(&peer_id.to_string(), message.clone()).await {
+                        error!("failed to send new block to {}: {}", peer_id, err);
+                    }
+                }
+            }
+        }
+    }
+
     /// Send transaction response NOK to peer
     async fn send_transaction_response_nok(&mut self, peer_id: &str, error: TransactionRejected) {
         debug!("sending transaction response NOK to {}", peer_id);
-        let description = error.to_string();
         if let Err(err) = self
             .node
-            .send(
-                peer_id,
-                Msg::transaction_result_nok(error.into(), description),
-            )
+            .send(peer_id, Msg::transaction_result_nok(error.into()))
             .await
         {
             error!(
@@ -363,56 +797,271 @@ impl Application {
         }
     }
 
-    /// Send transaction response OK to peer
-    async fn send_transaction_response_ok(&mut self, peer_id: &str) {
+    /// Send transaction response OK to peer, reporting the transaction mined in `block`
+    async fn send_transaction_response_ok(&mut self, peer_id: &str, block: &Block) {
         debug!("sending transaction response OK to {}", peer_id);
-        if let Err(err) = self.node.send(peer_id, Msg::transaction_result_ok()).await {
-            error!(
-                "could not send transaction response to {}: {}",
-                peer_id, err
-            );
-        }
-    }
-
-    /// Send wallet details response ERROR to peer
-    async fn send_wallet_details_error(&mut self, peer_id: &str, error: WalletQueryError) {
-        debug!(
-            "sending wallet details response ERROR to {}: {}",
-            peer_id, error
-        );
         if let Err(err) = self
             .node
-            .send(peer_id, Msg::wallet_details_result_error(error))
+            .send(peer_id, Self::transaction_accepted_response(block))
             .await
         {
             error!(
-                "could not send wallet details response to {}: {}",
+                "could not send transaction response to {}: {}",
                 peer_id, err
             );
         }
     }
 
-    /// Send wallet details response OK to peer
-    async fn send_wallet_details_ok(
+    /// Get the successful `TransactionResult` for the transaction mined in `block`
+    fn transaction_accepted_response(block: &Block) -> Msg {
+        Msg::transaction_result_ok(block.transaction().id(), block.index())
+    }
+
+    /// Send wallet details response to peer
+    /// Large transaction lists are split into pages tagged with `request_id`, to keep each message within the size limit
+    async fn send_wallet_details_result(
         &mut self,
         peer_id: &str,
-        address: &str,
-        transactions: Vec<Transaction>,
-        balance: Decimal,
+        request_id: u64,
+        result: WalletQueryResult,
     ) {
-        debug!("sending wallet details response OK to {}", peer_id);
-        if let Err(err) = self
-            .node
-            .send(
-                peer_id,
-                Msg::wallet_details_result_ok(address, transactions, balance),
-            )
+        debug!("sending wallet details response to {}", peer_id);
+        let results = match result {
+            WalletQueryResult::Ok(transactions) => {
+                transactions.into_pages(WALLET_TRANSACTIONS_PAGE_SIZE, &self.node.id(), request_id)
+            }
+            result => vec![result],
+        };
+        for result in results {
+            if let Err(err) = self
+                .node
+                .send(peer_id, Msg::WalletDetailsResult(result))
+                .await
+            {
+                error!(
+                    "could not send wallet details response to {}: {}",
+                    peer_id, err
+                );
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use jab::blockchain::{BlockchainDatabase, TransactionBuilder, TransactionVersion};
+    use jab::net::message::{TransactionAccepted, TransactionStatus};
+    use jab::net::{NodeBuilder, PublishOutcome, TransportKind};
+    use libp2p::Multiaddr;
+    use pretty_assertions::assert_eq;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn should_respond_with_chain_tip() {
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        let genesis = chain.get_genesis_block().unwrap();
+        assert_eq!(
+            Application::tip_response(&chain),
+            Msg::tip(0, genesis.header().merkle_root_hash())
+        );
+        let transaction = TransactionBuilder::new(TransactionVersion::V1)
+            .input(genesis.transaction().output_address().unwrap(), dec!(2.0))
+            .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(2.0))
+            .finish("bbb");
+        let block = chain.generate_next_block(transaction).unwrap();
+        assert_eq!(
+            Application::tip_response(&chain),
+            Msg::tip(1, block.header().merkle_root_hash())
+        );
+    }
+
+    #[test]
+    fn should_respond_with_accepted_transaction() {
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        let genesis = chain.get_genesis_block().unwrap();
+        let transaction = TransactionBuilder::new(TransactionVersion::V1)
+            .input(genesis.transaction().output_address().unwrap(), dec!(2.0))
+            .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(2.0))
+            .finish("bbb");
+        let txid = transaction.id();
+        let block = chain.generate_next_block(transaction).unwrap();
+        match Application::transaction_accepted_response(&block) {
+            Msg::TransactionResult(result) => {
+                assert_eq!(result.status, TransactionStatus::Ok);
+                assert_eq!(result.error, None);
+                assert_eq!(result.accepted, Some(TransactionAccepted::new(txid, 1)));
+            }
+            message => panic!("unexpected response {:?}", message),
+        }
+    }
+
+    #[tokio::test]
+    async fn should_relay_accepted_blocks_to_sampled_peers() {
+        // a -> b -> c, where b runs the application and a sends the block to b only
+        let (mut app, address) = memory_app(&[("BLOCK_FANOUT", "2")]).await;
+        let b_id = app.node.id();
+        let mut a = memory_node().await;
+        let mut c = memory_node().await;
+        a.swarm.dial(address.clone()).unwrap();
+        c.swarm.dial(address).unwrap();
+        // the block is mined on another chain with the same genesis
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        let genesis = chain.get_genesis_block().unwrap();
+        let transaction = TransactionBuilder::new(TransactionVersion::V1)
+            .input(genesis.transaction().output_address().unwrap(), dec!(2.0))
+            .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(2.0))
+            .finish("bbb");
+        let block = chain.generate_next_block(transaction).unwrap();
+        let relayed = tokio::time::timeout(Duration::from_secs(30), async {
+            // wait until a knows b's subscriptions and b knows both a's and c's
+            let probe = Msg::block_id(0, None);
+            while !(a.publish(probe.clone()).await.unwrap().has_recipients()
+                && app.node.publish(probe.clone()).await.unwrap() == PublishOutcome::Sent(2))
+            {
+                step(&mut app, &mut a, &mut c).await;
+            }
+            a.send(&b_id, Msg::block(block.clone())).await.unwrap();
+            loop {
+                if let Some(Msg::Block(block)) = step(&mut app, &mut a, &mut c).await {
+                    break block.block;
+                }
+            }
+        })
+        .await
+        .expect("block was not relayed in time");
+        assert_eq!(relayed, block);
+        assert_eq!(app.blockchain.get_latest_block().unwrap(), block);
+    }
+
+    #[tokio::test]
+    async fn should_request_miners_when_unknown_peer_connects() {
+        let (mut app, address) = memory_app(&[]).await;
+        // `observer` receives what the application publishes once it knows its subscriptions
+        let mut observer = memory_node().await;
+        let mut peer = memory_node().await;
+        observer.swarm.dial(address.clone()).unwrap();
+        tokio::time::timeout(Duration::from_secs(30), async {
+            let probe = Msg::block_id(0, None);
+            while !app
+                .node
+                .publish(probe.clone())
+                .await
+                .unwrap()
+                .has_recipients()
+            {
+                step(&mut app, &mut peer, &mut observer).await;
+            }
+            // an unknown peer connects
+            peer.swarm.dial(address).unwrap();
+            while step(&mut app, &mut peer, &mut observer).await
+                != Some(Msg::request_registered_miners())
+            {}
+        })
+        .await
+        .expect("registered miners were not requested");
+    }
+
+    /// Start an application on an in-memory chain and node, configured with the provided environment variables.
+    /// Returns the application and the address its node is listening at
+    async fn memory_app(vars: &[(&str, &str)]) -> (Application, Multiaddr) {
+        let config: Config = envy::from_iter(
+            [
+                ("DATABASE_DIRECTORY", "./db"),
+                ("WALLET_SECRET_KEY", "wallet.key"),
+            ]
+            .iter()
+            .chain(vars)
+            .map(|(key, value)| (key.to_string(), value.to_string())),
+        )
+        .unwrap();
+        let mut node = memory_node().await;
+        node.swarm.listen_on("/memory/0".parse().unwrap()).unwrap();
+        let address = node.ready(Duration::from_secs(5)).await.unwrap();
+        // the admin endpoint is not started
+        let (_, admin_receiver) = mpsc::unbounded_channel();
+        let app = Application::new(
+            config,
+            Chain::from_database(BlockchainDatabase::in_memory()).unwrap(),
+            node,
+            Wallet::new(),
+            admin_receiver,
+        );
+        (app, address)
+    }
+
+    /// Build a node communicating through an in-memory transport, with mDNS disabled
+    async fn memory_node() -> Node {
+        NodeBuilder::default()
+            .transport(TransportKind::Memory)
+            .mdns(false)
+            .build()
             .await
-        {
-            error!(
-                "could not send wallet details response to {}: {}",
-                peer_id, err
-            );
+            .unwrap()
+    }
+
+    /// Process the next event of `app`, `peer` or `observer`, returning the message received by `observer`, if any
+    async fn step(app: &mut Application, peer: &mut Node, observer: &mut Node) -> Option<Msg> {
+        let event = tokio::select! {
+            event = app.node.next_event() => AppEvent::Swarm(event),
+            message = app.node.event_receiver.next() => AppEvent::from(message),
+            _ = peer.swarm.select_next_some() => AppEvent::None,
+            _ = peer.event_receiver.next() => AppEvent::None,
+            _ = observer.swarm.select_next_some() => AppEvent::None,
+            message = observer.event_receiver.next() => return message.and_then(Result::ok),
+            _ = tokio::time::sleep(Duration::from_millis(100)) => AppEvent::None,
+        };
+        match event {
+            AppEvent::Swarm(event) => app.handle_swarm_event(event).await,
+            AppEvent::Message(message) => app.handle_message(message).await,
+            _ => {}
         }
+        None
+    }
+
+    #[test]
+    fn should_report_connected_peers() {
+        let miner_id = PeerId::random();
+        let peer_id = PeerId::random();
+        let address: libp2p::Multiaddr = "/ip4/127.0.0.1/tcp/4000".parse().unwrap();
+        let mut miners = MiningDatabase::new(Miner::new("host"));
+        miners.register_miner(Miner::new(miner_id));
+        let mut peers = Peers::default();
+        peers.connection_established(miner_id, address.clone());
+        peers.connection_established(peer_id, address.clone());
+        let mut report = Application::connected_peers(&peers, &miners);
+        report.sort_by_key(|x| !x.miner);
+        assert_eq!(
+            report,
+            vec![
+                PeerInfo::new(miner_id, &address, true),
+                PeerInfo::new(peer_id, &address, false),
+            ]
+        );
+        // closed connections are not reported anymore
+        peers.connection_closed(&peer_id, 0);
+        assert_eq!(Application::connected_peers(&peers, &miners).len(), 1);
+    }
+
+    #[test]
+    fn should_request_miners_when_unknown_peer_reconnects() {
+        let peer_id = PeerId::random();
+        let mut miners = MiningDatabase::new(Miner::new("host"));
+        // a new peer
+        assert!(Application::should_request_miners_on_connection(
+            &miners, &peer_id
+        ));
+        miners.register_miner(Miner::new(peer_id));
+        assert!(!Application::should_request_miners_on_connection(
+            &miners, &peer_id
+        ));
+        // the connection is closed, then established again
+        miners.unregister_miner(peer_id);
+        assert!(Application::should_request_miners_on_connection(
+            &miners, &peer_id
+        ));
     }
 }
@@ -0,0 +1,149 @@
+//! # Peers
+//!
+//! Keeps track of the peers connected to this node and of the addresses we're listening on
+
+use libp2p::{Multiaddr, PeerId};
+use rand::seq::IteratorRandom;
+use std::collections::HashMap;
+
+/// Connected peers and local listen addresses
+#[derive(Debug, Default)]
+pub struct Peers {
+    /// Connected peers, with the remote address of the first connection established
+    connected: HashMap<PeerId, Multiaddr>,
+    listen_addresses: Vec<Multiaddr>,
+}
+
+impl Peers {
+    /// Register a new connection with `peer_id` at `address`.
+    /// Returns whether the peer wasn't connected yet
+    pub fn connection_established(&mut self, peer_id: PeerId, address: Multiaddr) -> bool {
+        if self.connected.contains_key(&peer_id) {
+            return false;
+        }
+        self.connected.insert(peer_id, address);
+        true
+    }
+
+    /// Register a closed connection with `peer_id`.
+    /// The peer is removed only when no connection with it is left
+    pub fn connection_closed(&mut self, peer_id: &PeerId, num_established: u32) {
+        if num_established == 0 {
+            self.connected.remove(peer_id);
+        }
+    }
+
+    /// Get amount of connected peers
+    pub fn count(&self) -> usize {
+        self.connected.len()
+    }
+
+    /// Pick at most `amount` random connected peers
+    pub fn sample(&self, amount: usize) -> Vec<PeerId> {
+        self.connected
+            .keys()
+            .copied()
+            .choose_multiple(&mut rand::thread_rng(), amount)
+    }
+
+    /// Iterate over connected peers and their remote address
+    pub fn iter(&self) -> impl Iterator<Item = (&PeerId, &Multiaddr)> {
+        self.connected.iter()
+    }
+
+    /// Register a new local listen address
+    pub fn add_listen_address(&mut self, address: Multiaddr) {
+        if !self.listen_addresses.contains(&address) {
+            self.listen_addresses.push(address);
+        }
+    }
+
+    /// Remove an expired local listen address
+    pub fn remove_listen_address(&mut self, address: &Multiaddr) {
+        self.listen_addresses.retain(|x| x != address);
+    }
+
+    /// Get local listen addresses
+    pub fn listen_addresses(&self) -> &[Multiaddr] {
+        &self.listen_addresses
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    fn address() -> Multiaddr {
+        "/ip4/127.0.0.1/tcp/4000".parse().unwrap()
+    }
+
+    #[test]
+    fn should_add_peer_on_connection_established() {
+        let mut peers = Peers::default();
+        let peer_id = PeerId::random();
+        assert_eq!(peers.connection_established(peer_id, address()), true);
+        assert_eq!(peers.count(), 1);
+        // another connection with the same peer
+        assert_eq!(peers.connection_established(peer_id, address()), false);
+        assert_eq!(peers.count(), 1);
+    }
+
+    #[test]
+    fn should_remove_peer_when_last_connection_is_closed() {
+        let mut peers = Peers::default();
+        let peer_id = PeerId::random();
+        peers.connection_established(peer_id, address());
+        peers.connection_established(PeerId::random(), address());
+        peers.connection_closed(&peer_id, 1);
+        assert_eq!(peers.count(), 2);
+        peers.connection_closed(&peer_id, 0);
+        assert_eq!(peers.count(), 1);
+    }
+
+    #[test]
+    fn should_sample_connected_peers() {
+        let mut peers = Peers::default();
+        for _ in 0..5 {
+            peers.connection_established(PeerId::random(), address());
+        }
+        let sample = peers.sample(3);
+        assert_eq!(sample.len(), 3);
+        assert!(sample.iter().all(|x| peers.connected.contains_key(x)));
+        assert_eq!(
+            sample
+                .iter()
+                .collect::<std::collections::HashSet<_>>()
+                .len(),
+            3
+        );
+        // can't pick more peers than connected
+        assert_eq!(peers.sample(10).len(), 5);
+    }
+
+    #[test]
+    fn should_list_connected_peers() {
+        let mut peers = Peers::default();
+        let peer_id = PeerId::random();
+        peers.connection_established(peer_id, address());
+        assert_eq!(
+            peers.iter().collect::<Vec<_>>(),
+            vec![(&peer_id, &address())]
+        );
+        peers.connection_closed(&peer_id, 0);
+        assert_eq!(peers.iter().count(), 0);
+    }
+
+    #[test]
+    fn should_track_listen_addresses() {
+        let mut peers = Peers::default();
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/4000".parse().unwrap();
+        peers.add_listen_address(address.clone());
+        peers.add_listen_address(address.clone());
+        assert_eq!(peers.listen_addresses(), &[address.clone()]);
+        peers.remove_listen_address(&address);
+        assert!(peers.listen_addresses().is_empty());
+    }
+}
@@ -0,0 +1,176 @@
+//! # Preflight
+//!
+//! Validates the application configuration before starting the node, reporting all the problems at once
+
+use super::Config;
+
+use jab::blockchain::{MAX_INPUTS, MAX_OUTPUTS};
+use rust_decimal::Decimal;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// A problem found in the application configuration
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ConfigProblem {
+    #[error("database directory {0} is not usable: {1}")]
+    DatabaseDirectory(PathBuf, String),
+    #[error("wallet secret key {0} doesn't exist; please register a new wallet first")]
+    WalletNotFound(PathBuf),
+    #[error("{0}")]
+    InvalidGenesis(String),
+    #[error("{0}")]
+    InvalidExternalAddress(String),
+    #[error("{0} must be greater than zero")]
+    Zero(&'static str),
+    #[error("{0} can't exceed the protocol limit of {1}")]
+    AboveProtocolLimit(&'static str, usize),
+    #[error("max transaction amount can't be negative")]
+    NegativeMaxTransactionAmount,
+}
+
+/// Validate `config`. Returns all the problems found
+pub fn preflight(config: &Config) -> Result<(), Vec<ConfigProblem>> {
+    let mut problems = Vec::new();
+    if let Err(reason) = check_database_dir(config.database_dir()) {
+        problems.push(ConfigProblem::DatabaseDirectory(
+            config.database_dir().to_path_buf(),
+            reason,
+        ));
+    }
+    if !config.wallet_secret_key().is_file() {
+        problems.push(ConfigProblem::WalletNotFound(
+            config.wallet_secret_key().to_path_buf(),
+        ));
+    }
+    if let Err(err) = config.genesis() {
+        problems.push(ConfigProblem::InvalidGenesis(err.to_string()));
+    }
+    if let Err(err) = config.external_address() {
+        problems.push(ConfigProblem::InvalidExternalAddress(err.to_string()));
+    }
+    for (name, is_zero) in [
+        (
+            "block poll interval",
+            config.block_poll_interval().is_zero(),
+        ),
+        ("discovery interval", config.discovery_interval().is_zero()),
+        ("miner max age", config.miner_max_age().is_zero()),
+        (
+            "max concurrent wallet queries",
+            config.max_concurrent_wallet_queries() == 0,
+        ),
+        ("block fanout", config.block_fanout() == Some(0)),
+        ("max inputs", config.max_inputs() == 0),
+        ("max outputs", config.max_outputs() == 0),
+        ("max scan blocks", config.max_scan_blocks() == Some(0)),
+        ("max advertised miners", config.max_advertised_miners() == 0),
+        ("block serving workers", config.block_serving_workers() == 0),
+        (
+            "max message rate",
+            matches!(config.max_message_rate(), Some(rate) if rate.per_second == 0),
+        ),
+        ("message rate burst", config.message_rate_burst() == Some(0)),
+        ("max peer penalty", config.max_peer_penalty() == Some(0)),
+    ] {
+        if is_zero {
+            problems.push(ConfigProblem::Zero(name));
+        }
+    }
+    for (name, value, limit) in [
+        ("max inputs", config.max_inputs(), MAX_INPUTS),
+        ("max outputs", config.max_outputs(), MAX_OUTPUTS),
+    ] {
+        if value > limit {
+            problems.push(ConfigProblem::AboveProtocolLimit(name, limit));
+        }
+    }
+    if matches!(config.max_transaction_amount(), Some(amount) if amount < Decimal::ZERO) {
+        problems.push(ConfigProblem::NegativeMaxTransactionAmount);
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems)
+    }
+}
+
+/// Check whether the database can be created or opened at `dir`
+fn check_database_dir(dir: &Path) -> Result<(), String> {
+    if dir.exists() {
+        let metadata = dir.metadata().map_err(|e| e.to_string())?;
+        if !metadata.is_dir() {
+            return Err(String::from("not a directory"));
+        }
+        if metadata.permissions().readonly() {
+            return Err(String::from("directory is read-only"));
+        }
+        return Ok(());
+    }
+    // the directory will be created, so its parent must exist
+    match dir.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() && !parent.is_dir() => Err(format!(
+            "parent directory {} doesn't exist",
+            parent.display()
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    fn config(vars: &[(&str, &str)]) -> Config {
+        envy::from_iter(
+            vars.iter()
+                .map(|(key, value)| (key.to_string(), value.to_string())),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn should_pass_preflight() {
+        let tempdir = TempDir::new().expect("could not create tempdir");
+        let wallet = tempdir.path().join("wallet.key");
+        std::fs::write(&wallet, [0xab; 32]).unwrap();
+        let database = tempdir.path().join("db");
+        let config = config(&[
+            ("DATABASE_DIRECTORY", database.to_str().unwrap()),
+            ("WALLET_SECRET_KEY", wallet.to_str().unwrap()),
+        ]);
+        assert_eq!(preflight(&config), Ok(()));
+    }
+
+    #[test]
+    fn should_report_all_problems_together() {
+        let tempdir = TempDir::new().expect("could not create tempdir");
+        let database = tempdir.path().join("missing").join("db");
+        let wallet = tempdir.path().join("wallet.key");
+        let config = config(&[
+            ("DATABASE_DIRECTORY", database.to_str().unwrap()),
+            ("WALLET_SECRET_KEY", wallet.to_str().unwrap()),
+            ("BLOCK_POLL_INTERVAL", "0"),
+            ("BLOCK_FANOUT", "0"),
+            ("MAX_OUTPUTS", "65"),
+            ("MAX_TRANSACTION_AMOUNT", "-1"),
+        ]);
+        let problems = preflight(&config).unwrap_err();
+        assert_eq!(problems.len(), 6);
+        assert!(matches!(
+            &problems[0],
+            ConfigProblem::DatabaseDirectory(path, _) if path == &database
+        ));
+        assert_eq!(problems[1], ConfigProblem::WalletNotFound(wallet));
+        assert_eq!(problems[2], ConfigProblem::Zero("block poll interval"));
+        assert_eq!(problems[3], ConfigProblem::Zero("block fanout"));
+        assert_eq!(
+            problems[4],
+            ConfigProblem::AboveProtocolLimit("max outputs", 64)
+        );
+        assert_eq!(problems[5], ConfigProblem::NegativeMaxTransactionAmount);
+    }
+}
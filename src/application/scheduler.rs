@@ -0,0 +1,118 @@
+//! # Scheduler
+//!
+//! The scheduler drives the periodic tasks of the application
+
+use rand::Rng;
+use std::pin::Pin;
+use tokio::time::{interval, sleep_until, Duration, Instant, Interval, MissedTickBehavior, Sleep};
+
+/// A periodic task which must be run by the application
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Tick {
+    /// Poll peers for the next block
+    PollBlock,
+    /// Discover miners on the network
+    Discovery,
+}
+
+/// Runs block polling and peer discovery on two independent timers.
+/// A random jitter can be added to each poll, so nodes don't request blocks all at the same time
+pub struct Scheduler {
+    poll_interval: Duration,
+    poll_jitter: Duration,
+    next_poll: Pin<Box<Sleep>>,
+    discovery_interval: Interval,
+}
+
+impl Scheduler {
+    /// Create a new `Scheduler` with the provided block poll and discovery intervals
+    pub fn new(poll_interval: Duration, discovery_interval: Duration) -> Self {
+        Self {
+            poll_interval,
+            poll_jitter: Duration::ZERO,
+            next_poll: Box::pin(sleep_until(Instant::now())),
+            discovery_interval: Self::interval(discovery_interval),
+        }
+    }
+
+    /// Add a random delay, up to `jitter`, to each block poll interval
+    pub fn poll_jitter(mut self, jitter: Duration) -> Self {
+        self.poll_jitter = jitter;
+        self
+    }
+
+    /// Wait for the next tick of any of the timers
+    pub async fn tick(&mut self) -> Tick {
+        tokio::select! {
+            _ = self.next_poll.as_mut() => {
+                let delay = self.next_poll_delay();
+                self.next_poll.as_mut().reset(Instant::now() + delay);
+                Tick::PollBlock
+            }
+            _ = self.discovery_interval.tick() => Tick::Discovery,
+        }
+    }
+
+    /// Get the delay before the next block poll: the poll interval plus a random jitter
+    fn next_poll_delay(&self) -> Duration {
+        let jitter = self.poll_jitter.as_millis() as u64;
+        if jitter == 0 {
+            return self.poll_interval;
+        }
+        self.poll_interval + Duration::from_millis(rand::thread_rng().gen_range(0..=jitter))
+    }
+
+    fn interval(period: Duration) -> Interval {
+        let mut interval = interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        interval
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test(start_paused = true)]
+    async fn should_tick_discovery_independently_from_block_polling() {
+        let mut scheduler = Scheduler::new(Duration::from_secs(2), Duration::from_secs(30));
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(59);
+        let mut poll_ticks = 0;
+        let mut discovery_ticks = 0;
+        while tokio::time::Instant::now() <= deadline {
+            match scheduler.tick().await {
+                Tick::PollBlock => poll_ticks += 1,
+                Tick::Discovery => discovery_ticks += 1,
+            }
+        }
+        // both intervals tick immediately, then every 2 and 30 seconds
+        assert_eq!(poll_ticks, 30);
+        assert_eq!(discovery_ticks, 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn should_vary_poll_intervals_within_jitter() {
+        let poll_interval = Duration::from_secs(5);
+        let jitter = Duration::from_secs(2);
+        let mut scheduler =
+            Scheduler::new(poll_interval, Duration::from_secs(3600)).poll_jitter(jitter);
+        // skip the immediate ticks
+        scheduler.tick().await;
+        scheduler.tick().await;
+        let mut last_poll = Instant::now();
+        let mut intervals = Vec::new();
+        while intervals.len() < 20 {
+            if scheduler.tick().await == Tick::PollBlock {
+                intervals.push(Instant::now() - last_poll);
+                last_poll = Instant::now();
+            }
+        }
+        assert!(intervals
+            .iter()
+            .all(|interval| *interval >= poll_interval && *interval <= poll_interval + jitter));
+        assert!(intervals.windows(2).any(|pair| pair[0] != pair[1]));
+    }
+}
@@ -0,0 +1,70 @@
+//! # Seen blocks
+//!
+//! Keeps track of the blocks relayed to other peers, so each block is forwarded at most once
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Cache of the hashes of the blocks recently relayed.
+/// A block is forwarded again only once its entry is older than the TTL
+#[derive(Debug)]
+pub struct SeenBlocks {
+    ttl: Duration,
+    /// Relayed blocks hashes, with the time they've been relayed at
+    seen: HashMap<String, Instant>,
+}
+
+impl SeenBlocks {
+    /// Create a new `SeenBlocks` cache, keeping entries for `ttl`
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Returns whether the block with `hash` should be forwarded at `now`, marking it as seen if so.
+    /// Expired entries are evicted
+    pub fn should_forward(&mut self, hash: &str, now: Instant) -> bool {
+        let ttl = self.ttl;
+        self.seen
+            .retain(|_, seen_at| now.saturating_duration_since(*seen_at) < ttl);
+        if self.seen.contains_key(hash) {
+            return false;
+        }
+        self.seen.insert(hash.to_string(), now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_forward_block_once_within_ttl() {
+        let mut seen = SeenBlocks::new(Duration::from_secs(60));
+        let now = Instant::now();
+        assert_eq!(seen.should_forward("cafebabe", now), true);
+        assert_eq!(
+            seen.should_forward("cafebabe", now + Duration::from_secs(59)),
+            false
+        );
+        assert_eq!(seen.should_forward("deadbeef", now), true);
+        assert_eq!(seen.seen.len(), 2);
+    }
+
+    #[test]
+    fn should_forward_block_again_after_ttl() {
+        let mut seen = SeenBlocks::new(Duration::from_secs(60));
+        let now = Instant::now();
+        assert!(seen.should_forward("cafebabe", now));
+        assert!(seen.should_forward("cafebabe", now + Duration::from_secs(60)));
+        // expired entries are evicted
+        assert!(seen.should_forward("deadbeef", now + Duration::from_secs(150)));
+        assert_eq!(seen.seen.len(), 1);
+    }
+}
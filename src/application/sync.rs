@@ -0,0 +1,222 @@
+//! # Sync
+//!
+//! Detects when the synchronization of the chain is stuck on the same block, and whether the next block should be
+//! requested at all
+
+use jab::blockchain::Block;
+
+use std::collections::BTreeMap;
+
+/// Amount of consecutive requests for the same block, after which the sync is considered stalled
+pub const SYNC_STALL_THRESHOLD: u32 = 10;
+/// Amount of blocks requested at once when the sync is stalled
+pub const SYNC_RANGE_SIZE: u64 = 16;
+/// Blocks received ahead of the tip are kept only if within this distance from the next block
+pub const SYNC_PENDING_WINDOW: u64 = SYNC_RANGE_SIZE * 4;
+
+/// What to do to get the next block
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SyncAction {
+    /// Request the next block
+    Request(u64),
+    /// The next block has been requested too many times: request the range of blocks following the last block
+    /// received and rediscover peers
+    Escalate { from: u64, to: u64 },
+}
+
+/// Tracks how many times the same next block has been requested without the chain advancing
+#[derive(Debug)]
+pub struct SyncTracker {
+    threshold: u32,
+    next_index: Option<u64>,
+    attempts: u32,
+    proactive: bool,
+    /// Highest block index received from peers
+    announced: Option<u64>,
+    /// Blocks received ahead of the tip, waiting for the blocks before them
+    pending: BTreeMap<u64, Block>,
+}
+
+impl Default for SyncTracker {
+    fn default() -> Self {
+        Self::new(SYNC_STALL_THRESHOLD)
+    }
+}
+
+impl SyncTracker {
+    /// Create a new `SyncTracker`, escalating after `threshold` requests for the same block
+    pub fn new(threshold: u32) -> Self {
+        Self {
+            threshold,
+            next_index: None,
+            attempts: 0,
+            proactive: true,
+            announced: None,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Set whether the next block is always requested. If not, it is requested only if a peer has announced it
+    pub fn proactive(mut self, proactive: bool) -> Self {
+        self.proactive = proactive;
+        self
+    }
+
+    /// Register a block with `index` received from a peer
+    pub fn on_block_announced(&mut self, index: u64) {
+        self.announced = Some(self.announced.map_or(index, |x| x.max(index)));
+    }
+
+    /// Returns whether block `next_index` should be requested
+    pub fn should_request(&self, next_index: u64) -> bool {
+        self.proactive || self.announced.map_or(false, |x| x >= next_index)
+    }
+
+    /// Keep `block`, received while the next block of the chain is `next_index`, until the blocks before it are
+    /// received. Blocks farther than `SYNC_PENDING_WINDOW` from the next block are discarded
+    pub fn on_block_ahead(&mut self, next_index: u64, block: Block) {
+        if block.index() > next_index && block.index() - next_index <= SYNC_PENDING_WINDOW {
+            self.pending.insert(block.index(), block);
+        }
+    }
+
+    /// Take the pending block with index `next_index`, if received, discarding the pending blocks before it
+    pub fn take_pending(&mut self, next_index: u64) -> Option<Block> {
+        self.pending = self.pending.split_off(&next_index);
+        self.pending.remove(&next_index)
+    }
+
+    /// Register a new request for block `next_index` and get the action to perform.
+    /// Once escalated, attempts are counted again from zero, and the range requested starts after the last block
+    /// received ahead of the tip, if any
+    pub fn on_request(&mut self, next_index: u64) -> SyncAction {
+        if self.next_index == Some(next_index) {
+            self.attempts += 1;
+        } else {
+            self.next_index = Some(next_index);
+            self.attempts = 1;
+        }
+        if self.attempts > self.threshold {
+            self.attempts = 0;
+            let from = self
+                .pending
+                .range(next_index..)
+                .next_back()
+                .map_or(next_index, |(index, _)| index + 1);
+            SyncAction::Escalate {
+                from,
+                to: from + SYNC_RANGE_SIZE - 1,
+            }
+        } else {
+            SyncAction::Request(next_index)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use jab::blockchain::{BlockchainDatabase, Chain, TransactionBuilder, TransactionVersion};
+    use pretty_assertions::assert_eq;
+    use rust_decimal_macros::dec;
+
+    /// Generate a chain with `amount` blocks after the genesis block and return them
+    fn blocks(amount: usize) -> Vec<Block> {
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        (0..amount)
+            .map(|_| {
+                let transaction = TransactionBuilder::new(TransactionVersion::V1)
+                    .input(chain.genesis_address(), dec!(1.0))
+                    .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(1.0))
+                    .finish("aaa");
+                chain.generate_next_block(transaction).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn should_escalate_to_range_request_after_repeated_failures() {
+        let mut tracker = SyncTracker::new(3);
+        for _ in 0..3 {
+            assert_eq!(tracker.on_request(5), SyncAction::Request(5));
+        }
+        assert_eq!(
+            tracker.on_request(5),
+            SyncAction::Escalate {
+                from: 5,
+                to: 5 + SYNC_RANGE_SIZE - 1
+            }
+        );
+        // attempts are counted again after escalating
+        assert_eq!(tracker.on_request(5), SyncAction::Request(5));
+    }
+
+    #[test]
+    fn should_escalate_after_last_block_received() {
+        let mut tracker = SyncTracker::new(1);
+        let blocks = blocks(4);
+        // block #1 is missing, while #2 and #3 have been received
+        tracker.on_block_ahead(1, blocks[2].clone());
+        tracker.on_block_ahead(1, blocks[1].clone());
+        assert_eq!(tracker.on_request(1), SyncAction::Request(1));
+        assert_eq!(
+            tracker.on_request(1),
+            SyncAction::Escalate {
+                from: 4,
+                to: 4 + SYNC_RANGE_SIZE - 1
+            }
+        );
+        // once block #1 is received, the pending blocks are applied in order
+        assert_eq!(tracker.take_pending(2), Some(blocks[1].clone()));
+        assert_eq!(tracker.take_pending(3), Some(blocks[2].clone()));
+        assert_eq!(tracker.take_pending(4), None);
+    }
+
+    #[test]
+    fn should_keep_only_blocks_within_pending_window() {
+        let mut tracker = SyncTracker::default();
+        let block = blocks(1).remove(0);
+        let far = Block::new(
+            SYNC_PENDING_WINDOW + 2,
+            block.header().clone(),
+            block.transaction().clone(),
+        );
+        tracker.on_block_ahead(1, far);
+        // block #1 is not ahead of the next block #5
+        tracker.on_block_ahead(5, block);
+        assert!(tracker.pending.is_empty());
+    }
+
+    #[test]
+    fn should_reset_attempts_when_chain_advances() {
+        let mut tracker = SyncTracker::new(3);
+        for _ in 0..3 {
+            assert_eq!(tracker.on_request(5), SyncAction::Request(5));
+        }
+        for _ in 0..3 {
+            assert_eq!(tracker.on_request(6), SyncAction::Request(6));
+        }
+    }
+
+    #[test]
+    fn should_always_request_next_block_if_proactive() {
+        let tracker = SyncTracker::default();
+        assert!(tracker.should_request(1));
+        assert!(tracker.should_request(100));
+    }
+
+    #[test]
+    fn should_request_next_block_only_when_behind_if_not_proactive() {
+        let mut tracker = SyncTracker::default().proactive(false);
+        // idle ticks
+        assert!(!tracker.should_request(1));
+        tracker.on_block_announced(4);
+        tracker.on_block_announced(2);
+        assert!(tracker.should_request(1));
+        assert!(tracker.should_request(4));
+        // caught up
+        assert!(!tracker.should_request(5));
+    }
+}
@@ -3,43 +3,102 @@
 //! An helper to commit transactions
 
 use jab::blockchain::{
-    BlockchainError, Chain, Transaction, TransactionBuilder, TransactionVersion,
+    BlockchainError, Chain, Transaction, TransactionBuilder, TransactionVersion, MAX_INPUTS,
+    MAX_OUTPUTS,
 };
-use jab::net::message::TransactionErrorCode;
-use jab::wallet::{Wallet, WalletError};
+use jab::net::message::{TransactionError, TransactionErrorCode};
+use jab::wallet::{VerificationCache, Wallet, WalletError};
 
-use merkle::Hashable;
-use ring::digest::{Context, SHA256};
 use rust_decimal::Decimal;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 /// Transaction rejected error
 pub enum TransactionRejected {
-    #[error("the requested amount could not be paid by the issuer")]
-    InsufficientBalance,
-    #[error("input wallet not found")]
-    InputWalletNotFound,
-    #[error("output wallet not found")]
-    OutputWalletNotFound,
+    #[error("the requested amount could not be paid by the issuer: {available} available, but {required} required")]
+    InsufficientBalance {
+        available: Decimal,
+        required: Decimal,
+    },
+    #[error("the requested amount is negative")]
+    NegativeAmount,
+    #[error("input wallet {0} not found")]
+    InputWalletNotFound(String),
+    #[error("output wallet {0} not found")]
+    OutputWalletNotFound(String),
     #[error("transaction signature is invalid")]
     InvalidSignature,
+    #[error("the issuer public key is malformed")]
+    InvalidPublicKey,
+    #[error("the requested amount {amount} exceeds the maximum amount per transaction ({max})")]
+    AmountTooLarge { amount: Decimal, max: Decimal },
+    #[error("the transaction has already been mined")]
+    AlreadyMined,
     #[error("blockchain error: {0}")]
     BlockchainError(BlockchainError),
     #[error("wallet error: {0}")]
     WalletError(WalletError),
 }
 
+impl From<BlockchainError> for TransactionRejected {
+    fn from(e: BlockchainError) -> Self {
+        Self::BlockchainError(e)
+    }
+}
+
+impl From<WalletError> for TransactionRejected {
+    fn from(e: WalletError) -> Self {
+        Self::WalletError(e)
+    }
+}
+
 impl From<TransactionRejected> for TransactionErrorCode {
     fn from(e: TransactionRejected) -> Self {
         match e {
             TransactionRejected::BlockchainError(_) | TransactionRejected::WalletError(_) => {
                 Self::BlockchainError
             }
-            TransactionRejected::InputWalletNotFound => Self::InputWalletNotFound,
-            TransactionRejected::InsufficientBalance => Self::InsufficientBalance,
+            TransactionRejected::InputWalletNotFound(_) => Self::InputWalletNotFound,
+            TransactionRejected::InsufficientBalance { .. }
+            | TransactionRejected::NegativeAmount => Self::InsufficientBalance,
             TransactionRejected::InvalidSignature => Self::InvalidSignature,
-            TransactionRejected::OutputWalletNotFound => Self::OutputWalletNotFound,
+            TransactionRejected::InvalidPublicKey => Self::InvalidPublicKey,
+            TransactionRejected::AmountTooLarge { .. } => Self::AmountTooLarge,
+            TransactionRejected::AlreadyMined => Self::DuplicateTransaction,
+            TransactionRejected::OutputWalletNotFound(_) => Self::OutputWalletNotFound,
+        }
+    }
+}
+
+impl From<TransactionRejected> for TransactionError {
+    fn from(e: TransactionRejected) -> Self {
+        let description = e.to_string();
+        let details = e.details();
+        details.into_iter().fold(
+            TransactionError::new(e.into(), description),
+            |error, (key, value)| error.with_detail(key, value),
+        )
+    }
+}
+
+impl TransactionRejected {
+    /// Get the machine-readable details of the rejection
+    fn details(&self) -> Vec<(&'static str, String)> {
+        match self {
+            Self::InsufficientBalance {
+                available,
+                required,
+            } => vec![
+                ("available", available.to_string()),
+                ("required", required.to_string()),
+            ],
+            Self::InputWalletNotFound(address) | Self::OutputWalletNotFound(address) => {
+                vec![("address", address.to_string())]
+            }
+            Self::AmountTooLarge { amount, max } => {
+                vec![("amount", amount.to_string()), ("max", max.to_string())]
+            }
+            _ => vec![],
         }
     }
 }
@@ -48,16 +107,26 @@ impl From<TransactionRejected> for TransactionErrorCode {
 pub struct TransactionHelper;
 
 impl TransactionHelper {
-    /// Create transaction using the provided options
+    /// Create transaction using the provided options.
+    /// Signature verification results are looked up in and stored into `verification_cache`
     pub async fn create_transaction(
         opts: TransactionOptions,
         wallet: &Wallet,
         blockchain: &Chain,
+        verification_cache: &mut VerificationCache,
     ) -> Result<Transaction, TransactionRejected> {
+        // reject malformed public keys before doing any work on the chain
+        Self::check_public_key(&opts.public_key)?;
         // Prevent negative amount
         debug!("checking wallet amount...");
         if opts.amount < Decimal::ZERO {
-            return Err(TransactionRejected::InsufficientBalance);
+            return Err(TransactionRejected::NegativeAmount);
+        }
+        if let Some(max) = opts.max_amount.filter(|max| opts.amount > *max) {
+            return Err(TransactionRejected::AmountTooLarge {
+                amount: opts.amount,
+                max,
+            });
         }
         Self::check_wallet_amount(&opts.input_address, opts.amount, blockchain)?;
         debug!("checking whether output address exists");
@@ -65,14 +134,32 @@ impl TransactionHelper {
         // Calculate output amount; if amount is ZERO, keep zero (wallet creation)
         // make transaction
         debug!("making transaction");
-        let transaction = TransactionBuilder::new(TransactionVersion::V1)
-            .input(&opts.input_address, opts.amount)
-            .output(&opts.output_address, opts.amount)
-            .output(wallet.address(), opts.fee)
-            .finish(&opts.signature);
+        // rebuild the canonical form signed by the client, then append the fee
+        let transaction = TransactionBuilder::transfer(
+            TransactionVersion::V1,
+            &opts.input_address,
+            &opts.output_address,
+            opts.amount,
+        )
+        .nonce(opts.nonce)
+        .output(wallet.address(), opts.fee)
+        .finish(&opts.signature);
+        // node limits only apply to the requests accepted here; blocks are checked against the protocol limits
+        transaction.validate_structure(opts.max_inputs, opts.max_outputs)?;
         // verify transaction signature
         debug!("checking transaction signature");
-        Self::check_transaction_signature(&transaction, opts.public_key.as_str())?;
+        Self::check_transaction_signature(
+            &transaction,
+            opts.public_key.as_str(),
+            verification_cache,
+        )?;
+        // reject replayed transactions, even if mined by another node with a different fee output.
+        // Repeated payments are signed with a different nonce, so they are never mistaken for replays
+        if blockchain.contains_transaction(&transaction.id())?
+            || blockchain.contains_signed_transaction(&transaction)?
+        {
+            return Err(TransactionRejected::AlreadyMined);
+        }
         debug!(
             "transferring {} from {} to {} (fee: {})",
             opts.amount, opts.input_address, opts.output_address, opts.fee
@@ -86,14 +173,18 @@ impl TransactionHelper {
         amount: Decimal,
         blockchain: &Chain,
     ) -> Result<(), TransactionRejected> {
-        match blockchain.wallet_amount(addr) {
-            Ok(Some(wallet_amount)) if wallet_amount < amount => {
-                Err(TransactionRejected::InsufficientBalance)
+        match blockchain.wallet_amount(addr)? {
+            Some(wallet_amount) if wallet_amount < amount => {
+                Err(TransactionRejected::InsufficientBalance {
+                    available: wallet_amount,
+                    required: amount,
+                })
+            }
+            Some(_) => Ok(()),
+            None if amount > Decimal::ZERO => {
+                Err(TransactionRejected::InputWalletNotFound(addr.to_string()))
             }
-            Ok(Some(_)) => Ok(()),
-            Ok(None) if amount > Decimal::ZERO => Err(TransactionRejected::InputWalletNotFound),
-            Ok(None) => Ok(()),
-            Err(err) => Err(TransactionRejected::BlockchainError(err)),
+            None => Ok(()),
         }
     }
 
@@ -105,25 +196,28 @@ impl TransactionHelper {
         if input_addr == addr {
             return Ok(());
         }
-        match blockchain.wallet_exists(addr) {
-            Ok(true) => Ok(()),
-            Ok(false) => Err(TransactionRejected::OutputWalletNotFound),
-            Err(err) => Err(TransactionRejected::BlockchainError(err)),
+        if blockchain.wallet_exists(addr)? {
+            Ok(())
+        } else {
+            Err(TransactionRejected::OutputWalletNotFound(addr.to_string()))
         }
     }
 
+    /// Check whether the public key provided by the issuer is well-formed
+    fn check_public_key(pubkey: &str) -> Result<(), TransactionRejected> {
+        Wallet::validate_public_key(pubkey).map_err(|_| TransactionRejected::InvalidPublicKey)
+    }
+
     fn check_transaction_signature(
         transaction: &Transaction,
         pubkey: &str,
+        verification_cache: &mut VerificationCache,
     ) -> Result<(), TransactionRejected> {
-        let mut digest_ctx = Context::new(&SHA256);
-        transaction.update_context(&mut digest_ctx);
-        let sha256 = digest_ctx.finish();
         // verify signature is correct
-        match Wallet::verify(sha256.as_ref(), transaction.signature(), pubkey) {
-            Ok(true) => Ok(()),
-            Ok(false) => Err(TransactionRejected::InvalidSignature),
-            Err(err) => Err(TransactionRejected::WalletError(err)),
+        if verification_cache.verify_transaction(transaction, pubkey)? {
+            Ok(())
+        } else {
+            Err(TransactionRejected::InvalidSignature)
         }
     }
 }
@@ -135,7 +229,11 @@ pub struct TransactionOptions {
     signature: String,
     public_key: String,
     amount: Decimal,
+    nonce: u64,
     fee: Decimal,
+    max_amount: Option<Decimal>,
+    max_inputs: usize,
+    max_outputs: usize,
 }
 
 impl TransactionOptions {
@@ -147,7 +245,11 @@ impl TransactionOptions {
             public_key: String::default(),
             signature: String::default(),
             amount: Decimal::ZERO,
+            nonce: 0,
             fee: Decimal::ZERO,
+            max_amount: None,
+            max_inputs: MAX_INPUTS,
+            max_outputs: MAX_OUTPUTS,
         }
     }
 
@@ -167,9 +269,396 @@ impl TransactionOptions {
         self
     }
 
+    /// Set the nonce chosen by the issuer, covered by the signature
+    pub fn nonce(mut self, nonce: u64) -> Self {
+        self.nonce = nonce;
+        self
+    }
+
     /// Set fee for transaction
     pub fn fee(mut self, fee: Decimal) -> Self {
         self.fee = fee;
         self
     }
+
+    /// Set the maximum amount allowed for the transaction. `None` means no cap
+    pub fn max_amount(mut self, max_amount: Option<Decimal>) -> Self {
+        self.max_amount = max_amount;
+        self
+    }
+
+    /// Set the maximum amount of inputs of the transaction
+    pub fn max_inputs(mut self, max_inputs: usize) -> Self {
+        self.max_inputs = max_inputs;
+        self
+    }
+
+    /// Set the maximum amount of outputs of the transaction, fee output included
+    pub fn max_outputs(mut self, max_outputs: usize) -> Self {
+        self.max_outputs = max_outputs;
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use rust_decimal_macros::dec;
+    use tempfile::TempDir;
+
+    /// Setup a chain where `wallet` has been funded with 100 jab by the genesis wallet
+    fn setup_chain(wallet: &Wallet) -> (TempDir, Chain) {
+        let tempdir = TempDir::new().expect("could not create tempdir");
+        let mut chain = Chain::try_from(tempdir.path()).unwrap();
+        let genesis_address = chain
+            .get_genesis_block()
+            .unwrap()
+            .transaction()
+            .output_address()
+            .unwrap()
+            .to_string();
+        let transaction = TransactionBuilder::new(TransactionVersion::V1)
+            .input(genesis_address, dec!(100.0))
+            .output(wallet.address(), dec!(100.0))
+            .finish("aaa");
+        chain.generate_next_block(transaction).unwrap();
+        (tempdir, chain)
+    }
+
+    /// Make options for a signed transaction of `amount` from `wallet` to itself
+    fn signed_options(wallet: &Wallet, amount: Decimal) -> TransactionOptions {
+        signed_options_with_nonce(wallet, amount, 0)
+    }
+
+    /// Make options for a signed transaction of `amount` from `wallet` to itself, with `nonce`
+    fn signed_options_with_nonce(
+        wallet: &Wallet,
+        amount: Decimal,
+        nonce: u64,
+    ) -> TransactionOptions {
+        let signature = TransactionBuilder::transfer(
+            TransactionVersion::V1,
+            wallet.address(),
+            wallet.address(),
+            amount,
+        )
+        .nonce(nonce)
+        .sign_with_wallet(wallet)
+        .unwrap()
+        .signature()
+        .to_string();
+        TransactionOptions::new(wallet.address(), wallet.address())
+            .amount(amount)
+            .nonce(nonce)
+            .fee(dec!(20.0))
+            .signature(signature)
+            .public_key(wallet.public_key())
+    }
+
+    #[tokio::test]
+    async fn should_reuse_cached_signature_verification() {
+        let wallet = Wallet::new();
+        let (_tempdir, chain) = setup_chain(&wallet);
+        let mut verification_cache = VerificationCache::default();
+        for _ in 0..2 {
+            assert!(TransactionHelper::create_transaction(
+                signed_options(&wallet, dec!(50.0)),
+                &Wallet::new(),
+                &chain,
+                &mut verification_cache
+            )
+            .await
+            .is_ok());
+        }
+        // the second request is served from the cache, even if relayed to a node with another fee address
+        assert_eq!(verification_cache.hits(), 1);
+        assert_eq!(verification_cache.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn should_accept_transaction_at_max_amount() {
+        let wallet = Wallet::new();
+        let (_tempdir, chain) = setup_chain(&wallet);
+        let opts = signed_options(&wallet, dec!(50.0)).max_amount(Some(dec!(50.0)));
+        assert!(TransactionHelper::create_transaction(
+            opts,
+            &Wallet::new(),
+            &chain,
+            &mut VerificationCache::default()
+        )
+        .await
+        .is_ok());
+    }
+
+    #[tokio::test]
+    async fn should_reject_transaction_above_max_outputs() {
+        let wallet = Wallet::new();
+        let (_tempdir, chain) = setup_chain(&wallet);
+        // the transfer output and the fee output
+        let opts = signed_options(&wallet, dec!(50.0)).max_outputs(1);
+        assert!(matches!(
+            TransactionHelper::create_transaction(
+                opts,
+                &Wallet::new(),
+                &chain,
+                &mut VerificationCache::default()
+            )
+            .await,
+            Err(TransactionRejected::BlockchainError(
+                BlockchainError::TooManyOutputs(2, 1)
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn should_accept_transactions_on_chain_longer_than_scan_limit() {
+        let wallet = Wallet::new();
+        let (_tempdir, chain) = setup_chain(&wallet);
+        // the chain has two blocks already: wallet queries served to peers fail on it
+        let mut chain = chain.max_scan_blocks(1);
+        let mut cache = VerificationCache::default();
+        for amount in [dec!(10.0), dec!(20.0)] {
+            let transaction = TransactionHelper::create_transaction(
+                signed_options(&wallet, amount),
+                &Wallet::new(),
+                &chain,
+                &mut cache,
+            )
+            .await
+            .unwrap();
+            chain.generate_next_block(transaction).unwrap();
+        }
+        assert_eq!(chain.get_latest_block().unwrap().index(), 3);
+    }
+
+    #[tokio::test]
+    async fn should_reject_transaction_above_max_amount() {
+        let wallet = Wallet::new();
+        let (_tempdir, chain) = setup_chain(&wallet);
+        let opts = signed_options(&wallet, dec!(50.01)).max_amount(Some(dec!(50.0)));
+        assert!(matches!(
+            TransactionHelper::create_transaction(
+                opts,
+                &Wallet::new(),
+                &chain,
+                &mut VerificationCache::default()
+            )
+            .await,
+            Err(TransactionRejected::AmountTooLarge { amount, max })
+                if amount == dec!(50.01) && max == dec!(50.0)
+        ));
+    }
+
+    #[tokio::test]
+    async fn should_not_cap_transaction_without_max_amount() {
+        let wallet = Wallet::new();
+        let (_tempdir, chain) = setup_chain(&wallet);
+        let opts = signed_options(&wallet, dec!(100.0));
+        assert!(TransactionHelper::create_transaction(
+            opts,
+            &Wallet::new(),
+            &chain,
+            &mut VerificationCache::default()
+        )
+        .await
+        .is_ok());
+    }
+
+    #[tokio::test]
+    async fn should_verify_client_signed_transaction() {
+        let wallet = Wallet::new();
+        let (_tempdir, mut chain) = setup_chain(&wallet);
+        let recipient = Wallet::new();
+        let node_wallet = Wallet::new();
+        // register recipient wallet
+        chain
+            .generate_next_block(
+                TransactionBuilder::new(TransactionVersion::V1)
+                    .output(recipient.address(), Decimal::ZERO)
+                    .finish("aaa"),
+            )
+            .unwrap();
+        // signed by the client
+        let signed = TransactionBuilder::transfer(
+            TransactionVersion::V1,
+            wallet.address(),
+            recipient.address(),
+            dec!(30.0),
+        )
+        .sign_with_wallet(&wallet)
+        .unwrap();
+        let opts = TransactionOptions::new(wallet.address(), recipient.address())
+            .amount(dec!(30.0))
+            .fee(dec!(20.0))
+            .signature(signed.signature())
+            .public_key(wallet.public_key());
+        let transaction = TransactionHelper::create_transaction(
+            opts,
+            &node_wallet,
+            &chain,
+            &mut VerificationCache::default(),
+        )
+        .await
+        .unwrap();
+        // same signed structure, plus the fee
+        assert_eq!(transaction.digest(), signed.digest());
+        assert_eq!(transaction.inputs(), signed.inputs());
+        assert_eq!(&transaction.outputs()[..1], signed.outputs());
+        assert_eq!(
+            transaction.amount_received(node_wallet.address()),
+            dec!(20.0)
+        );
+    }
+
+    #[tokio::test]
+    async fn should_reject_already_mined_transaction() {
+        let wallet = Wallet::new();
+        let node_wallet = Wallet::new();
+        let (_tempdir, mut chain) = setup_chain(&wallet);
+        let mut cache = VerificationCache::default();
+        let transaction = TransactionHelper::create_transaction(
+            signed_options(&wallet, dec!(30.0)),
+            &node_wallet,
+            &chain,
+            &mut cache,
+        )
+        .await
+        .unwrap();
+        chain.generate_next_block(transaction).unwrap();
+        // replay the same request
+        assert!(matches!(
+            TransactionHelper::create_transaction(
+                signed_options(&wallet, dec!(30.0)),
+                &node_wallet,
+                &chain,
+                &mut cache,
+            )
+            .await,
+            Err(TransactionRejected::AlreadyMined)
+        ));
+    }
+
+    #[tokio::test]
+    async fn should_reject_transaction_already_mined_by_another_node() {
+        let wallet = Wallet::new();
+        let (_tempdir, mut chain) = setup_chain(&wallet);
+        let mut cache = VerificationCache::default();
+        let transaction = TransactionHelper::create_transaction(
+            signed_options(&wallet, dec!(30.0)),
+            &Wallet::new(),
+            &chain,
+            &mut cache,
+        )
+        .await
+        .unwrap();
+        let tip = chain.generate_next_block(transaction).unwrap().index();
+        // the same request is delivered to another node, which would collect the fee
+        assert!(matches!(
+            TransactionHelper::create_transaction(
+                signed_options(&wallet, dec!(30.0)),
+                &Wallet::new(),
+                &chain,
+                &mut cache,
+            )
+            .await,
+            Err(TransactionRejected::AlreadyMined)
+        ));
+        assert_eq!(chain.get_latest_block().unwrap().index(), tip);
+    }
+
+    #[tokio::test]
+    async fn should_accept_repeated_payment_with_another_nonce() {
+        let wallet = Wallet::new();
+        let (_tempdir, mut chain) = setup_chain(&wallet);
+        let mut cache = VerificationCache::default();
+        let transaction = TransactionHelper::create_transaction(
+            signed_options_with_nonce(&wallet, dec!(10.0), 1),
+            &Wallet::new(),
+            &chain,
+            &mut cache,
+        )
+        .await
+        .unwrap();
+        chain.generate_next_block(transaction).unwrap();
+        // the user pays the same amount to the same wallet again
+        assert!(TransactionHelper::create_transaction(
+            signed_options_with_nonce(&wallet, dec!(10.0), 2),
+            &Wallet::new(),
+            &chain,
+            &mut cache,
+        )
+        .await
+        .is_ok());
+    }
+
+    #[tokio::test]
+    async fn should_report_amounts_on_insufficient_balance() {
+        let wallet = Wallet::new();
+        let (_tempdir, chain) = setup_chain(&wallet);
+        let rejection = TransactionHelper::create_transaction(
+            signed_options(&wallet, dec!(150.0)),
+            &Wallet::new(),
+            &chain,
+            &mut VerificationCache::default(),
+        )
+        .await
+        .unwrap_err();
+        let error = TransactionError::from(rejection);
+        assert_eq!(error.code, TransactionErrorCode::InsufficientBalance);
+        assert_eq!(error.detail("available"), Some("100.0"));
+        assert_eq!(error.detail("required"), Some("150.0"));
+        assert_eq!(error.details.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn should_reject_malformed_public_key_before_checking_chain() {
+        let wallet = Wallet::new();
+        let (_tempdir, chain) = setup_chain(&wallet);
+        // the input wallet doesn't exist, but the public key is checked first
+        let opts = TransactionOptions::new(Wallet::new().address(), wallet.address())
+            .amount(dec!(30.0))
+            .signature("aaa")
+            .public_key("not-a-public-key");
+        let result = TransactionHelper::create_transaction(
+            opts,
+            &Wallet::new(),
+            &chain,
+            &mut VerificationCache::default(),
+        )
+        .await;
+        assert!(matches!(result, Err(TransactionRejected::InvalidPublicKey)));
+        assert_eq!(
+            TransactionErrorCode::from(result.unwrap_err()),
+            TransactionErrorCode::InvalidPublicKey
+        );
+    }
+
+    #[test]
+    fn should_propagate_underlying_errors_into_rejection() {
+        fn fail_with_blockchain_error() -> Result<(), TransactionRejected> {
+            Err(BlockchainError::InvalidBlock)?;
+            Ok(())
+        }
+        assert!(matches!(
+            fail_with_blockchain_error(),
+            Err(TransactionRejected::BlockchainError(
+                BlockchainError::InvalidBlock
+            ))
+        ));
+        // a malformed public key makes the signature verification fail with a wallet error
+        let transaction = TransactionBuilder::new(TransactionVersion::V1)
+            .input("alice", dec!(1.0))
+            .output("bob", dec!(1.0))
+            .finish("aaa");
+        assert!(matches!(
+            TransactionHelper::check_transaction_signature(
+                &transaction,
+                "not-a-public-key",
+                &mut VerificationCache::default()
+            ),
+            Err(TransactionRejected::WalletError(_))
+        ));
+    }
 }
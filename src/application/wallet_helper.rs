@@ -2,7 +2,7 @@
 //!
 //! An helper to create or initialize an existing wallet
 
-use jab::wallet::{Wallet, SECRET_KEY_SIZE};
+use jab::wallet::{Wallet, Zeroizing, SECRET_KEY_SIZE};
 use std::path::Path;
 use tokio::{fs::OpenOptions, io::AsyncReadExt};
 
@@ -23,14 +23,17 @@ impl WalletHelper {
                         e
                     )
                 })?;
-            let mut buffer = vec![0; SECRET_KEY_SIZE];
-            secret_key.read_exact(&mut buffer).await.map_err(|e| {
-                anyhow::anyhow!(
-                    "failed to read secret key from {}: {}",
-                    secret_key_path.display(),
-                    e
-                )
-            })?;
+            let mut buffer = Zeroizing::new(vec![0; SECRET_KEY_SIZE]);
+            secret_key
+                .read_exact(buffer.as_mut_slice())
+                .await
+                .map_err(|e| {
+                    anyhow::anyhow!(
+                        "failed to read secret key from {}: {}",
+                        secret_key_path.display(),
+                        e
+                    )
+                })?;
             Wallet::try_from(buffer.as_slice())
                 .map_err(|e| anyhow::anyhow!("invalid wallet key: {}", e))
                 .map(|w| {
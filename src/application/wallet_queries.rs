@@ -0,0 +1,189 @@
+//! # Wallet queries
+//!
+//! Serves wallet details queries on blocking threads, bounding the amount of queries served at the same time
+
+use jab::blockchain::ChainReader;
+use jab::net::message::{WalletQuery, WalletQueryError, WalletQueryResult};
+
+use std::sync::Arc;
+use tokio::sync::{mpsc::UnboundedSender, OwnedSemaphorePermit, Semaphore};
+
+/// Result for a wallet query, along with the id of the requesting peer and the id of the request
+pub type WalletQueryResponse = (String, u64, WalletQueryResult);
+
+/// Kind of wallet query to serve
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum WalletQueryKind {
+    /// Balance and transactions
+    Details,
+    /// Balance only
+    Balance,
+}
+
+/// Wallet queries worker
+pub struct WalletQueries {
+    blockchain: ChainReader,
+    permits: Arc<Semaphore>,
+    response_sender: UnboundedSender<WalletQueryResponse>,
+}
+
+impl WalletQueries {
+    /// Instantiate a new `WalletQueries` serving at most `max_concurrent_queries` at the same time.
+    /// Responses are sent through `response_sender`
+    pub fn new(
+        blockchain: ChainReader,
+        max_concurrent_queries: usize,
+        response_sender: UnboundedSender<WalletQueryResponse>,
+    ) -> Self {
+        Self {
+            blockchain,
+            permits: Arc::new(Semaphore::new(max_concurrent_queries)),
+            response_sender,
+        }
+    }
+
+    /// Try to reserve a slot to serve a query.
+    /// Returns `None` if the maximum amount of queries is already being served
+    pub fn try_acquire(&self) -> Option<OwnedSemaphorePermit> {
+        self.permits.clone().try_acquire_owned().ok()
+    }
+
+    /// Serve `query` on a blocking thread. The slot reserved by `permit` is released once the query has been served
+    pub fn serve(&self, query: WalletQuery, kind: WalletQueryKind, permit: OwnedSemaphorePermit) {
+        let blockchain = self.blockchain.clone();
+        let response_sender = self.response_sender.clone();
+        tokio::task::spawn_blocking(move || {
+            let result = match kind {
+                WalletQueryKind::Details => Self::query(&blockchain, &query.address),
+                WalletQueryKind::Balance => Self::balance(&blockchain, &query.address),
+            };
+            drop(permit);
+            if let Err(err) = response_sender.send((query.peer_id, query.request_id, result)) {
+                error!("failed to send wallet query response: {}", err);
+            }
+        });
+    }
+
+    /// Get balance for wallet with `address`, without collecting its transactions
+    fn balance(blockchain: &ChainReader, address: &str) -> WalletQueryResult {
+        let height = match blockchain.height() {
+            Ok(height) => height,
+            Err(err) => {
+                error!("could not get latest block: {}", err);
+                return WalletQueryResult::error(WalletQueryError::BlockchainError);
+            }
+        };
+        match blockchain.wallet_amount(address) {
+            Ok(Some(balance)) => WalletQueryResult::balance(address, balance, height),
+            Ok(None) => WalletQueryResult::error(WalletQueryError::WalletNotFound),
+            Err(err) => {
+                error!("could not get wallet amount for {}: {}", address, err);
+                WalletQueryResult::error(WalletQueryError::BlockchainError)
+            }
+        }
+    }
+
+    /// Collect balance and transactions for wallet with `address`
+    fn query(blockchain: &ChainReader, address: &str) -> WalletQueryResult {
+        let balance = match blockchain.wallet_amount(address) {
+            Ok(Some(balance)) => balance,
+            Ok(None) => return WalletQueryResult::error(WalletQueryError::WalletNotFound),
+            Err(err) => {
+                error!("could not get wallet amount for {}: {}", address, err);
+                return WalletQueryResult::error(WalletQueryError::BlockchainError);
+            }
+        };
+        // collect transactions
+        match blockchain.wallet_transactions(address) {
+            Err(err) => {
+                error!("could not get wallet transactions for {}: {}", address, err);
+                WalletQueryResult::error(WalletQueryError::BlockchainError)
+            }
+            Ok(None) => WalletQueryResult::error(WalletQueryError::WalletNotFound),
+            Ok(Some(transactions)) => {
+                debug!(
+                    "found {} transactions for wallet {}; current amount {} JAB",
+                    transactions.len(),
+                    address,
+                    balance
+                );
+                WalletQueryResult::ok(address, transactions, balance)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use jab::blockchain::Chain;
+    use pretty_assertions::assert_eq;
+    use rust_decimal_macros::dec;
+    use tempfile::TempDir;
+    use tokio::sync::mpsc;
+
+    fn wallet_queries(max_concurrent_queries: usize) -> (TempDir, WalletQueries) {
+        let tempdir = TempDir::new().expect("could not create tempdir");
+        let chain = Chain::try_from(tempdir.path()).unwrap();
+        let (sender, _) = mpsc::unbounded_channel();
+        (
+            tempdir,
+            WalletQueries::new(chain.reader(), max_concurrent_queries, sender),
+        )
+    }
+
+    #[test]
+    fn should_shed_queries_beyond_limit() {
+        let (_tempdir, queries) = wallet_queries(2);
+        let first = queries.try_acquire();
+        let second = queries.try_acquire();
+        assert!(first.is_some());
+        assert!(second.is_some());
+        assert!(queries.try_acquire().is_none());
+        // release a slot
+        drop(first);
+        assert!(queries.try_acquire().is_some());
+    }
+
+    #[test]
+    fn should_query_wallet() {
+        let (_tempdir, queries) = wallet_queries(1);
+        let genesis_block = queries.blockchain.get_genesis_block().unwrap();
+        let address = genesis_block.transaction().output_address().unwrap();
+        assert_eq!(
+            WalletQueries::query(&queries.blockchain, address),
+            WalletQueryResult::ok(
+                address,
+                vec![genesis_block.transaction().clone()],
+                dec!(500.0)
+            )
+        );
+        assert_eq!(
+            WalletQueries::query(
+                &queries.blockchain,
+                "jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1"
+            ),
+            WalletQueryResult::error(WalletQueryError::WalletNotFound)
+        );
+    }
+
+    #[test]
+    fn should_query_wallet_balance_only() {
+        let (_tempdir, queries) = wallet_queries(1);
+        let genesis_block = queries.blockchain.get_genesis_block().unwrap();
+        let address = genesis_block.transaction().output_address().unwrap();
+        assert_eq!(
+            WalletQueries::balance(&queries.blockchain, address),
+            WalletQueryResult::balance(address, dec!(500.0), 0)
+        );
+        assert_eq!(
+            WalletQueries::balance(
+                &queries.blockchain,
+                "jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1"
+            ),
+            WalletQueryResult::error(WalletQueryError::WalletNotFound)
+        );
+    }
+}
@@ -2,6 +2,10 @@
 //!
 //! block header
 
+use crate::blockchain::BlockchainResult;
+
+use data_encoding::HEXLOWER;
+use ring::digest::{digest, SHA256};
 use std::{str::FromStr, time::SystemTime};
 
 /// Blockchain version
@@ -28,6 +32,9 @@ impl ToString for Version {
     }
 }
 
+/// Difficulty of blocks which don't define any (blocks mined before difficulty was introduced)
+pub const MIN_DIFFICULTY: u32 = 1;
+
 /// Blockchain header
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct Header {
@@ -39,6 +46,17 @@ pub struct Header {
     merkle_root_hash: String,
     /// the UNIX epoch time the miner started hashing the header
     created_at: SystemTime,
+    /// the difficulty the block has been mined with
+    #[serde(default = "default_difficulty")]
+    difficulty: u32,
+    /// the nonce found by the miner.
+    /// NOTE: the field order is part of the header hash, so fields must never be reordered
+    #[serde(default)]
+    nonce: u64,
+}
+
+fn default_difficulty() -> u32 {
+    MIN_DIFFICULTY
 }
 
 impl Header {
@@ -54,9 +72,23 @@ impl Header {
             previous_block_header_hash,
             merkle_root_hash,
             created_at,
+            difficulty: MIN_DIFFICULTY,
+            nonce: 0,
         }
     }
 
+    /// Set the difficulty the block has been mined with
+    pub fn with_difficulty(mut self, difficulty: u32) -> Self {
+        self.difficulty = difficulty;
+        self
+    }
+
+    /// Set the nonce found by the miner
+    pub fn with_nonce(mut self, nonce: u64) -> Self {
+        self.nonce = nonce;
+        self
+    }
+
     /// Get previous block header hash
     pub fn previous_block_header_hash(&self) -> Option<&str> {
         self.previous_block_header_hash.as_deref()
@@ -66,4 +98,113 @@ impl Header {
     pub fn merkle_root_hash(&self) -> &str {
         &self.merkle_root_hash
     }
+
+    /// Get the time the block has been created at
+    pub fn created_at(&self) -> SystemTime {
+        self.created_at
+    }
+
+    /// Get the difficulty the block has been mined with
+    pub fn difficulty(&self) -> u32 {
+        self.difficulty
+    }
+
+    /// Get the nonce found by the miner
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    /// Calculate the SHA256 hash of the header serialization, as lowercase hex.
+    /// Fails if the header can't be serialized (e.g. it has been created before the UNIX epoch)
+    pub fn hash(&self) -> BlockchainResult<String> {
+        let serialized = serde_json::to_vec(self)?;
+        Ok(HEXLOWER.encode(digest(&SHA256, &serialized).as_ref()))
+    }
+
+    /// Returns whether the header hash starts with at least `difficulty` zero bits
+    pub fn meets_difficulty(&self) -> BlockchainResult<bool> {
+        let mut zeros = 0;
+        for nibble in self.hash()?.chars().filter_map(|x| x.to_digit(16)) {
+            if nibble != 0 {
+                zeros += nibble.leading_zeros() - 28;
+                break;
+            }
+            zeros += 4;
+        }
+        Ok(zeros >= self.difficulty)
+    }
+
+    /// Search the first nonce which makes the header meet its difficulty.
+    /// Returns `None` if no nonce does
+    pub fn mine(self) -> BlockchainResult<Option<Self>> {
+        for nonce in 0..=u64::MAX {
+            let header = self.clone().with_nonce(nonce);
+            if header.meets_difficulty()? {
+                return Ok(Some(header));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn should_serialize_and_hash_header_deterministically() {
+        // consensus critical: a change to these values forks the network
+        let header = Header::new(
+            Version::V010,
+            Some(String::from("cafebabe")),
+            String::from("deadbeef"),
+            UNIX_EPOCH + Duration::from_secs(1000),
+        )
+        .with_difficulty(4)
+        .with_nonce(42);
+        assert_eq!(
+            serde_json::to_string(&header).unwrap(),
+            r#"{"version":"V010","previous_block_header_hash":"cafebabe","merkle_root_hash":"deadbeef","created_at":{"secs_since_epoch":1000,"nanos_since_epoch":0},"difficulty":4,"nonce":42}"#
+        );
+        assert_eq!(
+            header.hash().unwrap(),
+            "c5badc0729957afe94453ab21394589266b685165422d8573a0d94c4ccf06964"
+        );
+    }
+
+    #[test]
+    fn should_mine_header_meeting_difficulty() {
+        let header = Header::new(
+            Version::V010,
+            Some(String::from("cafebabe")),
+            String::from("deadbeef"),
+            UNIX_EPOCH + Duration::from_secs(1000),
+        )
+        .with_difficulty(8)
+        .with_nonce(42);
+        // c5badc07...
+        assert!(!header.meets_difficulty().unwrap());
+        let mined = header.mine().unwrap().unwrap();
+        assert!(mined.meets_difficulty().unwrap());
+        assert!(mined.hash().unwrap().starts_with("00"));
+        assert!(!mined
+            .clone()
+            .with_difficulty(256)
+            .meets_difficulty()
+            .unwrap());
+    }
+
+    #[test]
+    fn should_deserialize_header_without_nonce() {
+        let header: Header = serde_json::from_str(
+            r#"{"version":"V010","previous_block_header_hash":null,"merkle_root_hash":"deadbeef","created_at":{"secs_since_epoch":1000,"nanos_since_epoch":0},"difficulty":4}"#,
+        )
+        .unwrap();
+        assert_eq!(header.nonce(), 0);
+        assert_eq!(header.difficulty(), 4);
+    }
 }
@@ -5,8 +5,13 @@
 mod header;
 mod transaction;
 
-pub use header::{Header, Version};
-pub use transaction::{Transaction, TransactionBuilder, TransactionVersion};
+use rust_decimal::Decimal;
+use std::hash::{Hash, Hasher};
+
+pub use header::{Header, Version, MIN_DIFFICULTY};
+pub use transaction::{
+    LockOutput, Transaction, TransactionBuilder, TransactionVersion, UnlockInput,
+};
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct Block {
@@ -33,6 +38,11 @@ impl Block {
         self.index
     }
 
+    /// Return the block id, which is the canonical block hash (the merkle root hash)
+    pub fn id(&self) -> String {
+        self.header.merkle_root_hash().to_string()
+    }
+
     /// Return a reference to the block header
     pub fn header(&self) -> &Header {
         &self.header
@@ -42,4 +52,161 @@ impl Block {
     pub fn transaction(&self) -> &Transaction {
         &self.txns
     }
+
+    /// Return the amount of transactions in the block.
+    /// Currently each block holds exactly one transaction
+    pub fn transactions_count(&self) -> usize {
+        1
+    }
+
+    /// Return the fees paid to `miner_addr` in this block.
+    /// The primary output of each transaction is not a fee, so it is never accounted.
+    /// Coinbase transactions pay no fees, since there's no issuer
+    pub fn total_fees(&self, miner_addr: &str) -> Decimal {
+        if self.txns.is_coinbase() {
+            return Decimal::ZERO;
+        }
+        self.txns
+            .outputs()
+            .iter()
+            .skip(1)
+            .filter(|x| x.address.as_str() == miner_addr)
+            .map(|x| x.amount)
+            .sum()
+    }
+}
+
+/// A block compared and hashed by its id only, so blocks can be deduplicated by hash in sets and maps
+#[derive(Debug, Clone)]
+pub struct BlockById(pub Block);
+
+impl From<Block> for BlockById {
+    fn from(block: Block) -> Self {
+        Self(block)
+    }
+}
+
+impl PartialEq for BlockById {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.header.merkle_root_hash() == other.0.header.merkle_root_hash()
+    }
+}
+
+impl Eq for BlockById {}
+
+impl Hash for BlockById {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.header.merkle_root_hash().hash(state);
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use rust_decimal_macros::dec;
+    use std::time::SystemTime;
+
+    fn block(transaction: Transaction) -> Block {
+        Block::new(
+            1,
+            Header::new(
+                Version::V010,
+                Some(String::from("cafebabe")),
+                String::from("deadbeef"),
+                SystemTime::now(),
+            ),
+            transaction,
+        )
+    }
+
+    #[test]
+    fn should_dedup_blocks_by_id() {
+        use std::collections::HashSet;
+
+        let first = block(
+            TransactionBuilder::new(TransactionVersion::V1)
+                .output("bob", dec!(10.50))
+                .finish("aaa"),
+        );
+        // same merkle root, different content
+        let second = block(
+            TransactionBuilder::new(TransactionVersion::V1)
+                .output("bob", dec!(20.0))
+                .finish("bbb"),
+        );
+        assert_ne!(first, second);
+        assert_eq!(first.id(), second.id());
+        assert_eq!(first.id(), "deadbeef");
+        let mut seen: HashSet<BlockById> = HashSet::new();
+        assert!(seen.insert(BlockById::from(first)));
+        assert!(!seen.insert(BlockById::from(second.clone())));
+        assert_eq!(seen.len(), 1);
+        // the index is not part of the id: the same merkle root at another index is the same block
+        let other = Block::new(2, second.header().clone(), second.transaction().clone());
+        assert!(!seen.insert(BlockById::from(other)));
+        // a block with another merkle root is a different block
+        let other = Block::new(
+            2,
+            Header::new(
+                Version::V010,
+                Some(String::from("deadbeef")),
+                String::from("cafebabe"),
+                SystemTime::now(),
+            ),
+            second.transaction().clone(),
+        );
+        assert!(seen.insert(BlockById::from(other)));
+    }
+
+    #[test]
+    fn should_count_block_transactions() {
+        let block = block(
+            TransactionBuilder::new(TransactionVersion::V1)
+                .input("alice", dec!(10.50))
+                .output("bob", dec!(10.50))
+                .finish("aaa"),
+        );
+        assert_eq!(block.transactions_count(), 1);
+    }
+
+    #[test]
+    fn should_calc_total_fees_for_miner() {
+        let block = block(
+            TransactionBuilder::new(TransactionVersion::V1)
+                .input("alice", dec!(10.50))
+                .output("bob", dec!(10.50))
+                .output("miner", dec!(0.02))
+                .output("miner", dec!(0.01))
+                .finish("aaa"),
+        );
+        assert_eq!(block.total_fees("miner"), dec!(0.03));
+        assert_eq!(block.total_fees("bob"), Decimal::ZERO);
+        assert_eq!(block.total_fees("alice"), Decimal::ZERO);
+    }
+
+    #[test]
+    fn should_not_account_primary_output_as_fee() {
+        let block = block(
+            TransactionBuilder::new(TransactionVersion::V1)
+                .input("alice", dec!(10.50))
+                .output("miner", dec!(10.50))
+                .output("miner", dec!(0.02))
+                .finish("aaa"),
+        );
+        assert_eq!(block.total_fees("miner"), dec!(0.02));
+    }
+
+    #[test]
+    fn should_not_account_coinbase_outputs_as_fees() {
+        let block = block(
+            TransactionBuilder::new(TransactionVersion::V1)
+                .output("alice", dec!(500.0))
+                .output("bob", dec!(100.0))
+                .finish("aaa"),
+        );
+        assert_eq!(block.total_fees("bob"), Decimal::ZERO);
+    }
 }
@@ -3,13 +3,14 @@
 //! Used to SAFELY create transactions
 
 use super::{LockOutput, Transaction, TransactionVersion, UnlockInput};
-use crate::wallet::{Wallet, WalletError};
+use crate::blockchain::{BlockchainError, BlockchainResult};
+use crate::wallet::{Signer, Wallet, WalletError};
 
-use merkle::Hashable;
-use ring::digest::{Context, SHA256};
 use rust_decimal::Decimal;
 
-/// A safe builder to create transactions
+/// A safe builder to create transactions.
+/// The builder doesn't hold any signature, so it can be cloned to build different variants of the same transaction
+#[derive(Debug, Clone)]
 pub struct TransactionBuilder {
     /// Transaction inputs
     inputs: Vec<UnlockInput>,
@@ -17,6 +18,8 @@ pub struct TransactionBuilder {
     outputs: Vec<LockOutput>,
     /// Transaction version
     version: TransactionVersion,
+    /// Nonce chosen by the issuer
+    nonce: u64,
 }
 
 impl TransactionBuilder {
@@ -26,9 +29,41 @@ impl TransactionBuilder {
             inputs: vec![],
             outputs: vec![],
             version,
+            nonce: 0,
         }
     }
 
+    /// Create the canonical form of a transfer of `amount` from `input` to `output`.
+    /// This is the form signed by clients and rebuilt by nodes before adding the fee, so the signature always matches
+    pub fn transfer(
+        version: TransactionVersion,
+        input: impl ToString,
+        output: impl ToString,
+        amount: Decimal,
+    ) -> Self {
+        Self::new(version)
+            .input(input, amount)
+            .output(output, amount)
+    }
+
+    /// Create a builder from the version, inputs and outputs of an existing transaction, so it can be modified and
+    /// signed again. The signature of `transaction` is discarded, while its nonce is kept
+    pub fn from_existing(transaction: &Transaction) -> Self {
+        Self {
+            inputs: transaction.inputs.clone(),
+            outputs: transaction.outputs.clone(),
+            version: transaction.version,
+            nonce: transaction.nonce,
+        }
+    }
+
+    /// Set the nonce of the transaction. Issuers should pick a random one, so paying the same amount to the same
+    /// wallet again doesn't produce the same signed transaction
+    pub fn nonce(mut self, nonce: u64) -> Self {
+        self.nonce = nonce;
+        self
+    }
+
     /// Add input to transaction
     pub fn input(mut self, addr: impl ToString, amount: Decimal) -> Self {
         self.inputs.push(UnlockInput::new(addr, amount));
@@ -41,14 +76,69 @@ impl TransactionBuilder {
         self
     }
 
+    /// Replace the input at `index`. If there's no input at `index`, the input is added
+    pub fn set_input(mut self, index: usize, addr: impl ToString, amount: Decimal) -> Self {
+        let input = UnlockInput::new(addr, amount);
+        match self.inputs.get_mut(index) {
+            Some(old) => *old = input,
+            None => self.inputs.push(input),
+        }
+        self
+    }
+
+    /// Replace the output at `index`. If there's no output at `index`, the output is added
+    pub fn set_output(mut self, index: usize, addr: impl ToString, amount: Decimal) -> Self {
+        let output = LockOutput::new(addr, amount);
+        match self.outputs.get_mut(index) {
+            Some(old) => *old = output,
+            None => self.outputs.push(output),
+        }
+        self
+    }
+
+    /// Add the change output for `addr`, given the total amount of the inputs, the amount sent and the fee.
+    /// If there's no change, no output is added.
+    /// Fails with `InsufficientFunds` if inputs don't cover the amount sent plus the fee
+    pub fn change(
+        self,
+        addr: impl ToString,
+        input_total: Decimal,
+        send_total: Decimal,
+        fee: Decimal,
+    ) -> BlockchainResult<Self> {
+        let required = send_total + fee;
+        if input_total < required {
+            return Err(BlockchainError::InsufficientFunds {
+                available: input_total,
+                required,
+            });
+        }
+        let change = input_total - required;
+        if change.is_zero() {
+            Ok(self)
+        } else {
+            Ok(self.output(addr, change))
+        }
+    }
+
     /// Sign transaction with wallet and return transaction
     pub fn sign_with_wallet(self, wallet: &Wallet) -> Result<Transaction, WalletError> {
-        let mut transaction =
-            Transaction::new(self.version, self.inputs, self.outputs, String::default());
-        let mut digest_ctx = Context::new(&SHA256);
-        transaction.update_context(&mut digest_ctx);
-        let sha256 = digest_ctx.finish();
-        let signature = wallet.sign(sha256.as_ref())?;
+        self.sign_with(wallet)
+    }
+
+    /// Sign transaction with any `Signer` (e.g. an external signer) and return transaction
+    pub fn sign_with<S>(self, signer: &S) -> Result<Transaction, WalletError>
+    where
+        S: Signer + ?Sized,
+    {
+        let mut transaction = Transaction::new(
+            self.version,
+            self.inputs,
+            self.outputs,
+            String::default(),
+            self.nonce,
+        );
+        let signature = signer.sign(&transaction.digest())?;
         transaction.signature = signature;
         Ok(transaction)
     }
@@ -60,6 +150,168 @@ impl TransactionBuilder {
             self.inputs,
             self.outputs,
             signature.to_string(),
+            self.nonce,
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::wallet::WalletResult;
+
+    use pretty_assertions::assert_eq;
+    use rust_decimal_macros::dec;
+    use secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+
+    /// A signer which keeps its key outside of a `Wallet`, as an external signer would
+    struct MockSigner {
+        secret_key: SecretKey,
+    }
+
+    impl Signer for MockSigner {
+        fn sign(&self, message: &[u8]) -> WalletResult<String> {
+            let message = Message::from_slice(message)?;
+            Ok(Secp256k1::new()
+                .sign_ecdsa(&message, &self.secret_key)
+                .to_string())
+        }
+
+        fn public_key(&self) -> String {
+            PublicKey::from_secret_key(&Secp256k1::new(), &self.secret_key).to_string()
+        }
+    }
+
+    #[test]
+    fn should_add_change_output() {
+        let transaction = TransactionBuilder::new(TransactionVersion::V1)
+            .input("alice", dec!(50.0))
+            .output("bob", dec!(30.0))
+            .output("miner", dec!(0.5))
+            .change("alice", dec!(50.0), dec!(30.0), dec!(0.5))
+            .unwrap()
+            .finish("aaa");
+        assert_eq!(
+            transaction.outputs().last().unwrap(),
+            &LockOutput::new("alice", dec!(19.5))
+        );
+        assert_eq!(transaction.amount_received("alice"), dec!(19.5));
+    }
+
+    #[test]
+    fn should_not_add_change_output_if_there_is_no_change() {
+        let transaction = TransactionBuilder::new(TransactionVersion::V1)
+            .input("alice", dec!(30.5))
+            .output("bob", dec!(30.0))
+            .output("miner", dec!(0.5))
+            .change("alice", dec!(30.5), dec!(30.0), dec!(0.5))
+            .unwrap()
+            .finish("aaa");
+        assert_eq!(transaction.outputs().len(), 2);
+    }
+
+    #[test]
+    fn should_finish_cloned_builders_independently() {
+        let wallet = Wallet::new();
+        let builder = TransactionBuilder::transfer(
+            TransactionVersion::V1,
+            wallet.address(),
+            "bob",
+            dec!(30.0),
+        );
+        let low_fee = builder
+            .clone()
+            .output("miner", dec!(0.5))
+            .sign_with_wallet(&wallet)
+            .unwrap();
+        let high_fee = builder
+            .output("miner", dec!(2.0))
+            .sign_with_wallet(&wallet)
+            .unwrap();
+        // finishing a clone doesn't affect the other variant
+        assert_eq!(low_fee.outputs().len(), 2);
+        assert_eq!(high_fee.outputs().len(), 2);
+        assert_eq!(low_fee.amount_received("miner"), dec!(0.5));
+        assert_eq!(high_fee.amount_received("miner"), dec!(2.0));
+        assert_ne!(low_fee.id(), high_fee.id());
+        for transaction in [&low_fee, &high_fee] {
+            assert!(Wallet::verify(
+                &transaction.digest(),
+                transaction.signature(),
+                &wallet.public_key()
+            )
+            .unwrap());
+        }
+    }
+
+    #[test]
+    fn should_rebuild_and_sign_again_existing_transaction() {
+        let wallet = Wallet::new();
+        let transaction = TransactionBuilder::new(TransactionVersion::V1)
+            .input(wallet.address(), dec!(30.5))
+            .output("bob", dec!(30.0))
+            .output("miner", dec!(0.5))
+            .sign_with_wallet(&wallet)
+            .unwrap();
+        // the builder doesn't keep the signature
+        assert_eq!(
+            TransactionBuilder::from_existing(&transaction).finish(""),
+            Transaction::new(
+                TransactionVersion::V1,
+                transaction.inputs().to_vec(),
+                transaction.outputs().to_vec(),
+                String::default(),
+                0
+            )
+        );
+        assert_eq!(
+            TransactionBuilder::from_existing(&transaction)
+                .sign_with_wallet(&wallet)
+                .unwrap(),
+            transaction
+        );
+        // bump fee
+        let bumped = TransactionBuilder::from_existing(&transaction)
+            .set_input(0, wallet.address(), dec!(31.0))
+            .set_output(1, "miner", dec!(1.0))
+            .sign_with_wallet(&wallet)
+            .unwrap();
+        assert_eq!(bumped.amount_received("miner"), dec!(1.0));
+        assert_eq!(bumped.outputs().len(), 2);
+        assert_ne!(bumped.signature(), transaction.signature());
+        assert!(bumped.verify_signature(&wallet.public_key()).unwrap());
+    }
+
+    #[test]
+    fn should_sign_with_external_signer() {
+        let signer = MockSigner {
+            secret_key: SecretKey::from_slice(&[0x42; 32]).unwrap(),
+        };
+        let transaction = TransactionBuilder::transfer(
+            TransactionVersion::V1,
+            Wallet::address_from_public_key(&signer.public_key()).unwrap(),
+            "bob",
+            dec!(30.0),
+        )
+        .sign_with(&signer)
+        .unwrap();
+        assert!(transaction.verify_signature(&signer.public_key()).unwrap());
+        // a wallet with the same key produces the same signature
+        let wallet = Wallet::try_from([0x42; 32].as_slice()).unwrap();
+        assert_eq!(wallet.public_key(), signer.public_key());
+        assert!(transaction.verify_signature(&wallet.public_key()).unwrap());
+    }
+
+    #[test]
+    fn should_fail_change_with_insufficient_funds() {
+        assert!(matches!(
+            TransactionBuilder::new(TransactionVersion::V1)
+                .input("alice", dec!(30.0))
+                .change("alice", dec!(30.0), dec!(30.0), dec!(0.5)),
+            Err(BlockchainError::InsufficientFunds { available, required })
+                if available == dec!(30.0) && required == dec!(30.5)
+        ));
+    }
+}
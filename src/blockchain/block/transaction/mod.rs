@@ -2,18 +2,22 @@
 //!
 //! the transaction contained in the block
 
+use data_encoding::HEXLOWER;
 use merkle::Hashable;
-use ring::digest::Context;
+use ring::digest::{Context, SHA256};
 
 mod builder;
 mod input;
 mod output;
 
 pub use builder::TransactionBuilder;
-use input::UnlockInput;
-use output::LockOutput;
+pub use input::UnlockInput;
+pub use output::LockOutput;
 use rust_decimal::Decimal;
 
+use crate::blockchain::{BlockchainError, BlockchainResult};
+use crate::wallet::{Wallet, WalletResult};
+
 /// Describes the transaction version
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
 #[repr(u8)]
@@ -31,6 +35,15 @@ pub struct Transaction {
     outputs: Vec<LockOutput>,
     /// HEXLOWER encoded signature of the issuer. The message for the signature
     signature: String,
+    /// Random number chosen by the issuer, so repeated identical payments have distinct signatures and ids.
+    /// Zero (not serialized) for transactions made before it was introduced and for coinbase transactions
+    #[serde(default, skip_serializing_if = "is_zero")]
+    nonce: u64,
+}
+
+/// Returns whether the nonce is unset
+fn is_zero(nonce: &u64) -> bool {
+    *nonce == 0
 }
 
 impl Transaction {
@@ -40,12 +53,14 @@ impl Transaction {
         inputs: Vec<UnlockInput>,
         outputs: Vec<LockOutput>,
         signature: String,
+        nonce: u64,
     ) -> Self {
         Self {
             version,
             inputs,
             outputs,
             signature,
+            nonce,
         }
     }
 
@@ -63,10 +78,12 @@ impl Transaction {
         self
     }
 
+    /// Get transaction inputs
     pub fn inputs(&self) -> &[UnlockInput] {
         &self.inputs
     }
 
+    /// Get transaction outputs
     pub fn outputs(&self) -> &[LockOutput] {
         &self.outputs
     }
@@ -76,16 +93,138 @@ impl Transaction {
         &self.signature
     }
 
+    /// Get the nonce chosen by the issuer
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    /// Get the digest signed by the issuer of the transaction.
+    ///
+    /// The digest covers the version, the primary input, the primary output and the nonce only,
+    /// so fee and change outputs appended by the node don't invalidate the issuer signature
+    pub fn digest(&self) -> Vec<u8> {
+        let mut digest_ctx = Context::new(&SHA256);
+        self.update_context(&mut digest_ctx);
+        digest_ctx.finish().as_ref().to_vec()
+    }
+
+    /// Verify whether the transaction has been signed by the owner of `pubkey`, recomputing its digest
+    pub fn verify_signature(&self, pubkey: &str) -> WalletResult<bool> {
+        Wallet::verify(&self.digest(), &self.signature, pubkey)
+    }
+
+    /// Get the transaction id: the HEXLOWER encoded SHA256 of all the inputs, outputs and of the signature.
+    /// Unlike `digest`, it covers the whole transaction, so two transactions differing in any field have different ids
+    pub fn id(&self) -> String {
+        let mut digest_ctx = Context::new(&SHA256);
+        digest_ctx.update(&[self.version as u8]);
+        for input in self.inputs.iter() {
+            input.update_context(&mut digest_ctx);
+        }
+        for output in self.outputs.iter() {
+            output.update_context(&mut digest_ctx);
+        }
+        Self::update_nonce(self.nonce, &mut digest_ctx);
+        digest_ctx.update(self.signature.as_bytes());
+        HEXLOWER.encode(digest_ctx.finish().as_ref())
+    }
+
+    /// Get the id of the transaction signed by the issuer: the HEXLOWER encoded SHA256 of the digest and of the signature.
+    /// Unlike `id`, it doesn't cover the fee and change outputs appended by the node, so the same request mined by
+    /// different nodes has the same signed id
+    pub fn signed_id(&self) -> String {
+        let mut digest_ctx = Context::new(&SHA256);
+        digest_ctx.update(&self.digest());
+        digest_ctx.update(self.signature.as_bytes());
+        HEXLOWER.encode(digest_ctx.finish().as_ref())
+    }
+
+    /// Returns whether this is a coinbase transaction, which creates new jab out of nothing (e.g. the genesis transaction).
+    /// A coinbase transaction has no inputs
+    pub fn is_coinbase(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// Update the digest `context` with `nonce`. Transactions without nonce are hashed as before it was introduced
+    fn update_nonce(nonce: u64, context: &mut Context) {
+        if nonce != 0 {
+            context.update(&nonce.to_be_bytes());
+        }
+    }
+
+    /// Check the transaction structure:
+    ///
+    /// - it must have outputs, no negative amounts and its primary output must be covered by the inputs
+    /// - it can't have more than `max_inputs` inputs and, unless it is a coinbase, more than `max_outputs` outputs.
+    ///   Coinbase transactions are exempt from the outputs limit, since the genesis may credit many allocations
+    /// - non-coinbase transactions must be signed
+    pub fn validate_structure(
+        &self,
+        max_inputs: usize,
+        max_outputs: usize,
+    ) -> BlockchainResult<()> {
+        let primary_output = self
+            .outputs
+            .first()
+            .ok_or(BlockchainError::MalformedTransaction(
+                "transaction has no outputs",
+            ))?;
+        if self.inputs.iter().any(|x| x.amount < Decimal::ZERO)
+            || self.outputs.iter().any(|x| x.amount < Decimal::ZERO)
+        {
+            return Err(BlockchainError::MalformedTransaction(
+                "transaction has negative amounts",
+            ));
+        }
+        if self.inputs.len() > max_inputs {
+            return Err(BlockchainError::TooManyInputs(
+                self.inputs.len(),
+                max_inputs,
+            ));
+        }
+        if self.is_coinbase() {
+            return Ok(());
+        }
+        if self.outputs.len() > max_outputs {
+            return Err(BlockchainError::TooManyOutputs(
+                self.outputs.len(),
+                max_outputs,
+            ));
+        }
+        if primary_output.amount > self.total_input() {
+            return Err(BlockchainError::MalformedTransaction(
+                "primary output exceeds inputs",
+            ));
+        }
+        if self.signature.is_empty() {
+            return Err(BlockchainError::MissingSignature);
+        }
+        Ok(())
+    }
+
     /// Get input address for transaction
     pub fn input_address(&self) -> Option<&str> {
         self.inputs.get(0).map(|x| x.address.as_str())
     }
 
-    /// Get original output address for transaction
+    /// Get the primary output address for transaction (the recipient).
+    ///
+    /// The primary output is always the first one, since fee and change outputs are appended after it.
+    /// Returns `None` if the transaction has no output
     pub fn output_address(&self) -> Option<&str> {
         self.outputs.get(0).map(|x| x.address.as_str())
     }
 
+    /// Returns the sum of the amounts of all the inputs
+    pub fn total_input(&self) -> Decimal {
+        self.inputs.iter().map(|x| x.amount).sum()
+    }
+
+    /// Returns the sum of the amounts of all the outputs, fee and change included
+    pub fn total_output(&self) -> Decimal {
+        self.outputs.iter().map(|x| x.amount).sum()
+    }
+
     /// Returns the amount spent by `addr` in this transaction
     /// The number returned is ZERO or NEGATIVE by design
     pub fn amount_spent(&self, addr: &str) -> Decimal {
@@ -118,6 +257,7 @@ impl Hashable for Transaction {
         if let Some(output) = self.outputs.get(0) {
             output.update_context(context);
         }
+        Self::update_nonce(self.nonce, context);
     }
 }
 
@@ -129,6 +269,38 @@ mod test {
     use pretty_assertions::assert_eq;
     use rust_decimal_macros::dec;
 
+    fn transaction_with(inputs: usize, outputs: usize) -> Transaction {
+        let builder = (0..inputs).fold(TransactionBuilder::new(TransactionVersion::V1), |b, _| {
+            b.input("alice", dec!(1.0))
+        });
+        (0..outputs)
+            .fold(builder, |b, _| b.output("bob", dec!(1.0)))
+            .finish("aaa")
+    }
+
+    #[test]
+    fn should_accept_transactions_at_limits() {
+        assert!(transaction_with(4, 4).validate_structure(4, 4).is_ok());
+        assert!(transaction_with(1, 1).validate_structure(4, 4).is_ok());
+    }
+
+    #[test]
+    fn should_reject_transactions_above_limits() {
+        assert!(matches!(
+            transaction_with(5, 4).validate_structure(4, 4),
+            Err(BlockchainError::TooManyInputs(5, 4))
+        ));
+        assert!(matches!(
+            transaction_with(4, 5).validate_structure(4, 4),
+            Err(BlockchainError::TooManyOutputs(5, 4))
+        ));
+    }
+
+    #[test]
+    fn should_not_limit_coinbase_outputs() {
+        assert!(transaction_with(0, 100).validate_structure(4, 4).is_ok());
+    }
+
     #[test]
     fn should_build_a_transaction() {
         let transaction = TransactionBuilder::new(TransactionVersion::V1)
@@ -141,6 +313,48 @@ mod test {
         assert_eq!(transaction.outputs.len(), 2);
     }
 
+    #[test]
+    fn should_enumerate_inputs_and_outputs() {
+        let transaction = TransactionBuilder::new(TransactionVersion::V1)
+            .input("alice", dec!(10.52))
+            .output("bob", dec!(10.50))
+            .output("miner", dec!(0.02))
+            .finish("aaa");
+        assert_eq!(
+            transaction.inputs(),
+            &[UnlockInput::new("alice", dec!(10.52))]
+        );
+        let outputs: Vec<(&str, Decimal)> = transaction
+            .outputs()
+            .iter()
+            .map(|x| (x.address.as_str(), x.amount))
+            .collect();
+        assert_eq!(outputs, vec![("bob", dec!(10.50)), ("miner", dec!(0.02))]);
+    }
+
+    #[test]
+    fn should_get_output_address_with_single_output() {
+        let transaction = TransactionBuilder::new(TransactionVersion::V1)
+            .input("alice", dec!(10.50))
+            .output("bob", dec!(10.50))
+            .finish("aaa");
+        assert_eq!(transaction.output_address(), Some("bob"));
+    }
+
+    #[test]
+    fn should_get_primary_output_address_with_multiple_outputs() {
+        let transaction = TransactionBuilder::new(TransactionVersion::V1)
+            .input("alice", dec!(10.52))
+            .output("bob", dec!(10.50))
+            .output("miner", dec!(0.02))
+            .finish("aaa");
+        assert_eq!(transaction.output_address(), Some("bob"));
+        let transaction = TransactionBuilder::new(TransactionVersion::V1)
+            .input("alice", dec!(10.52))
+            .finish("aaa");
+        assert!(transaction.output_address().is_none());
+    }
+
     #[test]
     fn should_correctly_calculate_input_amount() {
         let transaction = TransactionBuilder::new(TransactionVersion::V1)
@@ -166,4 +380,138 @@ mod test {
         assert_eq!(transaction.amount_received("bob"), dec!(10.50));
         assert_eq!(transaction.amount_received("miner"), dec!(0.02));
     }
+
+    #[test]
+    fn should_calculate_transaction_totals() {
+        let transaction = TransactionBuilder::new(TransactionVersion::V1)
+            .input("alice", dec!(6.0))
+            .input("carol", dec!(4.52))
+            .input("alice", dec!(1.0))
+            .output("bob", dec!(10.50))
+            .output("miner", dec!(0.02))
+            .output("alice", dec!(1.0))
+            .finish("aaa");
+        assert_eq!(
+            transaction.total_input(),
+            dec!(6.0) + dec!(4.52) + dec!(1.0)
+        );
+        assert_eq!(
+            transaction.total_output(),
+            dec!(10.50) + dec!(0.02) + dec!(1.0)
+        );
+        let coinbase = TransactionBuilder::new(TransactionVersion::V1)
+            .output("alice", dec!(50.0))
+            .finish("aaa");
+        assert_eq!(coinbase.total_input(), Decimal::ZERO);
+        assert_eq!(coinbase.total_output(), dec!(50.0));
+    }
+
+    #[test]
+    fn should_not_cover_fee_output_in_digest() {
+        let signed =
+            TransactionBuilder::transfer(TransactionVersion::V1, "alice", "bob", dec!(10.0))
+                .finish("aaa");
+        let with_fee =
+            TransactionBuilder::transfer(TransactionVersion::V1, "alice", "bob", dec!(10.0))
+                .output("miner", dec!(0.5))
+                .finish("aaa");
+        assert_eq!(signed.digest(), with_fee.digest());
+        let tampered =
+            TransactionBuilder::transfer(TransactionVersion::V1, "alice", "bob", dec!(11.0))
+                .finish("aaa");
+        assert_ne!(signed.digest(), tampered.digest());
+    }
+
+    #[test]
+    fn should_get_transaction_id() {
+        let transaction =
+            TransactionBuilder::transfer(TransactionVersion::V1, "alice", "bob", dec!(10.0))
+                .output("miner", dec!(0.5))
+                .finish("aaa");
+        assert_eq!(transaction.id().len(), 64);
+        assert_eq!(transaction.id(), transaction.clone().id());
+        // fee output and signature are covered by the id
+        let other_fee =
+            TransactionBuilder::transfer(TransactionVersion::V1, "alice", "bob", dec!(10.0))
+                .output("miner", dec!(0.6))
+                .finish("aaa");
+        assert_ne!(transaction.id(), other_fee.id());
+        let other_signature =
+            TransactionBuilder::transfer(TransactionVersion::V1, "alice", "bob", dec!(10.0))
+                .output("miner", dec!(0.5))
+                .finish("bbb");
+        assert_ne!(transaction.id(), other_signature.id());
+    }
+
+    #[test]
+    fn should_distinguish_repeated_payments_by_nonce() {
+        let wallet = Wallet::new();
+        let pay = |nonce| {
+            TransactionBuilder::transfer(
+                TransactionVersion::V1,
+                wallet.address(),
+                "bob",
+                dec!(10.0),
+            )
+            .nonce(nonce)
+            .sign_with_wallet(&wallet)
+            .unwrap()
+        };
+        let (first, again) = (pay(1), pay(2));
+        assert_ne!(first.digest(), again.digest());
+        assert_ne!(first.signed_id(), again.signed_id());
+        assert_ne!(first.id(), again.id());
+        assert!(again.verify_signature(&wallet.public_key()).unwrap());
+        // the nonce is covered by the signature
+        let tampered = TransactionBuilder::from_existing(&first)
+            .nonce(2)
+            .finish(first.signature());
+        assert!(!tampered.verify_signature(&wallet.public_key()).unwrap());
+        // transactions without nonce keep their serialization
+        let json = serde_json::to_string(&pay(0)).unwrap();
+        assert!(!json.contains("nonce"));
+        assert_eq!(serde_json::from_str::<Transaction>(&json).unwrap(), pay(0));
+        let json = serde_json::to_string(&first).unwrap();
+        assert_eq!(serde_json::from_str::<Transaction>(&json).unwrap(), first);
+    }
+
+    #[test]
+    fn should_tell_coinbase_from_transfer() {
+        let coinbase = TransactionBuilder::new(TransactionVersion::V1)
+            .output("alice", dec!(50.0))
+            .finish("aaa");
+        assert_eq!(coinbase.is_coinbase(), true);
+        let transfer =
+            TransactionBuilder::transfer(TransactionVersion::V1, "alice", "bob", dec!(10.0))
+                .finish("aaa");
+        assert_eq!(transfer.is_coinbase(), false);
+    }
+
+    #[test]
+    fn should_verify_transaction_signature() {
+        let wallet = Wallet::new();
+        let transaction = TransactionBuilder::transfer(
+            TransactionVersion::V1,
+            wallet.address(),
+            "bob",
+            dec!(10.0),
+        )
+        .sign_with_wallet(&wallet)
+        .unwrap();
+        assert!(transaction.verify_signature(&wallet.public_key()).unwrap());
+        // signed by someone else
+        assert!(!transaction
+            .verify_signature(&Wallet::new().public_key())
+            .unwrap());
+        // tampered amount
+        let tampered = TransactionBuilder::transfer(
+            TransactionVersion::V1,
+            wallet.address(),
+            "bob",
+            dec!(1000.0),
+        )
+        .finish(transaction.signature());
+        assert!(!tampered.verify_signature(&wallet.public_key()).unwrap());
+        assert!(transaction.verify_signature("not-a-public-key").is_err());
+    }
 }
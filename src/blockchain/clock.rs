@@ -0,0 +1,75 @@
+//! # Clock
+//!
+//! This module exposes the clock used by the blockchain to timestamp blocks
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// A source of time for the blockchain
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Get current time
+    fn now(&self) -> SystemTime;
+}
+
+/// Clock which returns the system time
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock which returns a controlled time. Used to make timestamps deterministic in tests.
+/// Clones share the same time, so a clone can be used to control the clock once it has been given to the chain
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<SystemTime>>,
+}
+
+impl MockClock {
+    /// Instantiate a new `MockClock` starting at `now`
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    /// Set current time
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    /// Move clock forward by `duration`
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn should_control_mock_clock() {
+        let clock = MockClock::new(UNIX_EPOCH);
+        let handle = clock.clone();
+        assert_eq!(clock.now(), UNIX_EPOCH);
+        handle.advance(Duration::from_secs(30));
+        assert_eq!(clock.now(), UNIX_EPOCH + Duration::from_secs(30));
+        handle.set(UNIX_EPOCH + Duration::from_secs(1000));
+        assert_eq!(clock.now(), UNIX_EPOCH + Duration::from_secs(1000));
+    }
+}
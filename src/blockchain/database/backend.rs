@@ -0,0 +1,93 @@
+//! # Backend
+//!
+//! Storage backends for the blockchain database
+
+use crate::blockchain::{BlockchainError, BlockchainResult};
+use crate::bridge::leveldb::{Key, LevelDbBridge};
+use crate::bridge::memory::MemoryBridge;
+
+use std::path::Path;
+
+/// Storage backend for the blockchain database
+pub enum Backend<K: Key> {
+    LevelDb(LevelDbBridge<K>),
+    Memory(MemoryBridge<K>),
+}
+
+impl<K: Key> Clone for Backend<K> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::LevelDb(db) => Self::LevelDb(db.clone()),
+            Self::Memory(db) => Self::Memory(db.clone()),
+        }
+    }
+}
+
+impl<K: Key> Backend<K> {
+    /// Open a leveldb backend at `path`
+    pub fn leveldb(path: &Path) -> BlockchainResult<Self> {
+        Ok(Self::LevelDb(LevelDbBridge::init(path)?))
+    }
+
+    /// Create an empty in-memory backend
+    pub fn memory() -> Self {
+        Self::Memory(MemoryBridge::default())
+    }
+
+    /// Put key and value into the backend
+    pub fn put(&self, key: K, value: &[u8]) -> BlockchainResult<()> {
+        match self {
+            Self::LevelDb(db) => db.put(key, value).map_err(BlockchainError::from),
+            Self::Memory(db) => {
+                db.put(key, value);
+                Ok(())
+            }
+        }
+    }
+
+    /// Get `key` from backend
+    pub fn get(&self, key: K) -> BlockchainResult<Option<Vec<u8>>> {
+        match self {
+            Self::LevelDb(db) => db.get(key).map_err(BlockchainError::from),
+            Self::Memory(db) => db.get(key).map_err(BlockchainError::from),
+        }
+    }
+
+    #[cfg(test)]
+    /// Make the next `n` reads fail, to simulate a transient database error. Supported only by the memory backend
+    pub fn fail_next_reads(&self, n: usize) {
+        if let Self::Memory(db) = self {
+            db.fail_next_reads(n);
+        }
+    }
+
+    /// Delete `key` from backend
+    pub fn delete(&self, key: K) -> BlockchainResult<()> {
+        match self {
+            Self::LevelDb(db) => db.delete(key).map_err(BlockchainError::from),
+            Self::Memory(db) => {
+                db.delete(key);
+                Ok(())
+            }
+        }
+    }
+
+    /// Delete all the keys from backend
+    pub fn clear(&self) -> BlockchainResult<()> {
+        match self {
+            Self::LevelDb(db) => db.clear().map_err(BlockchainError::from),
+            Self::Memory(db) => {
+                db.clear();
+                Ok(())
+            }
+        }
+    }
+
+    /// Force pending writes to disk
+    pub fn flush(&self) -> BlockchainResult<()> {
+        match self {
+            Self::LevelDb(db) => db.flush().map_err(BlockchainError::from),
+            Self::Memory(_) => Ok(()),
+        }
+    }
+}
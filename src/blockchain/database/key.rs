@@ -44,6 +44,25 @@ impl Key for BlockKey {
     }
 }
 
+/// The key used for the transaction index (the transaction id)
+pub struct TransactionKey(Vec<u8>);
+
+impl From<&str> for TransactionKey {
+    fn from(txid: &str) -> Self {
+        Self(txid.as_bytes().to_vec())
+    }
+}
+
+impl Key for TransactionKey {
+    fn from_u8(key: &[u8]) -> Self {
+        Self(key.to_vec())
+    }
+
+    fn as_slice<T, F: Fn(&[u8]) -> T>(&self, f: F) -> T {
+        f(&self.0)
+    }
+}
+
 #[cfg(test)]
 mod test {
 
@@ -2,37 +2,78 @@
 //!
 //! Database to store the blocks of our blockchain
 
+mod backend;
 mod key;
 
-use super::{Block, BlockchainError, BlockchainResult};
-use crate::bridge::leveldb::LevelDbBridge;
-use key::BlockKey;
+use super::{Block, BlockchainError, BlockchainResult, Header};
+use backend::Backend;
+use key::{BlockKey, TransactionKey};
 
 use std::path::Path;
 
+/// Name of the directory, inside the blockchain database, where the transaction index is stored
+const TRANSACTION_INDEX_DIR: &str = "txindex";
+/// Name of the directory, inside the blockchain database, where the block headers are stored
+const HEADER_INDEX_DIR: &str = "headers";
+
 /// Blockchain database client
+#[derive(Clone)]
 pub struct BlockchainDatabase {
-    database: LevelDbBridge<BlockKey>,
+    database: Backend<BlockKey>,
+    /// Maps transaction ids to the index of the block containing them
+    transactions: Backend<TransactionKey>,
+    /// Maps block indexes to the block headers, so headers can be read without deserializing the transactions
+    headers: Backend<BlockKey>,
 }
 
 impl TryFrom<&Path> for BlockchainDatabase {
     type Error = BlockchainError;
     fn try_from(path: &Path) -> Result<Self, Self::Error> {
         debug!("initializing blockchain database");
+        // leveldb creates the directory if missing, but fails with an opaque error if the path is a file
+        if path.exists() && !path.is_dir() {
+            return Err(BlockchainError::InvalidDatabasePath(path.to_path_buf()));
+        }
         Ok(Self {
-            database: LevelDbBridge::init(path)?,
+            database: Backend::leveldb(path)?,
+            transactions: Backend::leveldb(&path.join(TRANSACTION_INDEX_DIR))?,
+            headers: Backend::leveldb(&path.join(HEADER_INDEX_DIR))?,
         })
     }
 }
 
 impl BlockchainDatabase {
+    /// Initialize an empty blockchain database, which is kept in memory and lost once dropped
+    pub fn in_memory() -> Self {
+        debug!("initializing in-memory blockchain database");
+        Self {
+            database: Backend::memory(),
+            transactions: Backend::memory(),
+            headers: Backend::memory(),
+        }
+    }
+
     /// Put block into the database
     pub fn put_block(&self, block: &Block) -> BlockchainResult<()> {
         let payload = serde_json::json!(block).to_string();
         info!("inserting block {} ({})", block.index(), payload);
         self.database
-            .put(block.index().into(), payload.as_bytes())
-            .map_err(BlockchainError::from)
+            .put(block.index().into(), payload.as_bytes())?;
+        self.put_header(block.index(), block.header())
+    }
+
+    /// Put the header of block with `index` into the header index
+    fn put_header(&self, index: u64, header: &Header) -> BlockchainResult<()> {
+        let payload = serde_json::json!(header).to_string();
+        self.headers.put(index.into(), payload.as_bytes())
+    }
+
+    /// Force pending writes to disk
+    pub fn flush(&self) -> BlockchainResult<()> {
+        debug!("flushing blockchain database");
+        self.database.flush()?;
+        self.transactions.flush()?;
+        self.headers.flush()
     }
 
     /// Get block from database with provided index
@@ -51,6 +92,78 @@ impl BlockchainDatabase {
                 BlockchainError::from(e)
             })
     }
+
+    #[cfg(test)]
+    /// Make the next `n` block reads fail, to simulate a transient database error. Supported only in memory
+    pub fn fail_next_block_reads(&self, n: usize) {
+        self.database.fail_next_reads(n);
+    }
+
+    /// Get the header of block with provided index, without deserializing the block transactions.
+    /// Headers missing from the index (e.g. blocks stored before the index was introduced) are read from the block
+    /// and indexed
+    pub fn get_header(&self, index: u64) -> BlockchainResult<Option<Header>> {
+        if let Some(payload) = self.headers.get(index.into())? {
+            match serde_json::from_slice(&payload) {
+                Ok(header) => return Ok(Some(header)),
+                Err(_) => error!(
+                    "header of block {} has a bad payload; reindexing it from the block",
+                    index
+                ),
+            }
+        }
+        match self.get_block(index)? {
+            None => Ok(None),
+            Some(block) => {
+                self.put_header(index, block.header())?;
+                Ok(Some(block.header().clone()))
+            }
+        }
+    }
+
+    /// Drop all the entries of the header index. Headers are indexed again from the blocks once read
+    pub fn clear_header_index(&self) -> BlockchainResult<()> {
+        debug!("clearing header index");
+        self.headers.clear()
+    }
+
+    /// Index transaction with id `txid` as contained in block with index `block_index`
+    pub fn put_transaction_block(&self, txid: &str, block_index: u64) -> BlockchainResult<()> {
+        debug!("indexing transaction {} in block {}", txid, block_index);
+        self.transactions
+            .put(txid.into(), &block_index.to_be_bytes())
+    }
+
+    /// Remove transaction with id `txid` from the transaction index
+    pub fn delete_transaction_block(&self, txid: &str) -> BlockchainResult<()> {
+        debug!("removing transaction {} from index", txid);
+        self.transactions.delete(txid.into())
+    }
+
+    /// Drop all the entries of the transaction index
+    pub fn clear_transaction_index(&self) -> BlockchainResult<()> {
+        debug!("clearing transaction index");
+        self.transactions.clear()
+    }
+
+    /// Get the index of the block containing transaction with id `txid`
+    pub fn get_transaction_block(&self, txid: &str) -> BlockchainResult<Option<u64>> {
+        debug!("getting block for transaction {}", txid);
+        match self.transactions.get(txid.into())? {
+            None => Ok(None),
+            Some(payload) => match <[u8; 8]>::try_from(payload.as_slice()) {
+                Ok(index) => Ok(Some(u64::from_be_bytes(index))),
+                Err(_) => {
+                    error!(
+                        "transaction {} has a bad index; deleting it from database",
+                        txid
+                    );
+                    let _ = self.transactions.delete(txid.into());
+                    Ok(None)
+                }
+            },
+        }
+    }
 }
 
 #[cfg(test)]
@@ -92,4 +205,38 @@ mod test {
         // get unexisting block
         assert!(database.get_block(1).unwrap().is_none());
     }
+
+    #[test]
+    fn should_put_blocks_in_memory() {
+        let database = BlockchainDatabase::in_memory();
+        let block = Block::new(
+            0,
+            Header::new(
+                Version::V010,
+                None,
+                String::from("cafebabe"),
+                SystemTime::now(),
+            ),
+            TransactionBuilder::new(crate::blockchain::TransactionVersion::V1)
+                .input("alice", dec!(10.0))
+                .output("bob", dec!(10.0))
+                .finish("aaa"),
+        );
+        assert!(database.put_block(&block).is_ok());
+        assert_eq!(database.clone().get_block(0).unwrap().unwrap(), block);
+        assert!(database.get_block(1).unwrap().is_none());
+        assert!(database.flush().is_ok());
+    }
+
+    #[test]
+    fn should_index_transactions() {
+        let tempdir = TempDir::new().expect("could not create tempfile");
+        let database = BlockchainDatabase::try_from(tempdir.path()).unwrap();
+        assert!(database.put_transaction_block("cafebabe", 3).is_ok());
+        assert_eq!(database.get_transaction_block("cafebabe").unwrap(), Some(3));
+        assert!(database
+            .get_transaction_block("deadbeef")
+            .unwrap()
+            .is_none());
+    }
 }
@@ -0,0 +1,70 @@
+//! # Diff
+//!
+//! This module exposes how the local chain diverges from the chain of a peer
+
+/// Outcome of the comparison between the local chain and the chain of a peer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainComparison {
+    /// The chains share at least the genesis block
+    Shared(ChainDiff),
+    /// The chains don't even share the genesis block
+    Unrelated,
+    /// The peer doesn't know the id of the block at this index, so the chains can't be compared
+    Unknown(u64),
+}
+
+/// Comparison between the local chain and the chain of a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainDiff {
+    /// Index of the last block shared by both chains
+    pub common_height: u64,
+    /// Index of the local chain tip
+    pub local_height: u64,
+    /// Index of the peer chain tip
+    pub remote_height: u64,
+}
+
+impl ChainDiff {
+    /// Instantiate a new `ChainDiff`
+    pub fn new(common_height: u64, local_height: u64, remote_height: u64) -> Self {
+        Self {
+            common_height,
+            local_height,
+            remote_height,
+        }
+    }
+
+    /// Returns whether the chains have diverged, i.e. both have blocks after the common one
+    pub fn is_fork(&self) -> bool {
+        self.common_height < self.local_height && self.common_height < self.remote_height
+    }
+
+    /// Get the range of the peer blocks after the common one, to request from the peer.
+    /// Returns `None` if the peer has no block we lack
+    pub fn missing_blocks(&self) -> Option<(u64, u64)> {
+        if self.remote_height > self.common_height {
+            Some((self.common_height + 1, self.remote_height))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_tell_missing_blocks() {
+        let behind = ChainDiff::new(4, 4, 10);
+        assert!(!behind.is_fork());
+        assert_eq!(behind.missing_blocks(), Some((5, 10)));
+        let fork = ChainDiff::new(4, 6, 8);
+        assert!(fork.is_fork());
+        assert_eq!(fork.missing_blocks(), Some((5, 8)));
+        assert_eq!(ChainDiff::new(8, 10, 8).missing_blocks(), None);
+    }
+}
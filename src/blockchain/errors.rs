@@ -4,6 +4,7 @@
 
 use crate::bridge::leveldb::LevelDbError;
 
+use std::path::PathBuf;
 use thiserror::Error;
 
 /// Blockchain result type
@@ -13,6 +14,39 @@ pub type BlockchainResult<T> = Result<T, BlockchainError>;
 pub enum BlockchainError {
     #[error("the block is invalid")]
     InvalidBlock,
+    #[error("the genesis block can't be replaced")]
+    GenesisImmutable,
+    #[error("block #{0} is ahead of the chain tip (#{1})")]
+    OrphanBlock(u64, u64),
+    #[error("block #{0} could not be found")]
+    BlockNotFound(u64),
+    #[error("block #{0} has been created too far in the future")]
+    FutureBlock(u64),
+    #[error("block header has no merkle root or previous block hash")]
+    BadMerkleRoot,
+    #[error("block header hash doesn't meet difficulty {0}")]
+    InsufficientWork(u32),
+    #[error("malformed transaction: {0}")]
+    MalformedTransaction(&'static str),
+    #[error("transaction has {0} inputs, but at most {1} are allowed")]
+    TooManyInputs(usize, usize),
+    #[error("transaction has {0} outputs, but at most {1} are allowed")]
+    TooManyOutputs(usize, usize),
+    #[error("transaction {0} is included more than once")]
+    DuplicateTransaction(String),
+    #[error("transaction is not signed")]
+    MissingSignature,
+    #[error("difficulty {0} doesn't match the required difficulty")]
+    DifficultyNotMet(u32),
+    #[error("insufficient funds: {available} available, but {required} required")]
+    InsufficientFunds {
+        available: rust_decimal::Decimal,
+        required: rust_decimal::Decimal,
+    },
+    #[error("scan exceeded the limit of {0} blocks")]
+    ScanLimitExceeded(u64),
+    #[error("database path {} is not a directory", .0.display())]
+    InvalidDatabasePath(PathBuf),
     #[error("database error: {0}")]
     Database(LevelDbError),
     #[error("block in database has a bad value: {0}")]
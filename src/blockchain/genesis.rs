@@ -0,0 +1,105 @@
+//! # Genesis
+//!
+//! This module exposes the configuration for the genesis block
+
+use super::MIN_DIFFICULTY;
+
+use rust_decimal::Decimal;
+use std::str::FromStr;
+
+/// Genesis block configuration.
+/// Allocations are credited in the genesis transaction along with the genesis wallet funds,
+/// so that a network can be launched with pre-funded wallets.
+/// The initial difficulty is stored in the genesis header and applies to the blocks before the first retarget
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenesisConfig {
+    allocations: Vec<(String, Decimal)>,
+    initial_difficulty: u32,
+}
+
+impl Default for GenesisConfig {
+    fn default() -> Self {
+        Self {
+            allocations: Vec::new(),
+            initial_difficulty: MIN_DIFFICULTY,
+        }
+    }
+}
+
+impl GenesisConfig {
+    /// Add an initial allocation of `amount` for `address`
+    pub fn allocation(mut self, address: impl ToString, amount: Decimal) -> Self {
+        self.allocations.push((address.to_string(), amount));
+        self
+    }
+
+    /// Set the difficulty of the genesis block and of the blocks mined before the first retarget.
+    /// The genesis merkle root commits to it, so chains with different initial difficulties don't share any block
+    pub fn initial_difficulty(mut self, difficulty: u32) -> Self {
+        self.initial_difficulty = difficulty;
+        self
+    }
+
+    /// Get initial allocations
+    pub fn allocations(&self) -> &[(String, Decimal)] {
+        &self.allocations
+    }
+
+    /// Get the difficulty of the genesis block and of the blocks mined before the first retarget
+    pub fn difficulty(&self) -> u32 {
+        self.initial_difficulty
+    }
+}
+
+impl FromStr for GenesisConfig {
+    type Err = String;
+
+    /// Parse allocations from a comma-separated list of `address:amount`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = Self::default();
+        for allocation in s.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            let (address, amount) = allocation.split_once(':').ok_or_else(|| {
+                format!("bad allocation `{}`: expected `address:amount`", allocation)
+            })?;
+            let amount = Decimal::from_str(amount.trim())
+                .map_err(|e| format!("bad amount for `{}`: {}", address, e))?;
+            if amount <= Decimal::ZERO {
+                return Err(format!("amount for `{}` must be positive", address));
+            }
+            config = config.allocation(address.trim(), amount);
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn should_parse_genesis_config() {
+        let config = GenesisConfig::from_str("alice:100.5, bob:20").unwrap();
+        assert_eq!(
+            config.allocations(),
+            &[
+                (String::from("alice"), dec!(100.5)),
+                (String::from("bob"), dec!(20))
+            ]
+        );
+        assert!(GenesisConfig::from_str("")
+            .unwrap()
+            .allocations()
+            .is_empty());
+    }
+
+    #[test]
+    fn should_not_parse_bad_genesis_config() {
+        assert!(GenesisConfig::from_str("alice").is_err());
+        assert!(GenesisConfig::from_str("alice:foo").is_err());
+        assert!(GenesisConfig::from_str("alice:-5").is_err());
+    }
+}
@@ -0,0 +1,30 @@
+//! # Ledger
+//!
+//! This module exposes the entries of a wallet ledger
+
+use rust_decimal::Decimal;
+
+/// A transaction affecting a wallet, with the wallet balance after it has been applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedgerEntry {
+    /// Index of the block containing the transaction
+    pub index: u64,
+    /// Transaction id
+    pub txid: String,
+    /// Amount received (positive) or spent (negative) by the wallet in the transaction
+    pub delta: Decimal,
+    /// Wallet balance after the transaction
+    pub balance: Decimal,
+}
+
+impl LedgerEntry {
+    /// Instantiate a new `LedgerEntry`
+    pub fn new(index: u64, txid: impl ToString, delta: Decimal, balance: Decimal) -> Self {
+        Self {
+            index,
+            txid: txid.to_string(),
+            delta,
+            balance,
+        }
+    }
+}
@@ -5,7 +5,7 @@
 use super::Transaction;
 
 use merkle::MerkleTree;
-use ring::digest::{Algorithm, SHA256};
+use ring::digest::{digest, Algorithm, SHA256};
 
 static DIGEST_ALGO: &Algorithm = &SHA256;
 
@@ -25,4 +25,11 @@ impl JabMerkleTree {
     pub fn root_hash(&self) -> String {
         hex::encode(self.tree.root_hash())
     }
+
+    /// Get the hash of the root hash followed by `context`, to commit the root to data outside of the tree
+    pub fn committed_root_hash(&self, context: &[u8]) -> String {
+        let mut data = self.tree.root_hash().to_vec();
+        data.extend_from_slice(context);
+        hex::encode(digest(DIGEST_ALGO, &data))
+    }
 }
@@ -4,64 +4,484 @@
 
 // -- modules
 mod block;
+mod clock;
 mod database;
+mod diff;
 mod errors;
+mod genesis;
+mod ledger;
 mod merkle;
+mod reader;
 
 use self::merkle::JabMerkleTree;
-pub use block::{Block, Header, Transaction, TransactionBuilder, TransactionVersion, Version};
-use database::BlockchainDatabase;
+pub use block::{
+    Block, BlockById, Header, LockOutput, Transaction, TransactionBuilder, TransactionVersion,
+    UnlockInput, Version, MIN_DIFFICULTY,
+};
+pub use clock::{Clock, MockClock, SystemClock};
+pub use database::BlockchainDatabase;
+pub use diff::{ChainComparison, ChainDiff};
 pub use errors::{BlockchainError, BlockchainResult};
+pub use genesis::GenesisConfig;
+pub use ledger::LedgerEntry;
+pub use reader::ChainReader;
 
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
 
 const GENESIS_BLOCK_ADDRESS: &str = "jabbe2cce18177f64c3eb2cc51f0bd640dec8b22668";
 const GENESIS_BLOCK_SIGNATURE: &str = "3045022100a6a9106ecbef322e967438dbc8f1bf0ea8f5ee75cd3519f55e2bb90693d67ee3022042ecad494ead5fd441814201e8ae915a934c29644984cfc3624e48290054a155";
+/// Target time between two blocks. Difficulty is a consensus rule, so all the nodes must use the same value
+pub const DEFAULT_BLOCK_TIME_TARGET: Duration = Duration::from_secs(60);
+/// Amount of blocks used to retarget the difficulty. All the nodes must use the same value
+pub const DEFAULT_RETARGET_WINDOW: u64 = 10;
+/// Default maximum time a block may be ahead of the local clock
+pub const DEFAULT_FUTURE_BLOCK_TOLERANCE: Duration = Duration::from_secs(120);
+/// Maximum amount of inputs of a transaction. All the nodes must use the same value
+pub const MAX_INPUTS: usize = 64;
+/// Maximum amount of outputs of a non-coinbase transaction. All the nodes must use the same value
+pub const MAX_OUTPUTS: usize = 64;
+/// Amount of times a block read failed with a database error is retried while scanning the chain
+const SCAN_READ_RETRIES: u32 = 3;
+/// Delay before retrying a failed block read on reader threads, multiplied by the attempt
+const SCAN_RETRY_BACKOFF: Duration = Duration::from_millis(10);
 
-/// The main blockchain struct, contains the entire blockchain and the methods to interact with it
+/// The main blockchain struct, contains the entire blockchain and the methods to interact with it.
+///
+/// ## Concurrency model
+///
+/// Cloning a `Chain` returns a new handle to the same database, so there's no need to wrap it into a lock to share it.
+/// LevelDB is safe to be accessed from multiple threads and each block is written atomically,
+/// so readers (e.g. wallet queries served on worker threads) never block each other nor the writer:
+/// they either see a block or they don't.
+/// Blocks must be written by a single owner (the application main loop), since `add_block` checks the tip before writing it.
+#[derive(Clone)]
 pub struct Chain {
     /// the database which stores the blockchain
     blockchain: BlockchainDatabase,
+    /// target time between two blocks
+    block_time_target: Duration,
+    /// amount of blocks used to retarget the difficulty
+    retarget_window: u64,
+    /// maximum time a block may be ahead of the clock
+    future_block_tolerance: Duration,
+    /// maximum amount of blocks scanned by wallet queries; if unset, the whole chain is scanned
+    max_scan_blocks: Option<u64>,
+    /// address of the wallet funded by the genesis transaction
+    genesis_address: String,
+    /// difficulty of the genesis block, required to the blocks before the first retarget
+    initial_difficulty: u32,
+    /// clock used to timestamp new blocks
+    clock: Arc<dyn Clock>,
+    /// delay before retrying a failed block read; set only on reader handles, which run on blocking workers
+    scan_retry_backoff: Option<Duration>,
 }
 
 impl TryFrom<&Path> for Chain {
     type Error = BlockchainError;
     fn try_from(path: &Path) -> Result<Self, Self::Error> {
+        Self::open(path, &GenesisConfig::default())
+    }
+}
+
+impl Chain {
+    /// Open the blockchain database at `path`.
+    /// If the database doesn't contain the genesis block yet, it is generated using the provided `genesis` configuration
+    pub fn open(path: &Path, genesis: &GenesisConfig) -> BlockchainResult<Self> {
         // setup database
         let database = BlockchainDatabase::try_from(path)?;
         debug!("leveldb successfully initialized");
+        Self::init(database, genesis)
+    }
+
+    /// Instantiate a new `Chain` on top of the provided database.
+    /// If the database doesn't contain the genesis block yet, the default genesis block is generated
+    pub fn from_database(database: BlockchainDatabase) -> BlockchainResult<Self> {
+        Self::init(database, &GenesisConfig::default())
+    }
+
+    /// Initialize chain on `database`, generating the genesis block with the `genesis` configuration if missing
+    fn init(database: BlockchainDatabase, genesis: &GenesisConfig) -> BlockchainResult<Self> {
         // initialize database if genesis block doesn't exist
         if database.get_block(0)?.is_none() {
             debug!("database doesn't contain the genesis block yet; generating genesis block...");
-            database.put_block(&Self::genesis_block())?;
+            let genesis_block = Self::genesis_block(genesis);
+            database.put_block(&genesis_block)?;
+            Self::index_transaction(&database, genesis_block.transaction(), 0)?;
             debug!("generated genesis block");
         }
-        Ok(Self {
+        // the genesis wallet is the first output of the genesis transaction
+        let genesis_block = database
+            .get_block(0)?
+            .ok_or(BlockchainError::InvalidBlock)?;
+        let genesis_address = genesis_block
+            .transaction()
+            .output_address()
+            .map(str::to_string)
+            .ok_or(BlockchainError::InvalidBlock)?;
+        let chain = Self {
             blockchain: database,
-        })
+            block_time_target: DEFAULT_BLOCK_TIME_TARGET,
+            retarget_window: DEFAULT_RETARGET_WINDOW,
+            future_block_tolerance: DEFAULT_FUTURE_BLOCK_TOLERANCE,
+            max_scan_blocks: None,
+            genesis_address,
+            initial_difficulty: genesis_block.header().difficulty(),
+            clock: Arc::new(SystemClock),
+            scan_retry_backoff: None,
+        };
+        // rebuild transaction index if missing (e.g. database created before the index was introduced)
+        let latest_block = chain.get_latest_block()?;
+        if chain
+            .transaction_block(&latest_block.transaction().id())?
+            .is_none()
+        {
+            chain.rebuild_transaction_index()?;
+        }
+        Ok(chain)
+    }
+
+    /// Set the clock used to timestamp new blocks
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    #[cfg(test)]
+    /// Set the target time between two blocks, used to retarget the difficulty
+    pub fn block_time_target(mut self, block_time_target: Duration) -> Self {
+        self.block_time_target = block_time_target;
+        self
+    }
+
+    #[cfg(test)]
+    /// Set the amount of latest blocks used to retarget the difficulty
+    pub fn retarget_window(mut self, retarget_window: u64) -> Self {
+        self.retarget_window = retarget_window;
+        self
+    }
+
+    /// Set the maximum time a block may be ahead of the clock; blocks created later are rejected with `FutureBlock`
+    pub fn future_block_tolerance(mut self, tolerance: Duration) -> Self {
+        self.future_block_tolerance = tolerance;
+        self
+    }
+
+    /// Set the maximum amount of blocks scanned by the wallet queries served to peers through `ChainReader` and by
+    /// `audit_wallet`; longer scans fail with `ScanLimitExceeded` instead of blocking the caller for an unbounded time.
+    /// Block validation and transaction acceptance always scan the whole chain
+    pub fn max_scan_blocks(mut self, max_scan_blocks: u64) -> Self {
+        self.max_scan_blocks = Some(max_scan_blocks);
+        self
+    }
+
+    /// Get a read-only handle to the chain, which can query it while this `Chain` is writing blocks
+    pub fn reader(&self) -> ChainReader {
+        let mut chain = self.clone();
+        chain.scan_retry_backoff = Some(SCAN_RETRY_BACKOFF);
+        ChainReader::from(chain)
+    }
+
+    /// Get the address of the wallet funded by the genesis transaction
+    pub fn genesis_address(&self) -> &str {
+        &self.genesis_address
     }
-}
 
-impl Chain {
     /// Get genesis block (first block in the blockchain)
     pub fn get_genesis_block(&self) -> BlockchainResult<Block> {
         self.blockchain.get_block(0).map(|x| x.unwrap())
     }
 
-    /// Push new block to the end of the blockchain
+    /// Push new block to the end of the blockchain.
+    /// The block must be the strict successor of the current tip, so gaps can't be created.
+    /// Blocks ahead of the tip are rejected with `OrphanBlock`, while blocks at index 0 are rejected with `GenesisImmutable`.
+    /// Blocks created beyond the future block tolerance, according to the chain clock, are rejected with `FutureBlock`,
+    /// while blocks not mined with the difficulty computed from the chain (see `next_difficulty`) with `DifficultyNotMet`.
+    /// Context-free checks are run first through `validate_block_standalone`, while the block transactions
+    /// must be covered by the balances at the tip (see `validate_transactions`)
     pub fn add_block(&mut self, b: Block) -> BlockchainResult<()> {
-        let previous_block = self.get_latest_block()?;
-        if previous_block.index() < b.index()
-            && b.header().previous_block_header_hash()
-                == Some(previous_block.header().merkle_root_hash())
+        if b.index() == 0 {
+            return Err(BlockchainError::GenesisImmutable);
+        }
+        self.validate_block_standalone(&b)?;
+        if b.header().created_at() > self.clock.now() + self.future_block_tolerance {
+            return Err(BlockchainError::FutureBlock(b.index()));
+        }
+        let (previous_index, previous_header) = self.tip_header()?;
+        if b.index() > previous_index + 1 {
+            return Err(BlockchainError::OrphanBlock(b.index(), previous_index));
+        }
+        if b.index() != previous_index + 1
+            || b.header().previous_block_header_hash() != Some(previous_header.merkle_root_hash())
         {
-            self.blockchain.put_block(&b)
-        } else {
-            Err(BlockchainError::InvalidBlock)
+            return Err(BlockchainError::InvalidBlock);
+        }
+        if b.header().difficulty() != self.difficulty_at(b.index())? {
+            return Err(BlockchainError::DifficultyNotMet(b.header().difficulty()));
+        }
+        self.validate_transactions(std::slice::from_ref(b.transaction()))?;
+        self.blockchain.put_block(&b)?;
+        Self::index_transaction(&self.blockchain, b.transaction(), b.index())
+    }
+
+    /// Push a batch of blocks (e.g. received during sync) to the end of the blockchain, in order.
+    /// The whole batch is checked with `validate_sequence` before any block is committed, so a batch including the same
+    /// transaction twice is rejected as a whole; then each block is pushed as `add_block` does
+    pub fn add_blocks(&mut self, blocks: Vec<Block>) -> BlockchainResult<()> {
+        Self::validate_sequence(&blocks)?;
+        for block in blocks {
+            self.add_block(block)?;
+        }
+        Ok(())
+    }
+
+    /// Replace the chain tip with `candidate`, a different block at the same height with the same parent, if the
+    /// tie-break rule prefers it (see `prefers_block`). Returns whether the tip has been replaced.
+    /// The candidate is validated as `add_block` would, against the balances before the current tip. Its header must
+    /// commit to its transaction through the merkle root (see `merkle_root_at`) and its hash must meet the difficulty
+    /// computed from its parent, so the candidate can't be crafted without proof of work
+    pub fn replace_tip_if_better(&mut self, candidate: Block) -> BlockchainResult<bool> {
+        if candidate.index() == 0 {
+            return Err(BlockchainError::GenesisImmutable);
+        }
+        self.validate_block_standalone(&candidate)?;
+        if candidate.header().created_at() > self.clock.now() + self.future_block_tolerance {
+            return Err(BlockchainError::FutureBlock(candidate.index()));
+        }
+        let tip = self.get_latest_block()?;
+        if candidate.index() != tip.index() {
+            return Err(BlockchainError::InvalidBlock);
+        }
+        let parent = self
+            .blockchain
+            .get_header(tip.index() - 1)?
+            .ok_or(BlockchainError::BlockNotFound(tip.index() - 1))?;
+        if candidate.header().previous_block_header_hash() != Some(parent.merkle_root_hash()) {
+            return Err(BlockchainError::InvalidBlock);
+        }
+        if candidate.header().difficulty() != self.difficulty_at(candidate.index())? {
+            return Err(BlockchainError::DifficultyNotMet(
+                candidate.header().difficulty(),
+            ));
+        }
+        if candidate.header().merkle_root_hash()
+            != self.merkle_root_at(candidate.index(), candidate.transaction())?
+        {
+            return Err(BlockchainError::BadMerkleRoot);
+        }
+        if !candidate.header().meets_difficulty()? {
+            return Err(BlockchainError::InsufficientWork(
+                candidate.header().difficulty(),
+            ));
+        }
+        if candidate.id() == tip.id() || !Self::prefers_block(&candidate, &tip) {
+            return Ok(false);
+        }
+        let txid = candidate.transaction().id();
+        if txid != tip.transaction().id() && self.contains_transaction(&txid)? {
+            return Err(BlockchainError::DuplicateTransaction(txid));
+        }
+        // validate the candidate against the balances before the tip; each address is reverted once, since the
+        // amounts are summed over all the tip inputs and outputs
+        let mut balances = HashMap::new();
+        let addresses: HashSet<&String> = tip
+            .transaction()
+            .inputs()
+            .iter()
+            .map(|x| &x.address)
+            .chain(tip.transaction().outputs().iter().map(|x| &x.address))
+            .collect();
+        for address in addresses {
+            let mut delta = tip.transaction().amount_received(address);
+            if !tip.transaction().is_coinbase() {
+                delta += tip.transaction().amount_spent(address);
+            }
+            *self.snapshot_balance(&mut balances, address)? -= delta;
+        }
+        self.apply_transactions(&mut balances, std::slice::from_ref(candidate.transaction()))?;
+        info!(
+            "replacing block #{} with hash {} with {}",
+            tip.index(),
+            tip.header().merkle_root_hash(),
+            candidate.header().merkle_root_hash()
+        );
+        // write the new tip first, so a failure never leaves the tip without its indexes
+        self.blockchain.put_block(&candidate)?;
+        Self::index_transaction(&self.blockchain, candidate.transaction(), candidate.index())?;
+        let candidate_ids = [
+            candidate.transaction().id(),
+            candidate.transaction().signed_id(),
+        ];
+        for id in [tip.transaction().id(), tip.transaction().signed_id()] {
+            if !candidate_ids.contains(&id) {
+                self.blockchain.delete_transaction_block(&id)?;
+            }
         }
+        Ok(true)
+    }
+
+    /// The deterministic tie-break rule between two valid blocks at the same height: the block whose transaction has
+    /// the lower id wins. The header hash is not accounted, since the nonce can be ground to lower it.
+    /// Returns whether `candidate` is preferred to `current`
+    pub fn prefers_block(candidate: &Block, current: &Block) -> bool {
+        candidate.transaction().id() < current.transaction().id()
+    }
+
+    /// Apply `transactions` in order to a snapshot of the balances at the chain tip.
+    /// Each transaction must be covered by the balance left by the previous ones, so the same funds
+    /// can't be spent twice within a block; otherwise `InsufficientFunds` is returned
+    pub fn validate_transactions(&self, transactions: &[Transaction]) -> BlockchainResult<()> {
+        self.apply_transactions(&mut HashMap::new(), transactions)
+    }
+
+    /// Apply `transactions` in order to the `balances` snapshot; balances missing from the snapshot are loaded from
+    /// the chain tip. See `validate_transactions`
+    fn apply_transactions(
+        &self,
+        balances: &mut HashMap<String, Decimal>,
+        transactions: &[Transaction],
+    ) -> BlockchainResult<()> {
+        for transaction in transactions {
+            // coinbase transactions have no issuer to charge
+            if !transaction.is_coinbase() {
+                for input in transaction.inputs() {
+                    let required = -transaction.amount_spent(&input.address);
+                    let available = self.snapshot_balance(balances, &input.address)?;
+                    if *available < required {
+                        return Err(BlockchainError::InsufficientFunds {
+                            available: *available,
+                            required,
+                        });
+                    }
+                }
+                for input in transaction.inputs() {
+                    *self.snapshot_balance(balances, &input.address)? -= input.amount;
+                }
+            }
+            for output in transaction.outputs() {
+                *self.snapshot_balance(balances, &output.address)? += output.amount;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the balance of `addr` in `snapshot`, loading it from the chain the first time
+    fn snapshot_balance<'a>(
+        &self,
+        snapshot: &'a mut HashMap<String, Decimal>,
+        addr: &str,
+    ) -> BlockchainResult<&'a mut Decimal> {
+        if !snapshot.contains_key(addr) {
+            let balance = self.scan_wallet_amount(addr, None)?.unwrap_or_default();
+            snapshot.insert(addr.to_string(), balance);
+        }
+        Ok(snapshot.get_mut(addr).unwrap())
+    }
+
+    /// Get the index of the block containing the transaction with id `txid`
+    pub fn transaction_block(&self, txid: &str) -> BlockchainResult<Option<u64>> {
+        self.blockchain.get_transaction_block(txid)
+    }
+
+    /// Returns whether the transaction with id `txid` has already been mined
+    pub fn contains_transaction(&self, txid: &str) -> BlockchainResult<bool> {
+        self.transaction_block(txid).map(|x| x.is_some())
+    }
+
+    /// Returns whether a transaction with the same signed id of `transaction` has already been mined, even by a node
+    /// which appended different fee outputs (see `Transaction::signed_id`)
+    pub fn contains_signed_transaction(&self, transaction: &Transaction) -> BlockchainResult<bool> {
+        self.contains_transaction(&transaction.signed_id())
+    }
+
+    /// Index `transaction` mined in block #`index` by both its id and its signed id
+    fn index_transaction(
+        database: &BlockchainDatabase,
+        transaction: &Transaction,
+        index: u64,
+    ) -> BlockchainResult<()> {
+        database.put_transaction_block(&transaction.id(), index)?;
+        database.put_transaction_block(&transaction.signed_id(), index)
+    }
+
+    /// Drop all the indexes and recompute them from the blocks in the chain.
+    /// Use this to repair indexes which have drifted from the block data (e.g. after a crash)
+    pub fn rebuild_indexes(&mut self) -> BlockchainResult<()> {
+        self.blockchain.clear_header_index()?;
+        self.blockchain.clear_transaction_index()?;
+        self.rebuild_transaction_index()?;
+        self.blockchain.flush()
+    }
+
+    /// Index the transactions of all the blocks in the chain
+    fn rebuild_transaction_index(&self) -> BlockchainResult<()> {
+        info!("rebuilding transaction index...");
+        let mut indexed = 0;
+        for block in self.iter_from(0) {
+            let block = block?;
+            Self::index_transaction(&self.blockchain, block.transaction(), block.index())?;
+            indexed += 1;
+        }
+        info!("indexed transactions of {} blocks", indexed);
+        Ok(())
+    }
+
+    /// Run all the checks on `block` which don't depend on the chain state:
+    ///
+    /// - the header must have a merkle root and, for any block but the genesis, the previous block hash
+    /// - the difficulty must be at least `MIN_DIFFICULTY`, or exactly the initial difficulty for the blocks before the
+    ///   first retarget
+    /// - the transaction structure must be valid within the protocol limits (see `Transaction::validate_structure`)
+    ///
+    /// Signatures can't be verified here, since transactions don't carry the issuer public key
+    pub fn validate_block_standalone(&self, block: &Block) -> BlockchainResult<()> {
+        let header = block.header();
+        if header.merkle_root_hash().is_empty()
+            || (block.index() > 0 && header.previous_block_header_hash().is_none())
+        {
+            return Err(BlockchainError::BadMerkleRoot);
+        }
+        if !self.difficulty_in_bounds(block.index(), header.difficulty()) {
+            return Err(BlockchainError::DifficultyNotMet(header.difficulty()));
+        }
+        block
+            .transaction()
+            .validate_structure(MAX_INPUTS, MAX_OUTPUTS)
+    }
+
+    /// Check that `blocks` form a sequence which can be applied in order:
+    /// indices must be contiguous and each block must link to its predecessor in the sequence.
+    /// A gap is reported as `OrphanBlock`, while a repeated index or a bad linkage as `InvalidBlock`.
+    /// The same transaction can't be included twice in the sequence, except for coinbase transactions,
+    /// otherwise `DuplicateTransaction` is returned.
+    /// The first block is not checked against the chain tip, which is done by `add_block`
+    pub fn validate_sequence(blocks: &[Block]) -> BlockchainResult<()> {
+        for pair in blocks.windows(2) {
+            let (previous, block) = (&pair[0], &pair[1]);
+            if block.index() > previous.index() + 1 {
+                return Err(BlockchainError::OrphanBlock(
+                    block.index(),
+                    previous.index(),
+                ));
+            }
+            if block.index() != previous.index() + 1
+                || block.header().previous_block_header_hash()
+                    != Some(previous.header().merkle_root_hash())
+            {
+                return Err(BlockchainError::InvalidBlock);
+            }
+        }
+        let mut txids = HashSet::new();
+        for transaction in blocks.iter().map(Block::transaction) {
+            if !transaction.is_coinbase() && !txids.insert(transaction.id()) {
+                return Err(BlockchainError::DuplicateTransaction(transaction.id()));
+            }
+        }
+        Ok(())
     }
 
     /// Get block at `index`
@@ -69,49 +489,279 @@ impl Chain {
         self.blockchain.get_block(index)
     }
 
-    /// Get latest block. Unwrap is safe, since blockchain cannot be empty
-    pub fn get_latest_block(&self) -> BlockchainResult<Block> {
-        let mut index = 1;
-        let mut block = self.get_genesis_block()?;
+    /// Get block at `index` while scanning the chain. `None` means the end of the chain has been reached.
+    /// Database errors are retried up to `SCAN_READ_RETRIES` times, so a transient failure doesn't abort a long scan.
+    /// The owner runs on the async main loop, so it retries immediately; only reader handles back off between attempts
+    fn scan_block(&self, index: u64) -> BlockchainResult<Option<Block>> {
+        let mut retries = 0;
         loop {
-            // if next block exists, update block and keep iterating; otherwise return last block `block`
-            match self.get_block(index)? {
-                None => break,
-                Some(b) => block = b,
+            match self.get_block(index) {
+                Err(BlockchainError::Database(err)) if retries < SCAN_READ_RETRIES => {
+                    retries += 1;
+                    warn!(
+                        "could not read block #{} (attempt {}/{}): {}",
+                        index, retries, SCAN_READ_RETRIES, err
+                    );
+                    if let Some(backoff) = self.scan_retry_backoff {
+                        std::thread::sleep(backoff * retries);
+                    }
+                }
+                result => return result,
             }
-            index += 1;
         }
-        Ok(block)
     }
 
-    /// Generate the next block in the blockchain
+    /// Iterate over the blocks from index `start` up to the tip.
+    /// The iteration stops after the first error
+    pub fn iter_from(&self, start: u64) -> impl Iterator<Item = BlockchainResult<Block>> + '_ {
+        let mut next = Some(start);
+        std::iter::from_fn(move || {
+            let index = next.take()?;
+            match self.scan_block(index) {
+                Ok(Some(block)) => {
+                    next = Some(index + 1);
+                    Some(Ok(block))
+                }
+                Ok(None) => None,
+                Err(err) => Some(Err(err)),
+            }
+        })
+    }
+
+    /// Get the id (the merkle root hash) of block #`index`, read through the header index
+    pub fn block_id(&self, index: u64) -> BlockchainResult<Option<String>> {
+        Ok(self
+            .blockchain
+            .get_header(index)?
+            .map(|header| header.merkle_root_hash().to_string()))
+    }
+
+    /// Compare the chain with the chain of a peer, whose tip is block #`other_height` with id `other_head_id`,
+    /// finding the last block both chains share.
+    /// `id_at` returns the id of the peer block at an index (e.g. asking the peer with `GetBlockId`), or `None` if the
+    /// peer doesn't know it. Blocks are compared by id, which is the merkle root of all the transactions up to the
+    /// block: chains sharing a block share all the previous ones too, so the fork point is binary searched,
+    /// calling `id_at` O(log n) times
+    pub fn diff<F>(
+        &self,
+        other_head_id: &str,
+        other_height: u64,
+        mut id_at: F,
+    ) -> BlockchainResult<ChainComparison>
+    where
+        F: FnMut(u64) -> Option<String>,
+    {
+        let (local_height, _) = self.tip_header()?;
+        let mut remote_id = |index: u64| {
+            if index == other_height {
+                Some(other_head_id.to_string())
+            } else {
+                id_at(index)
+            }
+        };
+        let genesis_id = match remote_id(0) {
+            Some(id) => id,
+            None => return Ok(ChainComparison::Unknown(0)),
+        };
+        if self.block_id(0)?.as_deref() != Some(genesis_id.as_str()) {
+            return Ok(ChainComparison::Unrelated);
+        }
+        // the last block shared is in `low..=high`
+        let mut low = 0;
+        let mut high = local_height.min(other_height);
+        while low < high {
+            let mid = low + (high - low + 1) / 2;
+            let id = match remote_id(mid) {
+                Some(id) => id,
+                None => return Ok(ChainComparison::Unknown(mid)),
+            };
+            if self.block_id(mid)?.as_deref() == Some(id.as_str()) {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+        Ok(ChainComparison::Shared(ChainDiff::new(
+            low,
+            local_height,
+            other_height,
+        )))
+    }
+
+    /// Get the last `n` blocks, oldest first. If `n` is greater than the chain length, the whole chain is returned
+    pub fn tail(&self, n: u64) -> BlockchainResult<Vec<Block>> {
+        if n == 0 {
+            return Ok(Vec::new());
+        }
+        let (tip, _) = self.tip_header()?;
+        self.iter_from((tip + 1).saturating_sub(n)).collect()
+    }
+
+    /// Get latest block
+    pub fn get_latest_block(&self) -> BlockchainResult<Block> {
+        let (index, _) = self.tip_header()?;
+        self.get_block(index)?
+            .ok_or(BlockchainError::BlockNotFound(index))
+    }
+
+    /// Get the header of the latest block, without reading the block transactions
+    pub fn latest_header(&self) -> BlockchainResult<Header> {
+        self.tip_header().map(|(_, header)| header)
+    }
+
+    /// Get index and header of the latest block.
+    /// Blocks are stored with contiguous indexes from the genesis block, so the tip is found with an exponential search
+    /// over the header index, reading O(log n) headers
+    fn tip_header(&self) -> BlockchainResult<(u64, Header)> {
+        let mut header = self
+            .blockchain
+            .get_header(0)?
+            .ok_or(BlockchainError::BlockNotFound(0))?;
+        // the tip is in `low..high`: block #`low` exists, block #`high` doesn't
+        let mut low = 0;
+        let mut high = 1;
+        while let Some(next) = self.blockchain.get_header(high)? {
+            header = next;
+            low = high;
+            high *= 2;
+        }
+        while high - low > 1 {
+            let mid = low + (high - low) / 2;
+            match self.blockchain.get_header(mid)? {
+                Some(next) => {
+                    header = next;
+                    low = mid;
+                }
+                None => high = mid,
+            }
+        }
+        Ok((low, header))
+    }
+
+    /// Force pending writes to disk. Should be called before snapshotting the database or on shutdown
+    pub fn flush(&self) -> BlockchainResult<()> {
+        self.blockchain.flush()
+    }
+
+    /// Generate the next block in the blockchain, mining its header with the difficulty computed for it
     pub fn generate_next_block(&mut self, transaction: Transaction) -> BlockchainResult<Block> {
         let previous_block = self.get_latest_block()?;
         let next_index = previous_block.index() + 1;
-        let next_merkle_root = self.calc_merkle_root_hash()?;
+        let next_merkle_root = self.merkle_root_at(next_index, &transaction)?;
+        let next_difficulty = self.next_difficulty()?;
 
         // generate new block
-        let new_block = Block::new(
-            next_index,
-            Header::new(
-                Version::V010,
-                Some(previous_block.header().merkle_root_hash().to_string()),
-                next_merkle_root,
-                SystemTime::now(),
-            ),
-            transaction,
-        );
+        let header = Header::new(
+            Version::V010,
+            Some(previous_block.header().merkle_root_hash().to_string()),
+            next_merkle_root,
+            self.clock.now(),
+        )
+        .with_difficulty(next_difficulty)
+        .mine()?
+        .ok_or(BlockchainError::InsufficientWork(next_difficulty))?;
+        let new_block = Block::new(next_index, header, transaction);
         // add block and return latest block
         self.add_block(new_block)?;
         self.get_latest_block()
     }
 
-    /// Get current jab amount for provided wallet
+    /// Calculate the difficulty for the next block.
+    /// Blocks before the first retarget, i.e. until the chain fills the retarget window, are mined with the initial
+    /// difficulty of the genesis block.
+    /// If the blocks in the retarget window have been created faster than the block time target, difficulty is raised;
+    /// if they have been created slower, it is lowered. Since it only depends on chain data, all nodes compute the same value
+    pub fn next_difficulty(&self) -> BlockchainResult<u32> {
+        let (latest_index, _) = self.tip_header()?;
+        self.difficulty_at(latest_index + 1)
+    }
+
+    /// Calculate the difficulty block #`index` must be mined with, from the blocks before it (see `next_difficulty`)
+    fn difficulty_at(&self, index: u64) -> BlockchainResult<u32> {
+        if self.before_first_retarget(index) {
+            return Ok(self.initial_difficulty);
+        }
+        let difficulty = self
+            .blockchain
+            .get_header(index - 1)?
+            .ok_or(BlockchainError::BlockNotFound(index - 1))?
+            .difficulty();
+        Ok(
+            match self.average_block_time_until(index - 1, self.retarget_window)? {
+                Some(interval) if interval < self.block_time_target => difficulty.saturating_add(1),
+                Some(interval) if interval > self.block_time_target => {
+                    difficulty.saturating_sub(1).max(MIN_DIFFICULTY)
+                }
+                _ => difficulty,
+            },
+        )
+    }
+
+    /// Returns whether block #`index` is mined before the first retarget, hence with the initial difficulty
+    fn before_first_retarget(&self, index: u64) -> bool {
+        index <= self.retarget_window
+    }
+
+    /// Check the difficulty declared by block #`index` without reading the chain: blocks before the first retarget
+    /// must declare the initial difficulty, later blocks at least `MIN_DIFFICULTY`
+    fn difficulty_in_bounds(&self, index: u64, difficulty: u32) -> bool {
+        if self.before_first_retarget(index) {
+            difficulty == self.initial_difficulty
+        } else {
+            difficulty >= MIN_DIFFICULTY
+        }
+    }
+
+    /// Calculate the average time between the creation of the latest `window` blocks.
+    /// If `window` is larger than the chain, all the blocks are accounted.
+    /// The genesis block is never accounted, since its creation time is the UNIX epoch.
+    /// Returns `None` if there are less than two blocks to compare
+    pub fn average_block_time(&self, window: u64) -> BlockchainResult<Option<Duration>> {
+        let (latest_index, _) = self.tip_header()?;
+        self.average_block_time_until(latest_index, window)
+    }
+
+    /// Calculate the average time between the creation of the `window` blocks ending with block #`last_index`
+    fn average_block_time_until(
+        &self,
+        last_index: u64,
+        window: u64,
+    ) -> BlockchainResult<Option<Duration>> {
+        let first_index = last_index.saturating_sub(window.saturating_sub(1)).max(1);
+        if last_index <= first_index {
+            return Ok(None);
+        }
+        let (first_header, last_header) = match (
+            self.blockchain.get_header(first_index)?,
+            self.blockchain.get_header(last_index)?,
+        ) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Ok(None),
+        };
+        let elapsed = last_header
+            .created_at()
+            .duration_since(first_header.created_at())
+            .unwrap_or_default();
+        let intervals = (last_index - first_index) as u32;
+        Ok(Some(elapsed / intervals))
+    }
+
+    /// Get current jab amount for provided wallet, scanning the whole chain
     pub fn wallet_amount(&self, addr: &str) -> BlockchainResult<Option<Decimal>> {
+        self.scan_wallet_amount(addr, None)
+    }
+
+    /// Get current jab amount for provided wallet, scanning at most `max_scan_blocks` blocks
+    pub(super) fn scan_wallet_amount(
+        &self,
+        addr: &str,
+        max_scan_blocks: Option<u64>,
+    ) -> BlockchainResult<Option<Decimal>> {
         let mut index = 0;
         let mut wallet_amount = Decimal::ZERO;
         let mut wallet_found = false;
-        while let Some(block) = self.get_block(index)? {
+        while let Some(block) = self.scan_block(index)? {
+            Self::check_scan_limit(index, max_scan_blocks)?;
             let mut already_checked = false;
             wallet_amount += block.transaction().amount_received(addr);
             wallet_amount += block.transaction().amount_spent(addr);
@@ -137,12 +787,38 @@ impl Chain {
         }
     }
 
-    /// Collect transactions for wallet with provided address
+    /// Get the total jab supply as of block `index`: the sum of the outputs of the coinbase transactions
+    /// (the genesis allocations and the rewards) up to block `index` included.
+    /// Transfers only move jab between wallets, hence they never change the supply
+    pub fn supply_at(&self, index: u64) -> BlockchainResult<Decimal> {
+        let mut supply = Decimal::ZERO;
+        for i in 0..=index {
+            let block = self
+                .scan_block(i)?
+                .ok_or(BlockchainError::BlockNotFound(i))?;
+            if block.transaction().is_coinbase() {
+                supply += block.transaction().total_output();
+            }
+        }
+        Ok(supply)
+    }
+
+    /// Collect transactions for wallet with provided address, scanning the whole chain
     pub fn wallet_transactions(&self, addr: &str) -> BlockchainResult<Option<Vec<Transaction>>> {
+        self.scan_wallet_transactions(addr, None)
+    }
+
+    /// Collect transactions for wallet with provided address, scanning at most `max_scan_blocks` blocks
+    pub(super) fn scan_wallet_transactions(
+        &self,
+        addr: &str,
+        max_scan_blocks: Option<u64>,
+    ) -> BlockchainResult<Option<Vec<Transaction>>> {
         let mut index = 0;
         let mut wallet_transactions = Vec::new();
         let mut wallet_found = false;
-        while let Some(block) = self.get_block(index)? {
+        while let Some(block) = self.scan_block(index)? {
+            Self::check_scan_limit(index, max_scan_blocks)?;
             let mut already_pushed = false;
             for input in block.transaction().inputs() {
                 if input.address.as_str() == addr {
@@ -168,10 +844,48 @@ impl Chain {
         }
     }
 
+    /// Fail with `ScanLimitExceeded` if `scanned` blocks already reached the scan limit
+    fn check_scan_limit(scanned: u64, max_scan_blocks: Option<u64>) -> BlockchainResult<()> {
+        match max_scan_blocks {
+            Some(max) if scanned >= max => Err(BlockchainError::ScanLimitExceeded(max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Get the ledger of the wallet with provided address: every transaction affecting it, ordered by block,
+    /// with the balance after each one. The balance of the last entry equals `wallet_amount`.
+    /// Fails with `ScanLimitExceeded` if the chain is longer than the maximum amount of scanned blocks
+    pub fn audit_wallet(&self, addr: &str) -> BlockchainResult<Vec<LedgerEntry>> {
+        let mut ledger = Vec::new();
+        let mut balance = Decimal::ZERO;
+        let mut index = 0;
+        while let Some(block) = self.scan_block(index)? {
+            Self::check_scan_limit(index, self.max_scan_blocks)?;
+            let transaction = block.transaction();
+            let affected = transaction
+                .inputs()
+                .iter()
+                .map(|x| &x.address)
+                .chain(transaction.outputs().iter().map(|x| &x.address))
+                .any(|x| x.as_str() == addr);
+            if affected {
+                let mut delta = transaction.amount_received(addr);
+                // coinbase transactions have no issuer to charge
+                if !transaction.is_coinbase() {
+                    delta += transaction.amount_spent(addr);
+                }
+                balance += delta;
+                ledger.push(LedgerEntry::new(index, transaction.id(), delta, balance));
+            }
+            index += 1;
+        }
+        Ok(ledger)
+    }
+
     /// Returns whether a certain wallet exists
     pub fn wallet_exists(&self, addr: &str) -> BlockchainResult<bool> {
         let mut index = 0;
-        while let Some(block) = self.get_block(index)? {
+        while let Some(block) = self.scan_block(index)? {
             for input in block.transaction().inputs() {
                 if input.address.as_str() == addr {
                     return Ok(true);
@@ -187,15 +901,32 @@ impl Chain {
         Ok(false)
     }
 
-    #[inline]
-    fn genesis_block() -> Block {
-        let genesis_transaction =
-            Self::genesis_transaction(TransactionVersion::V1, GENESIS_BLOCK_ADDRESS, dec!(500.0))
-                .finish(GENESIS_BLOCK_SIGNATURE);
+    /// Make the genesis block.
+    /// Allocations are appended after the genesis wallet output, so they don't change the genesis transaction signature
+    fn genesis_block(genesis: &GenesisConfig) -> Block {
+        let genesis_transaction = genesis
+            .allocations()
+            .iter()
+            .fold(
+                Self::genesis_transaction(
+                    TransactionVersion::V1,
+                    GENESIS_BLOCK_ADDRESS,
+                    dec!(500.0),
+                ),
+                |builder, (address, amount)| builder.output(address, *amount),
+            )
+            .finish(GENESIS_BLOCK_SIGNATURE);
         let tree = JabMerkleTree::new(vec![genesis_transaction.clone()]);
+        // blocks link to the merkle root of their parent, so it commits to the initial difficulty too: nodes with
+        // another initial difficulty don't share the genesis. The default difficulty keeps the original root
+        let merkle_root = match genesis.difficulty() {
+            MIN_DIFFICULTY => tree.root_hash(),
+            difficulty => tree.committed_root_hash(&difficulty.to_be_bytes()),
+        };
         Block::new(
             0,
-            Header::new(Version::V010, None, tree.root_hash(), UNIX_EPOCH),
+            Header::new(Version::V010, None, merkle_root, UNIX_EPOCH)
+                .with_difficulty(genesis.difficulty()),
             genesis_transaction,
         )
     }
@@ -210,15 +941,1377 @@ impl Chain {
         TransactionBuilder::new(version).output(address, amount)
     }
 
-    /// Calculate the merkle root hash from all the transactions in the blockchain
-    fn calc_merkle_root_hash(&self) -> BlockchainResult<String> {
+    /// Calculate the merkle root hash of block #`index` carrying `transaction`: as for the genesis block, the tree
+    /// covers the transactions of the blocks before it and `transaction` itself
+    fn merkle_root_at(&self, index: u64, transaction: &Transaction) -> BlockchainResult<String> {
         let mut transactions: Vec<Transaction> = Vec::new();
-        let mut index = 0;
-        while let Some(block) = self.get_block(index)? {
+        for i in 0..index {
+            let block = self
+                .scan_block(i)?
+                .ok_or(BlockchainError::BlockNotFound(i))?;
             transactions.push(block.transaction().clone());
-            index += 1;
         }
+        transactions.push(transaction.clone());
         let tree = JabMerkleTree::new(transactions);
         Ok(tree.root_hash())
     }
 }
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use std::time::SystemTime;
+    use tempfile::TempDir;
+
+    #[test]
+    fn should_keep_blocks_after_flush_and_reopen() {
+        let tempdir = TempDir::new().expect("could not create tempfile");
+        let mut chain = Chain::try_from(tempdir.path()).unwrap();
+        let transaction = TransactionBuilder::new(TransactionVersion::V1)
+            .input(GENESIS_BLOCK_ADDRESS, dec!(10.0))
+            .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(10.0))
+            .finish("aaa");
+        let block = chain.generate_next_block(transaction).unwrap();
+        assert!(chain.flush().is_ok());
+        drop(chain);
+        let chain = Chain::try_from(tempdir.path()).unwrap();
+        assert_eq!(chain.get_latest_block().unwrap(), block);
+        assert_eq!(
+            chain.get_genesis_block().unwrap(),
+            Chain::genesis_block(&GenesisConfig::default())
+        );
+    }
+
+    #[test]
+    fn should_construct_chain_from_in_memory_database() {
+        let database = BlockchainDatabase::in_memory();
+        let mut chain = Chain::from_database(database.clone()).unwrap();
+        assert_eq!(
+            chain.get_genesis_block().unwrap(),
+            Chain::genesis_block(&GenesisConfig::default())
+        );
+        push_block(&mut chain, 1000, MIN_DIFFICULTY);
+        // genesis block is not generated again on an initialized database
+        let chain = Chain::from_database(database).unwrap();
+        assert_eq!(chain.get_latest_block().unwrap().index(), 1);
+    }
+
+    #[test]
+    fn should_find_block_containing_transaction() {
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        let genesis_block = chain.get_genesis_block().unwrap();
+        assert_eq!(
+            chain
+                .transaction_block(&genesis_block.transaction().id())
+                .unwrap(),
+            Some(0)
+        );
+        push_block(&mut chain, 1000, MIN_DIFFICULTY);
+        let transaction = TransactionBuilder::new(TransactionVersion::V1)
+            .input(GENESIS_BLOCK_ADDRESS, dec!(2.0))
+            .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(2.0))
+            .finish("bbb");
+        let block = chain.generate_next_block(transaction.clone()).unwrap();
+        assert_eq!(
+            chain.transaction_block(&transaction.id()).unwrap(),
+            Some(block.index())
+        );
+        assert!(chain.transaction_block("cafebabe").unwrap().is_none());
+    }
+
+    #[test]
+    fn should_tell_whether_transaction_has_been_mined() {
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        let transaction = TransactionBuilder::new(TransactionVersion::V1)
+            .input(GENESIS_BLOCK_ADDRESS, dec!(2.0))
+            .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(2.0))
+            .finish("bbb");
+        assert_eq!(
+            chain.contains_transaction(&transaction.id()).unwrap(),
+            false
+        );
+        chain.generate_next_block(transaction.clone()).unwrap();
+        assert_eq!(chain.contains_transaction(&transaction.id()).unwrap(), true);
+    }
+
+    #[test]
+    fn should_replace_tip_with_better_block_at_same_height() {
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        push_block(&mut chain, 1000, MIN_DIFFICULTY);
+        let tip = mined_block(
+            &chain,
+            2,
+            TransactionBuilder::new(TransactionVersion::V1)
+                .input(GENESIS_BLOCK_ADDRESS, dec!(2.0))
+                .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(2.0))
+                .finish("ccc"),
+        );
+        chain.add_block(tip.clone()).unwrap();
+        let transaction = preferred_transaction(
+            TransactionBuilder::new(TransactionVersion::V1)
+                .input(GENESIS_BLOCK_ADDRESS, dec!(3.0))
+                .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(3.0)),
+            tip.transaction(),
+        );
+        let candidate = mined_block(&chain, 2, transaction);
+        assert!(chain.replace_tip_if_better(candidate.clone()).unwrap());
+        assert_eq!(chain.get_latest_block().unwrap(), candidate);
+        assert_eq!(
+            chain
+                .transaction_block(&candidate.transaction().id())
+                .unwrap(),
+            Some(2)
+        );
+        assert!(!chain.contains_transaction(&tip.transaction().id()).unwrap());
+        assert_eq!(
+            chain
+                .wallet_amount("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1")
+                .unwrap(),
+            Some(dec!(4.0))
+        );
+        // the replaced block is worse than the new tip
+        assert!(!chain.replace_tip_if_better(tip).unwrap());
+        assert_eq!(chain.get_latest_block().unwrap(), candidate);
+    }
+
+    #[test]
+    fn should_revert_tip_with_change_output_once() {
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        let (alice, bob) = (
+            "jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1",
+            "jab8a3bd8e7e12b5f6bc8d5e3e3a0a2b7c1d0f3e9a4",
+        );
+        chain
+            .add_block(mined_block(
+                &chain,
+                1,
+                TransactionBuilder::new(TransactionVersion::V1)
+                    .input(GENESIS_BLOCK_ADDRESS, dec!(10.0))
+                    .output(alice, dec!(10.0))
+                    .finish("aaa"),
+            ))
+            .unwrap();
+        // alice pays 4 to bob, getting 6 back as change
+        let tip = mined_block(
+            &chain,
+            2,
+            TransactionBuilder::new(TransactionVersion::V1)
+                .input(alice, dec!(10.0))
+                .output(bob, dec!(4.0))
+                .output(alice, dec!(6.0))
+                .finish("bbb"),
+        );
+        chain.add_block(tip.clone()).unwrap();
+        // before the tip alice had 10, not 14
+        let overspending = mined_block(
+            &chain,
+            2,
+            preferred_transaction(
+                TransactionBuilder::new(TransactionVersion::V1)
+                    .input(alice, dec!(12.0))
+                    .output(bob, dec!(12.0)),
+                tip.transaction(),
+            ),
+        );
+        assert!(matches!(
+            chain.replace_tip_if_better(overspending),
+            Err(BlockchainError::InsufficientFunds { .. })
+        ));
+        let candidate = mined_block(
+            &chain,
+            2,
+            preferred_transaction(
+                TransactionBuilder::new(TransactionVersion::V1)
+                    .input(alice, dec!(10.0))
+                    .output(bob, dec!(10.0)),
+                tip.transaction(),
+            ),
+        );
+        assert!(chain.replace_tip_if_better(candidate.clone()).unwrap());
+        assert_eq!(chain.get_latest_block().unwrap(), candidate);
+        assert_eq!(chain.wallet_amount(bob).unwrap(), Some(dec!(10.0)));
+    }
+
+    #[test]
+    fn should_not_replace_tip_with_block_declaring_another_difficulty() {
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        let tip = next_block(&chain, 1000, MIN_DIFFICULTY);
+        chain.add_block(tip.clone()).unwrap();
+        let transaction = TransactionBuilder::new(TransactionVersion::V1)
+            .input(GENESIS_BLOCK_ADDRESS, dec!(3.0))
+            .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(3.0))
+            .finish("bbb");
+        let candidate = Block::new(
+            tip.index(),
+            tip.header().clone().with_difficulty(u32::MAX),
+            transaction,
+        );
+        assert!(matches!(
+            chain.replace_tip_if_better(candidate),
+            Err(BlockchainError::DifficultyNotMet(u32::MAX))
+        ));
+        assert_eq!(chain.get_latest_block().unwrap(), tip);
+    }
+
+    #[test]
+    fn should_not_replace_tip_with_block_without_work_or_commitment() {
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        let tip = mined_block(
+            &chain,
+            1,
+            TransactionBuilder::new(TransactionVersion::V1)
+                .input(GENESIS_BLOCK_ADDRESS, dec!(2.0))
+                .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(2.0))
+                .finish("ccc"),
+        );
+        chain.add_block(tip.clone()).unwrap();
+        let transaction = preferred_transaction(
+            TransactionBuilder::new(TransactionVersion::V1)
+                .input(GENESIS_BLOCK_ADDRESS, dec!(3.0))
+                .output("jab8a3bd8e7e12b5f6bc8d5e3e3a0a2b7c1d0f3e9a4", dec!(3.0)),
+            tip.transaction(),
+        );
+        // the header of the tip doesn't commit to another transaction
+        assert!(matches!(
+            chain.replace_tip_if_better(Block::new(1, tip.header().clone(), transaction.clone())),
+            Err(BlockchainError::BadMerkleRoot)
+        ));
+        // a header committing to the transaction, but not mined
+        let candidate = mined_block(&chain, 1, transaction);
+        let unmined = (0..)
+            .map(|nonce| candidate.header().clone().with_nonce(nonce))
+            .find(|header| !header.meets_difficulty().unwrap())
+            .unwrap();
+        assert!(matches!(
+            chain.replace_tip_if_better(Block::new(1, unmined, candidate.transaction().clone())),
+            Err(BlockchainError::InsufficientWork(MIN_DIFFICULTY))
+        ));
+        assert_eq!(chain.get_latest_block().unwrap(), tip);
+        assert!(chain.replace_tip_if_better(candidate).unwrap());
+    }
+
+    #[test]
+    fn should_break_ties_with_the_lower_transaction_id() {
+        let chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        let a = next_block(&chain, 1000, MIN_DIFFICULTY);
+        let b = block_with_transaction(
+            &chain,
+            preferred_transaction(
+                TransactionBuilder::new(TransactionVersion::V1)
+                    .input(GENESIS_BLOCK_ADDRESS, dec!(1.0))
+                    .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(1.0)),
+                a.transaction(),
+            ),
+        );
+        assert!(Chain::prefers_block(&b, &a));
+        assert!(!Chain::prefers_block(&a, &b));
+        assert!(!Chain::prefers_block(&b, &b));
+        // grinding the nonce to lower the header hash doesn't help
+        let ground = (0..)
+            .map(|nonce| a.header().clone().with_nonce(nonce))
+            .find(|header| header.hash().unwrap() < b.header().hash().unwrap())
+            .unwrap();
+        assert!(!Chain::prefers_block(
+            &Block::new(a.index(), ground, a.transaction().clone()),
+            &b
+        ));
+    }
+
+    #[test]
+    fn should_not_replace_tip_with_block_at_other_height() {
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        assert!(matches!(
+            chain.replace_tip_if_better(chain.get_genesis_block().unwrap()),
+            Err(BlockchainError::GenesisImmutable)
+        ));
+        let block = next_block(&chain, 1000, MIN_DIFFICULTY);
+        assert!(matches!(
+            chain.replace_tip_if_better(block),
+            Err(BlockchainError::InvalidBlock)
+        ));
+    }
+
+    /// Get the ids of all the blocks of `chain`
+    fn block_ids(chain: &Chain) -> Vec<String> {
+        chain
+            .iter_from(0)
+            .map(|block| block.unwrap().id())
+            .collect()
+    }
+
+    #[test]
+    fn should_find_fork_height_with_peer_chain() {
+        let mut local = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        let mut remote = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        for created_at in 1..=5 {
+            let block = next_block(&local, 1000 + created_at, MIN_DIFFICULTY);
+            local.add_block(block.clone()).unwrap();
+            remote.add_block(block).unwrap();
+        }
+        // diverge after block #5
+        for created_at in 1..=3 {
+            push_block(&mut local, 2000 + created_at, MIN_DIFFICULTY);
+        }
+        for created_at in 1..=7 {
+            push_block(&mut remote, 3000 + created_at, MIN_DIFFICULTY);
+        }
+        let remote_ids = block_ids(&remote);
+        assert_eq!(
+            remote.block_id(12).unwrap().as_deref(),
+            Some(remote_ids[12].as_str())
+        );
+        let mut requests = 0;
+        let diff = local
+            .diff(&remote_ids[12], 12, |index| {
+                requests += 1;
+                remote_ids.get(index as usize).cloned()
+            })
+            .unwrap();
+        assert_eq!(diff, ChainComparison::Shared(ChainDiff::new(5, 8, 12)));
+        let diff = match diff {
+            ChainComparison::Shared(diff) => diff,
+            _ => unreachable!(),
+        };
+        assert!(diff.is_fork());
+        assert_eq!(diff.missing_blocks(), Some((6, 12)));
+        assert!(requests <= 5);
+        // a peer behind on the same chain
+        let local_ids = block_ids(&local);
+        assert_eq!(
+            local
+                .diff(&local_ids[3], 3, |index| local_ids
+                    .get(index as usize)
+                    .cloned())
+                .unwrap(),
+            ChainComparison::Shared(ChainDiff::new(3, 8, 3))
+        );
+        // chains with a different genesis block share nothing
+        assert_eq!(
+            local
+                .diff("cafebabe", 12, |_| Some(String::from("cafebabe")))
+                .unwrap(),
+            ChainComparison::Unrelated
+        );
+    }
+
+    #[test]
+    fn should_report_unknown_peer_block_in_diff() {
+        let mut local = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        for created_at in 1..=4 {
+            push_block(&mut local, 1000 + created_at, MIN_DIFFICULTY);
+        }
+        let local_ids = block_ids(&local);
+        // the peer knows the genesis block only
+        assert_eq!(
+            local
+                .diff(&local_ids[4], 4, |index| match index {
+                    0 => Some(local_ids[0].clone()),
+                    _ => None,
+                })
+                .unwrap(),
+            ChainComparison::Unknown(2)
+        );
+        assert_eq!(
+            local.diff(&local_ids[4], 4, |_| None).unwrap(),
+            ChainComparison::Unknown(0)
+        );
+    }
+
+    #[test]
+    fn should_retry_transient_read_errors_during_scan() {
+        let database = BlockchainDatabase::in_memory();
+        let mut chain = Chain::from_database(database.clone()).unwrap();
+        let transaction = TransactionBuilder::new(TransactionVersion::V1)
+            .input(GENESIS_BLOCK_ADDRESS, dec!(10.0))
+            .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(10.0))
+            .finish("aaa");
+        chain.generate_next_block(transaction).unwrap();
+        database.fail_next_block_reads(SCAN_READ_RETRIES as usize);
+        assert_eq!(
+            chain
+                .wallet_amount("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1")
+                .unwrap(),
+            Some(dec!(10.0))
+        );
+        // persistent errors still fail the scan
+        database.fail_next_block_reads(SCAN_READ_RETRIES as usize + 1);
+        assert!(matches!(
+            chain.wallet_amount("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1"),
+            Err(BlockchainError::Database(_))
+        ));
+    }
+
+    #[test]
+    fn should_tell_whether_signed_transaction_has_been_mined_with_other_fee() {
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        let builder = TransactionBuilder::transfer(
+            TransactionVersion::V1,
+            GENESIS_BLOCK_ADDRESS,
+            "jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1",
+            dec!(2.0),
+        );
+        let transaction = builder.clone().output("node-a", dec!(0.5)).finish("bbb");
+        let other_node = builder.output("node-b", dec!(0.5)).finish("bbb");
+        assert_eq!(
+            chain.contains_signed_transaction(&other_node).unwrap(),
+            false
+        );
+        chain.generate_next_block(transaction).unwrap();
+        assert_eq!(chain.contains_transaction(&other_node.id()).unwrap(), false);
+        assert_eq!(
+            chain.contains_signed_transaction(&other_node).unwrap(),
+            true
+        );
+    }
+
+    #[test]
+    fn should_rebuild_missing_transaction_index() {
+        let database = BlockchainDatabase::in_memory();
+        let mut chain = Chain::from_database(database.clone()).unwrap();
+        push_block(&mut chain, 1000, MIN_DIFFICULTY);
+        let latest_block = chain.get_latest_block().unwrap();
+        // write a block bypassing the index
+        let transaction = TransactionBuilder::new(TransactionVersion::V1)
+            .input(GENESIS_BLOCK_ADDRESS, dec!(2.0))
+            .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(2.0))
+            .finish("bbb");
+        database
+            .put_block(&Block::new(
+                2,
+                Header::new(
+                    Version::V010,
+                    Some(latest_block.header().merkle_root_hash().to_string()),
+                    String::from("block2"),
+                    UNIX_EPOCH + Duration::from_secs(1060),
+                ),
+                transaction.clone(),
+            ))
+            .unwrap();
+        assert!(chain
+            .transaction_block(&transaction.id())
+            .unwrap()
+            .is_none());
+        let chain = Chain::from_database(database).unwrap();
+        assert_eq!(chain.transaction_block(&transaction.id()).unwrap(), Some(2));
+    }
+
+    #[test]
+    fn should_validate_transactions_spending_available_funds() {
+        let chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        let recipient = "jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1";
+        let transactions = vec![
+            TransactionBuilder::transfer(
+                TransactionVersion::V1,
+                GENESIS_BLOCK_ADDRESS,
+                recipient,
+                dec!(300.0),
+            )
+            .finish("aaa"),
+            TransactionBuilder::transfer(
+                TransactionVersion::V1,
+                GENESIS_BLOCK_ADDRESS,
+                recipient,
+                dec!(200.0),
+            )
+            .finish("bbb"),
+            // the recipient can spend what it received earlier in the same block
+            TransactionBuilder::transfer(
+                TransactionVersion::V1,
+                recipient,
+                GENESIS_BLOCK_ADDRESS,
+                dec!(450.0),
+            )
+            .finish("ccc"),
+        ];
+        assert!(chain.validate_transactions(&transactions).is_ok());
+    }
+
+    #[test]
+    fn should_reject_intra_block_double_spend() {
+        let chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        let spend = |signature: &str| {
+            TransactionBuilder::transfer(
+                TransactionVersion::V1,
+                GENESIS_BLOCK_ADDRESS,
+                "jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1",
+                dec!(300.0),
+            )
+            .finish(signature)
+        };
+        // each transaction alone is covered by the balance
+        assert!(chain.validate_transactions(&[spend("aaa")]).is_ok());
+        assert!(matches!(
+            chain.validate_transactions(&[spend("aaa"), spend("bbb")]),
+            Err(BlockchainError::InsufficientFunds { available, required })
+                if available == dec!(200.0) && required == dec!(300.0)
+        ));
+    }
+
+    #[test]
+    fn should_reject_block_overspending_balance() {
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        let block = block_with_transaction(
+            &chain,
+            TransactionBuilder::transfer(
+                TransactionVersion::V1,
+                GENESIS_BLOCK_ADDRESS,
+                "jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1",
+                dec!(501.0),
+            )
+            .finish("aaa"),
+        );
+        assert!(matches!(
+            chain.add_block(block),
+            Err(BlockchainError::InsufficientFunds { .. })
+        ));
+        assert_eq!(chain.get_latest_block().unwrap().index(), 0);
+    }
+
+    #[test]
+    fn should_iterate_blocks_from_start_index() {
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        for created_at in [1000, 1060, 1120, 1180] {
+            push_block(&mut chain, created_at, MIN_DIFFICULTY);
+        }
+        let indexes: Vec<u64> = chain
+            .iter_from(2)
+            .map(|block| block.unwrap().index())
+            .collect();
+        assert_eq!(indexes, vec![2, 3, 4]);
+        assert_eq!(chain.iter_from(0).count(), 5);
+        assert_eq!(chain.iter_from(5).count(), 0);
+    }
+
+    #[test]
+    fn should_get_chain_tail() {
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        for created_at in 1000..1004 {
+            push_block(&mut chain, created_at, MIN_DIFFICULTY);
+        }
+        let indexes: Vec<u64> = chain.tail(3).unwrap().iter().map(|x| x.index()).collect();
+        assert_eq!(indexes, vec![2, 3, 4]);
+        assert!(chain.tail(0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_clamp_chain_tail_to_chain_length() {
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        push_block(&mut chain, 1000, MIN_DIFFICULTY);
+        let indexes: Vec<u64> = chain.tail(10).unwrap().iter().map(|x| x.index()).collect();
+        assert_eq!(indexes, vec![0, 1]);
+    }
+
+    #[test]
+    fn should_rebuild_corrupted_indexes() {
+        let database = BlockchainDatabase::in_memory();
+        let mut chain = Chain::from_database(database.clone()).unwrap();
+        push_block(&mut chain, 1000, MIN_DIFFICULTY);
+        let genesis_txid = chain.get_genesis_block().unwrap().transaction().id();
+        let txid = chain.get_latest_block().unwrap().transaction().id();
+        // corrupt index: point a transaction to the wrong block and index an unexisting one
+        database.put_transaction_block(&txid, 7).unwrap();
+        database.put_transaction_block("cafebabe", 1).unwrap();
+        assert_eq!(chain.transaction_block(&txid).unwrap(), Some(7));
+        assert!(chain.rebuild_indexes().is_ok());
+        assert_eq!(chain.transaction_block(&genesis_txid).unwrap(), Some(0));
+        assert_eq!(chain.transaction_block(&txid).unwrap(), Some(1));
+        assert_eq!(chain.contains_transaction("cafebabe").unwrap(), false);
+    }
+
+    /// Push a block created at `created_at` seconds from the UNIX epoch to the chain
+    fn push_block(chain: &mut Chain, created_at: u64, difficulty: u32) {
+        let block = next_block(chain, created_at, difficulty);
+        chain.add_block(block).unwrap();
+    }
+
+    /// Make the successor of the chain tip, created at `created_at` seconds from the UNIX epoch
+    fn next_block(chain: &Chain, created_at: u64, difficulty: u32) -> Block {
+        let previous_block = chain.get_latest_block().unwrap();
+        let index = previous_block.index() + 1;
+        Block::new(
+            index,
+            Header::new(
+                Version::V010,
+                Some(previous_block.header().merkle_root_hash().to_string()),
+                format!("block{}", index),
+                UNIX_EPOCH + Duration::from_secs(created_at),
+            )
+            .with_difficulty(difficulty),
+            TransactionBuilder::new(TransactionVersion::V1)
+                .input(GENESIS_BLOCK_ADDRESS, dec!(1.0))
+                .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(1.0))
+                .finish("aaa"),
+        )
+    }
+
+    /// Make block #`index` carrying `transaction`, linked to block #`index - 1` of `chain`, committing to the
+    /// transaction and mined with the difficulty computed for `index`
+    fn mined_block(chain: &Chain, index: u64, transaction: Transaction) -> Block {
+        let parent = chain.get_block(index - 1).unwrap().unwrap();
+        let header = Header::new(
+            Version::V010,
+            Some(parent.header().merkle_root_hash().to_string()),
+            chain.merkle_root_at(index, &transaction).unwrap(),
+            UNIX_EPOCH + Duration::from_secs(1000),
+        )
+        .with_difficulty(chain.difficulty_at(index).unwrap())
+        .mine()
+        .unwrap()
+        .unwrap();
+        Block::new(index, header, transaction)
+    }
+
+    /// Sign `builder` with the first signature giving it a lower id than `transaction`
+    fn preferred_transaction(
+        builder: TransactionBuilder,
+        transaction: &Transaction,
+    ) -> Transaction {
+        (0..)
+            .map(|n| builder.clone().finish(format!("sig{}", n)))
+            .find(|x| x.id() < transaction.id())
+            .unwrap()
+    }
+
+    /// Make the successor of the chain tip carrying `transaction`
+    fn block_with_transaction(chain: &Chain, transaction: Transaction) -> Block {
+        let block = next_block(chain, 1000, MIN_DIFFICULTY);
+        Block::new(block.index(), block.header().clone(), transaction)
+    }
+
+    #[test]
+    fn should_validate_block_sequence() {
+        let blocks = transfer_blocks(3);
+        assert!(Chain::validate_sequence(&blocks).is_ok());
+        assert!(Chain::validate_sequence(&blocks[..1]).is_ok());
+        assert!(Chain::validate_sequence(&[]).is_ok());
+    }
+
+    #[test]
+    fn should_reject_block_sequence_with_gap_or_bad_linkage() {
+        let blocks = transfer_blocks(3);
+        // gap
+        assert!(matches!(
+            Chain::validate_sequence(&[blocks[0].clone(), blocks[2].clone()]),
+            Err(BlockchainError::OrphanBlock(3, 1))
+        ));
+        // repeated index
+        assert!(matches!(
+            Chain::validate_sequence(&[blocks[0].clone(), blocks[0].clone()]),
+            Err(BlockchainError::InvalidBlock)
+        ));
+        // bad linkage
+        let unlinked = Block::new(
+            blocks[1].index(),
+            Header::new(
+                Version::V010,
+                Some("cafebabe".to_string()),
+                blocks[1].header().merkle_root_hash().to_string(),
+                blocks[1].header().created_at(),
+            ),
+            blocks[1].transaction().clone(),
+        );
+        assert!(matches!(
+            Chain::validate_sequence(&[blocks[0].clone(), unlinked, blocks[2].clone()]),
+            Err(BlockchainError::InvalidBlock)
+        ));
+    }
+
+    #[test]
+    fn should_reject_block_sequence_with_duplicate_transaction() {
+        let blocks = transfer_blocks(2);
+        // replay the transaction of the first block
+        let replay = Block::new(
+            3,
+            Header::new(
+                Version::V010,
+                Some(blocks[1].header().merkle_root_hash().to_string()),
+                String::from("block3"),
+                blocks[1].header().created_at(),
+            ),
+            blocks[0].transaction().clone(),
+        );
+        let txid = blocks[0].transaction().id();
+        assert!(matches!(
+            Chain::validate_sequence(&[blocks[0].clone(), blocks[1].clone(), replay]),
+            Err(BlockchainError::DuplicateTransaction(id)) if id == txid
+        ));
+        // coinbase transactions may be reissued
+        let coinbase = TransactionBuilder::new(TransactionVersion::V1)
+            .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", Decimal::ZERO)
+            .finish("");
+        let first = Block::new(
+            1,
+            Header::new(
+                Version::V010,
+                Some(String::from("block0")),
+                String::from("block1"),
+                UNIX_EPOCH,
+            ),
+            coinbase.clone(),
+        );
+        let second = Block::new(
+            2,
+            Header::new(
+                Version::V010,
+                Some(String::from("block1")),
+                String::from("block2"),
+                UNIX_EPOCH,
+            ),
+            coinbase,
+        );
+        assert!(Chain::validate_sequence(&[first, second]).is_ok());
+    }
+
+    #[test]
+    fn should_reject_added_blocks_with_duplicate_transaction() {
+        let blocks = transfer_blocks(2);
+        let replay = Block::new(
+            3,
+            Header::new(
+                Version::V010,
+                Some(blocks[1].header().merkle_root_hash().to_string()),
+                String::from("block3"),
+                blocks[1].header().created_at(),
+            ),
+            blocks[0].transaction().clone(),
+        );
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        let txid = blocks[0].transaction().id();
+        assert!(matches!(
+            chain.add_blocks(vec![blocks[0].clone(), blocks[1].clone(), replay]),
+            Err(BlockchainError::DuplicateTransaction(id)) if id == txid
+        ));
+        // nothing has been committed
+        assert_eq!(chain.get_latest_block().unwrap().index(), 0);
+        chain.add_blocks(blocks.clone()).unwrap();
+        assert_eq!(chain.get_latest_block().unwrap(), blocks[1]);
+    }
+
+    /// Make a chain with `n` blocks after the genesis, each one with a different transfer; returns those blocks
+    fn transfer_blocks(n: u64) -> Vec<Block> {
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        for amount in 1..=n {
+            let amount = Decimal::from(amount);
+            let transaction = TransactionBuilder::new(TransactionVersion::V1)
+                .input(GENESIS_BLOCK_ADDRESS, amount)
+                .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", amount)
+                .finish("aaa");
+            let block = block_with_transaction(&chain, transaction);
+            chain.add_block(block).unwrap();
+        }
+        chain.iter_from(1).map(|x| x.unwrap()).collect()
+    }
+
+    #[test]
+    fn should_validate_block_standalone() {
+        let chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        assert!(chain
+            .validate_block_standalone(&next_block(&chain, 1000, MIN_DIFFICULTY))
+            .is_ok());
+        // coinbase
+        let coinbase = TransactionBuilder::new(TransactionVersion::V1)
+            .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(1.0))
+            .finish("");
+        assert!(chain
+            .validate_block_standalone(&block_with_transaction(&chain, coinbase))
+            .is_ok());
+    }
+
+    #[test]
+    fn should_reject_block_without_merkle_root() {
+        let chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        let block = next_block(&chain, 1000, MIN_DIFFICULTY);
+        let block = Block::new(
+            block.index(),
+            Header::new(Version::V010, None, String::from("block1"), UNIX_EPOCH),
+            block.transaction().clone(),
+        );
+        assert!(matches!(
+            chain.validate_block_standalone(&block),
+            Err(BlockchainError::BadMerkleRoot)
+        ));
+    }
+
+    #[test]
+    fn should_reject_block_below_min_difficulty() {
+        let chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        assert!(matches!(
+            chain.validate_block_standalone(&next_block(&chain, 1000, 0)),
+            Err(BlockchainError::DifficultyNotMet(0))
+        ));
+    }
+
+    #[test]
+    fn should_reject_malformed_transactions() {
+        let chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        for transaction in [
+            // no outputs
+            TransactionBuilder::new(TransactionVersion::V1)
+                .input(GENESIS_BLOCK_ADDRESS, dec!(1.0))
+                .finish("aaa"),
+            // negative amount
+            TransactionBuilder::new(TransactionVersion::V1)
+                .input(GENESIS_BLOCK_ADDRESS, dec!(-1.0))
+                .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(-1.0))
+                .finish("aaa"),
+            // output not covered by inputs
+            TransactionBuilder::new(TransactionVersion::V1)
+                .input(GENESIS_BLOCK_ADDRESS, dec!(1.0))
+                .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(2.0))
+                .finish("aaa"),
+        ] {
+            assert!(matches!(
+                chain.validate_block_standalone(&block_with_transaction(&chain, transaction)),
+                Err(BlockchainError::MalformedTransaction(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn should_reject_unsigned_transaction() {
+        let chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        let transaction = TransactionBuilder::new(TransactionVersion::V1)
+            .input(GENESIS_BLOCK_ADDRESS, dec!(1.0))
+            .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(1.0))
+            .finish("");
+        assert!(matches!(
+            chain.validate_block_standalone(&block_with_transaction(&chain, transaction)),
+            Err(BlockchainError::MissingSignature)
+        ));
+    }
+
+    #[test]
+    fn should_reject_blocks_beyond_future_tolerance() {
+        let clock = MockClock::new(UNIX_EPOCH + Duration::from_secs(1000));
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory())
+            .unwrap()
+            .clock(clock)
+            .future_block_tolerance(Duration::from_secs(60));
+        assert!(matches!(
+            chain.add_block(next_block(&chain, 1061, MIN_DIFFICULTY)),
+            Err(BlockchainError::FutureBlock(1))
+        ));
+        assert!(chain
+            .add_block(next_block(&chain, 1060, MIN_DIFFICULTY))
+            .is_ok());
+    }
+
+    #[test]
+    fn should_raise_difficulty_on_fast_blocks() {
+        let tempdir = TempDir::new().expect("could not create tempfile");
+        let mut chain = Chain::try_from(tempdir.path())
+            .unwrap()
+            .block_time_target(Duration::from_secs(60))
+            .retarget_window(5);
+        for created_at in [1000, 1010, 1020, 1030, 1040] {
+            push_block(&mut chain, created_at, MIN_DIFFICULTY);
+        }
+        assert_eq!(chain.next_difficulty().unwrap(), 2);
+        // blocks must be mined with the computed difficulty
+        for difficulty in [MIN_DIFFICULTY, u32::MAX] {
+            assert!(matches!(
+                chain.add_block(next_block(&chain, 1050, difficulty)),
+                Err(BlockchainError::DifficultyNotMet(x)) if x == difficulty
+            ));
+        }
+        push_block(&mut chain, 1050, 2);
+        assert_eq!(chain.next_difficulty().unwrap(), 3);
+    }
+
+    #[test]
+    fn should_lower_difficulty_on_slow_blocks() {
+        let tempdir = TempDir::new().expect("could not create tempfile");
+        let mut chain = Chain::open(
+            tempdir.path(),
+            &GenesisConfig::default().initial_difficulty(2),
+        )
+        .unwrap()
+        .block_time_target(Duration::from_secs(60))
+        .retarget_window(5);
+        for created_at in [1000, 1120, 1240, 1360, 1480] {
+            push_block(&mut chain, created_at, 2);
+        }
+        assert_eq!(chain.next_difficulty().unwrap(), 1);
+        // difficulty can't go below minimum
+        push_block(&mut chain, 1600, MIN_DIFFICULTY);
+        assert_eq!(chain.next_difficulty().unwrap(), MIN_DIFFICULTY);
+    }
+
+    #[test]
+    fn should_use_initial_difficulty_before_first_retarget() {
+        let tempdir = TempDir::new().expect("could not create tempfile");
+        let mut chain = Chain::try_from(tempdir.path()).unwrap();
+        assert_eq!(chain.next_difficulty().unwrap(), MIN_DIFFICULTY);
+        push_block(&mut chain, 1000, MIN_DIFFICULTY);
+        assert_eq!(chain.next_difficulty().unwrap(), MIN_DIFFICULTY);
+    }
+
+    #[test]
+    fn should_commit_initial_difficulty_to_genesis() {
+        let harder_genesis = GenesisConfig::default().initial_difficulty(3);
+        assert_ne!(
+            Chain::genesis_block(&GenesisConfig::default()).id(),
+            Chain::genesis_block(&harder_genesis).id()
+        );
+        // a block linked to the default genesis doesn't extend a chain with another initial difficulty
+        let chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        let block = next_block(&chain, 1000, 3);
+        let tempdir = TempDir::new().expect("could not create tempfile");
+        let mut harder_chain = Chain::open(tempdir.path(), &harder_genesis).unwrap();
+        assert!(matches!(
+            harder_chain.add_block(block),
+            Err(BlockchainError::InvalidBlock)
+        ));
+    }
+
+    #[test]
+    fn should_validate_early_blocks_against_initial_difficulty() {
+        let tempdir = TempDir::new().expect("could not create tempfile");
+        let genesis = GenesisConfig::default().initial_difficulty(3);
+        let mut chain = Chain::open(tempdir.path(), &genesis)
+            .unwrap()
+            .block_time_target(Duration::from_secs(60))
+            .retarget_window(3);
+        assert_eq!(chain.get_genesis_block().unwrap().header().difficulty(), 3);
+        assert!(matches!(
+            chain.add_block(next_block(&chain, 1000, 2)),
+            Err(BlockchainError::DifficultyNotMet(2))
+        ));
+        // a higher difficulty is rejected as well, even without the chain headers
+        assert!(matches!(
+            chain.validate_block_standalone(&next_block(&chain, 1000, 4)),
+            Err(BlockchainError::DifficultyNotMet(4))
+        ));
+        for created_at in [1000, 1010, 1020] {
+            assert_eq!(chain.next_difficulty().unwrap(), 3);
+            push_block(&mut chain, created_at, 3);
+        }
+        // the retarget window is full: fast blocks raise the difficulty
+        assert_eq!(chain.next_difficulty().unwrap(), 4);
+        push_block(&mut chain, 1030, 4);
+        // the initial difficulty is read from the genesis block once the chain is reopened
+        drop(chain);
+        let chain = Chain::try_from(tempdir.path()).unwrap().retarget_window(10);
+        assert_eq!(chain.next_difficulty().unwrap(), 3);
+    }
+
+    #[test]
+    fn should_calc_average_block_time() {
+        let tempdir = TempDir::new().expect("could not create tempfile");
+        let mut chain = Chain::try_from(tempdir.path()).unwrap();
+        assert!(chain.average_block_time(10).unwrap().is_none());
+        push_block(&mut chain, 1000, MIN_DIFFICULTY);
+        assert!(chain.average_block_time(10).unwrap().is_none());
+        for created_at in [1010, 1030, 1060, 1100] {
+            push_block(&mut chain, created_at, MIN_DIFFICULTY);
+        }
+        // 1060 -> 1100
+        assert_eq!(
+            chain.average_block_time(2).unwrap(),
+            Some(Duration::from_secs(40))
+        );
+        // 1030 -> 1060 -> 1100
+        assert_eq!(
+            chain.average_block_time(3).unwrap(),
+            Some(Duration::from_secs(35))
+        );
+        // window larger than chain: 1000 -> 1100
+        assert_eq!(
+            chain.average_block_time(100).unwrap(),
+            Some(Duration::from_secs(25))
+        );
+        assert!(chain.average_block_time(1).unwrap().is_none());
+        assert!(chain.average_block_time(0).unwrap().is_none());
+    }
+
+    #[test]
+    fn should_accept_strict_successor() {
+        let tempdir = TempDir::new().expect("could not create tempfile");
+        let mut chain = Chain::try_from(tempdir.path()).unwrap();
+        push_block(&mut chain, 1000, MIN_DIFFICULTY);
+        assert_eq!(chain.get_latest_block().unwrap().index(), 1);
+    }
+
+    #[test]
+    fn should_reject_gap_creating_block() {
+        let tempdir = TempDir::new().expect("could not create tempfile");
+        let mut chain = Chain::try_from(tempdir.path()).unwrap();
+        let genesis_block = chain.get_genesis_block().unwrap();
+        let block = Block::new(
+            2,
+            Header::new(
+                Version::V010,
+                Some(genesis_block.header().merkle_root_hash().to_string()),
+                String::from("block2"),
+                SystemTime::now(),
+            ),
+            genesis_block.transaction().clone(),
+        );
+        assert!(matches!(
+            chain.add_block(block),
+            Err(BlockchainError::OrphanBlock(2, 0))
+        ));
+        assert!(chain.get_block(2).unwrap().is_none());
+    }
+
+    #[test]
+    fn should_reject_block_with_wrong_parent() {
+        let tempdir = TempDir::new().expect("could not create tempfile");
+        let mut chain = Chain::try_from(tempdir.path()).unwrap();
+        let block = Block::new(
+            1,
+            Header::new(
+                Version::V010,
+                Some(String::from("cafebabe")),
+                String::from("block1"),
+                SystemTime::now(),
+            ),
+            chain.get_genesis_block().unwrap().transaction().clone(),
+        );
+        assert!(matches!(
+            chain.add_block(block),
+            Err(BlockchainError::InvalidBlock)
+        ));
+    }
+
+    #[test]
+    fn should_timestamp_blocks_with_clock() {
+        let tempdir = TempDir::new().expect("could not create tempfile");
+        let clock = MockClock::new(UNIX_EPOCH + Duration::from_secs(1000));
+        let mut chain = Chain::try_from(tempdir.path())
+            .unwrap()
+            .clock(clock.clone());
+        for _ in 0..4 {
+            let transaction = TransactionBuilder::new(TransactionVersion::V1)
+                .input(GENESIS_BLOCK_ADDRESS, dec!(1.0))
+                .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(1.0))
+                .finish("aaa");
+            chain.generate_next_block(transaction).unwrap();
+            clock.advance(Duration::from_secs(30));
+        }
+        assert_eq!(
+            chain.get_block(1).unwrap().unwrap().header().created_at(),
+            UNIX_EPOCH + Duration::from_secs(1000)
+        );
+        assert_eq!(
+            chain.get_latest_block().unwrap().header().created_at(),
+            UNIX_EPOCH + Duration::from_secs(1090)
+        );
+        assert_eq!(
+            chain.average_block_time(10).unwrap(),
+            Some(Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn should_read_balance_concurrently_while_writing_blocks() {
+        let tempdir = TempDir::new().expect("could not create tempfile");
+        let mut chain = Chain::try_from(tempdir.path()).unwrap();
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let chain = chain.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..20 {
+                        let balance = chain.wallet_amount(GENESIS_BLOCK_ADDRESS).unwrap().unwrap();
+                        assert!(balance <= dec!(500.0));
+                        assert!(balance >= dec!(490.0));
+                    }
+                })
+            })
+            .collect();
+        for _ in 0..10 {
+            let transaction = TransactionBuilder::new(TransactionVersion::V1)
+                .input(GENESIS_BLOCK_ADDRESS, dec!(1.0))
+                .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(1.0))
+                .finish("aaa");
+            chain.generate_next_block(transaction).unwrap();
+        }
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(
+            chain.wallet_amount(GENESIS_BLOCK_ADDRESS).unwrap(),
+            Some(dec!(490.0))
+        );
+    }
+
+    #[test]
+    fn should_not_replace_genesis_block() {
+        let tempdir = TempDir::new().expect("could not create tempfile");
+        let mut chain = Chain::try_from(tempdir.path()).unwrap();
+        let genesis_block = chain.get_genesis_block().unwrap();
+        let fake_genesis = Block::new(
+            0,
+            Header::new(
+                Version::V010,
+                None,
+                String::from("cafebabe"),
+                SystemTime::now(),
+            ),
+            TransactionBuilder::new(TransactionVersion::V1)
+                .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(1000.0))
+                .finish("aaa"),
+        );
+        assert!(matches!(
+            chain.add_block(fake_genesis),
+            Err(BlockchainError::GenesisImmutable)
+        ));
+        // also with a longer chain
+        push_block(&mut chain, 1000, MIN_DIFFICULTY);
+        assert!(matches!(
+            chain.add_block(genesis_block.clone()),
+            Err(BlockchainError::GenesisImmutable)
+        ));
+        assert_eq!(chain.get_genesis_block().unwrap(), genesis_block);
+    }
+
+    #[test]
+    fn should_credit_genesis_allocations() {
+        let tempdir = TempDir::new().expect("could not create tempfile");
+        let genesis = GenesisConfig::default()
+            .allocation("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(100.0))
+            .allocation("jab8a3bd8e7e12b5f6bc8d5e3e3a0a2b7c1d0f3e9a4", dec!(25.5));
+        let chain = Chain::open(tempdir.path(), &genesis).unwrap();
+        assert_eq!(
+            chain.wallet_amount(GENESIS_BLOCK_ADDRESS).unwrap(),
+            Some(dec!(500.0))
+        );
+        assert_eq!(
+            chain
+                .wallet_amount("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1")
+                .unwrap(),
+            Some(dec!(100.0))
+        );
+        assert_eq!(
+            chain
+                .wallet_amount("jab8a3bd8e7e12b5f6bc8d5e3e3a0a2b7c1d0f3e9a4")
+                .unwrap(),
+            Some(dec!(25.5))
+        );
+    }
+
+    #[test]
+    fn should_grow_supply_by_reward_per_block() {
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        assert_eq!(chain.supply_at(0).unwrap(), dec!(500.0));
+        for _ in 0..3 {
+            let reward = TransactionBuilder::new(TransactionVersion::V1)
+                .output("miner", dec!(10.0))
+                .finish("");
+            let block = block_with_transaction(&chain, reward);
+            chain.add_block(block).unwrap();
+        }
+        // transfers don't change the supply
+        push_block(&mut chain, 1000, MIN_DIFFICULTY);
+        assert_eq!(chain.supply_at(1).unwrap(), dec!(510.0));
+        assert_eq!(chain.supply_at(2).unwrap(), dec!(520.0));
+        assert_eq!(chain.supply_at(3).unwrap(), dec!(530.0));
+        assert_eq!(chain.supply_at(4).unwrap(), dec!(530.0));
+        // cross-check against the sum of balances
+        let balances: Decimal = [
+            GENESIS_BLOCK_ADDRESS,
+            "miner",
+            "jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1",
+        ]
+        .iter()
+        .map(|addr| chain.wallet_amount(addr).unwrap().unwrap())
+        .sum();
+        assert_eq!(balances, dec!(530.0));
+        assert!(matches!(
+            chain.supply_at(5),
+            Err(BlockchainError::BlockNotFound(5))
+        ));
+        // the first missing block is reported
+        assert!(matches!(
+            chain.supply_at(7),
+            Err(BlockchainError::BlockNotFound(5))
+        ));
+    }
+
+    #[test]
+    fn should_fail_wallet_scan_exceeding_limit() {
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory())
+            .unwrap()
+            .max_scan_blocks(2);
+        push_block(&mut chain, 1000, MIN_DIFFICULTY);
+        // block validation is not bounded by the scan limit
+        push_block(&mut chain, 1001, MIN_DIFFICULTY);
+        let reader = chain.reader();
+        assert!(matches!(
+            reader.wallet_amount(GENESIS_BLOCK_ADDRESS),
+            Err(BlockchainError::ScanLimitExceeded(2))
+        ));
+        assert!(matches!(
+            reader.wallet_transactions(GENESIS_BLOCK_ADDRESS),
+            Err(BlockchainError::ScanLimitExceeded(2))
+        ));
+        // the local balance lookup is not bounded either
+        assert_eq!(
+            chain.wallet_amount(GENESIS_BLOCK_ADDRESS).unwrap(),
+            Some(dec!(498.0))
+        );
+        // a limit covering the whole chain completes the scan
+        let reader = chain.max_scan_blocks(3).reader();
+        assert_eq!(
+            reader.wallet_amount(GENESIS_BLOCK_ADDRESS).unwrap(),
+            Some(dec!(498.0))
+        );
+        assert_eq!(
+            reader
+                .wallet_transactions(GENESIS_BLOCK_ADDRESS)
+                .unwrap()
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn should_get_genesis_address() {
+        let chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        assert_eq!(chain.genesis_address(), GENESIS_BLOCK_ADDRESS);
+        // allocations don't replace the genesis wallet
+        let tempdir = TempDir::new().unwrap();
+        let genesis = GenesisConfig::default()
+            .allocation("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(100.0));
+        let chain = Chain::open(tempdir.path(), &genesis).unwrap();
+        assert_eq!(chain.genesis_address(), GENESIS_BLOCK_ADDRESS);
+        // reopening the database reads the address from the genesis block
+        drop(chain);
+        let chain = Chain::try_from(tempdir.path()).unwrap();
+        assert_eq!(chain.genesis_address(), GENESIS_BLOCK_ADDRESS);
+    }
+
+    #[test]
+    fn should_audit_wallet() {
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        let alice = "jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1";
+        for (input, output, amount) in [
+            (GENESIS_BLOCK_ADDRESS, alice, dec!(100.0)),
+            (alice, GENESIS_BLOCK_ADDRESS, dec!(30.0)),
+            (GENESIS_BLOCK_ADDRESS, alice, dec!(5.5)),
+        ] {
+            let transaction = TransactionBuilder::new(TransactionVersion::V1)
+                .input(input, amount)
+                .output(output, amount)
+                .finish("aaa");
+            let block = block_with_transaction(&chain, transaction);
+            chain.add_block(block).unwrap();
+        }
+        let ledger = chain.audit_wallet(alice).unwrap();
+        let entries: Vec<(u64, Decimal, Decimal)> = ledger
+            .iter()
+            .map(|x| (x.index, x.delta, x.balance))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                (1, dec!(100.0), dec!(100.0)),
+                (2, dec!(-30.0), dec!(70.0)),
+                (3, dec!(5.5), dec!(75.5)),
+            ]
+        );
+        assert_eq!(
+            ledger[1].txid,
+            chain.get_block(2).unwrap().unwrap().transaction().id()
+        );
+        // running balance matches the wallet amount
+        for addr in [alice, GENESIS_BLOCK_ADDRESS] {
+            assert_eq!(
+                chain.audit_wallet(addr).unwrap().last().map(|x| x.balance),
+                chain.wallet_amount(addr).unwrap()
+            );
+        }
+        assert!(chain.audit_wallet("jabunknown").unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_include_genesis_allocations_in_supply() {
+        let genesis = GenesisConfig::default()
+            .allocation("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(100.0));
+        let tempdir = TempDir::new().expect("could not create tempfile");
+        let chain = Chain::open(tempdir.path(), &genesis).unwrap();
+        assert_eq!(chain.supply_at(0).unwrap(), dec!(600.0));
+    }
+
+    #[test]
+    fn should_reject_database_path_pointing_to_a_file() {
+        let tempdir = TempDir::new().expect("could not create tempfile");
+        let path = tempdir.path().join("db");
+        std::fs::write(&path, b"not a database").unwrap();
+        assert!(matches!(
+            Chain::try_from(path.as_path()),
+            Err(BlockchainError::InvalidDatabasePath(p)) if p == path
+        ));
+    }
+
+    #[test]
+    fn should_read_latest_header_only() {
+        let database = BlockchainDatabase::in_memory();
+        let mut chain = Chain::from_database(database.clone()).unwrap();
+        assert_eq!(
+            &chain.latest_header().unwrap(),
+            chain.get_genesis_block().unwrap().header()
+        );
+        push_block(&mut chain, 1000, MIN_DIFFICULTY);
+        push_block(&mut chain, 1060, MIN_DIFFICULTY);
+        assert_eq!(
+            &chain.latest_header().unwrap(),
+            chain.get_latest_block().unwrap().header()
+        );
+        // headers dropped from the index are read again from the blocks
+        database.clear_header_index().unwrap();
+        assert_eq!(
+            &chain.latest_header().unwrap(),
+            chain.get_latest_block().unwrap().header()
+        );
+        assert_eq!(
+            chain.latest_header().unwrap().created_at(),
+            UNIX_EPOCH + Duration::from_secs(1060)
+        );
+    }
+
+    #[test]
+    fn should_find_tip_at_any_height() {
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        for created_at in 1000..1010 {
+            push_block(&mut chain, created_at, MIN_DIFFICULTY);
+            let (index, header) = chain.tip_header().unwrap();
+            assert_eq!(index, created_at - 999);
+            assert_eq!(
+                header.created_at(),
+                UNIX_EPOCH + Duration::from_secs(created_at)
+            );
+            assert_eq!(chain.get_latest_block().unwrap().index(), index);
+        }
+    }
+
+    #[test]
+    fn should_build_genesis_block_matching_test_vector() {
+        // consensus critical: any change to these values makes the node incompatible with the existing networks
+        let genesis = Chain::genesis_block(&GenesisConfig::default());
+        assert_eq!(genesis.index(), 0);
+        assert_eq!(genesis.header().created_at(), UNIX_EPOCH);
+        assert_eq!(genesis.header().previous_block_header_hash(), None);
+        assert_eq!(genesis.header().difficulty(), MIN_DIFFICULTY);
+        assert_eq!(
+            hex::encode(genesis.transaction().digest()),
+            "2dca30c02986658a58044124c1efe6080223546b599f44e9009c362a5e686298"
+        );
+        assert_eq!(
+            genesis.transaction().id(),
+            "a7c696925baaaaa090dde2c802a356ca9ae29dd60b070db31d1879d846695727"
+        );
+        assert_eq!(
+            genesis.header().merkle_root_hash(),
+            "5e86c1743dcdda95c450f1f7804ad009f69cd7819a0236afc37e1fe9055fd437"
+        );
+        assert_eq!(
+            genesis.id(),
+            "5e86c1743dcdda95c450f1f7804ad009f69cd7819a0236afc37e1fe9055fd437"
+        );
+    }
+}
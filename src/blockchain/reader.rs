@@ -0,0 +1,91 @@
+//! # Reader
+//!
+//! This module exposes a read-only handle to the blockchain
+
+use super::{Block, BlockchainResult, Chain, Transaction};
+
+use rust_decimal::Decimal;
+
+/// A read-only handle to the blockchain.
+///
+/// It shares the database of the `Chain` it has been obtained from, so it can be moved to other threads
+/// (e.g. block serving and wallet query workers) and query the chain while the owner keeps writing blocks.
+/// Reads see each block either entirely or not at all (see `Chain` concurrency model)
+#[derive(Clone)]
+pub struct ChainReader {
+    chain: Chain,
+}
+
+impl From<Chain> for ChainReader {
+    fn from(chain: Chain) -> Self {
+        Self { chain }
+    }
+}
+
+impl ChainReader {
+    /// Get genesis block (first block in the blockchain)
+    pub fn get_genesis_block(&self) -> BlockchainResult<Block> {
+        self.chain.get_genesis_block()
+    }
+
+    /// Get block #`index`
+    pub fn get_block(&self, index: u64) -> BlockchainResult<Option<Block>> {
+        self.chain.get_block(index)
+    }
+
+    /// Get index of the latest block
+    pub fn height(&self) -> BlockchainResult<u64> {
+        self.chain.tip_header().map(|(index, _)| index)
+    }
+
+    /// Get current jab amount for provided wallet (see `Chain::wallet_amount`).
+    /// Fails with `ScanLimitExceeded` if the chain is longer than the maximum amount of scanned blocks
+    pub fn wallet_amount(&self, addr: &str) -> BlockchainResult<Option<Decimal>> {
+        self.chain
+            .scan_wallet_amount(addr, self.chain.max_scan_blocks)
+    }
+
+    /// Collect transactions for wallet with provided address (see `Chain::wallet_transactions`).
+    /// Fails with `ScanLimitExceeded` if the chain is longer than the maximum amount of scanned blocks
+    pub fn wallet_transactions(&self, addr: &str) -> BlockchainResult<Option<Vec<Transaction>>> {
+        self.chain
+            .scan_wallet_transactions(addr, self.chain.max_scan_blocks)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::blockchain::{BlockchainDatabase, TransactionBuilder, TransactionVersion};
+
+    use pretty_assertions::assert_eq;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn should_read_chain_owned_by_another_thread() {
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        let reader = chain.reader();
+        let genesis_address = chain.genesis_address().to_string();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let worker = std::thread::spawn(move || {
+            // wait for the owner to write a block
+            receiver.recv().unwrap();
+            (
+                reader.height().unwrap(),
+                reader.get_block(1).unwrap(),
+                reader.wallet_amount(&genesis_address).unwrap(),
+            )
+        });
+        let transaction = TransactionBuilder::new(TransactionVersion::V1)
+            .input(chain.genesis_address(), dec!(1.0))
+            .output("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1", dec!(1.0))
+            .finish("aaa");
+        let block = chain.generate_next_block(transaction).unwrap();
+        sender.send(()).unwrap();
+        let (height, read_block, amount) = worker.join().unwrap();
+        assert_eq!(height, 1);
+        assert_eq!(read_block, Some(block));
+        assert_eq!(amount, Some(dec!(499.0)));
+    }
+}
@@ -4,12 +4,15 @@
 
 pub use db_key::Key;
 use leveldb::{
+    batch::{Batch, Writebatch},
     database::Database,
     error::Error as DbError,
+    iterator::Iterable,
     kv::KV,
     options::{Options, ReadOptions, WriteOptions},
 };
 use std::path::Path;
+use std::sync::Arc;
 use thiserror::Error;
 
 /// The result type returned by an operation on the database
@@ -28,9 +31,18 @@ impl From<DbError> for LevelDbError {
     }
 }
 
-/// a bridge to operate on a levelDB
+/// a bridge to operate on a levelDB.
+/// Cloning the bridge returns a new handle to the same database
 pub struct LevelDbBridge<K: Key> {
-    database: Database<K>,
+    database: Arc<Database<K>>,
+}
+
+impl<K: Key> Clone for LevelDbBridge<K> {
+    fn clone(&self) -> Self {
+        Self {
+            database: self.database.clone(),
+        }
+    }
 }
 
 impl<K> LevelDbBridge<K>
@@ -45,7 +57,9 @@ where
         let mut options = Options::new();
         options.create_if_missing = true;
         Database::open(path.as_ref(), options)
-            .map(|x| Self { database: x })
+            .map(|x| Self {
+                database: Arc::new(x),
+            })
             .map_err(LevelDbError::from)
     }
 
@@ -69,6 +83,26 @@ where
             .delete(WriteOptions::new(), key)
             .map_err(LevelDbError::from)
     }
+
+    /// Delete all the keys from database
+    pub fn clear(&self) -> LevelDbResult<()> {
+        let mut batch = Writebatch::new();
+        for key in self.database.keys_iter(ReadOptions::new()) {
+            batch.delete(key);
+        }
+        self.database
+            .write(WriteOptions::new(), &batch)
+            .map_err(LevelDbError::from)
+    }
+
+    /// Force pending writes to disk, by writing an empty batch with `sync` enabled
+    pub fn flush(&self) -> LevelDbResult<()> {
+        let mut options = WriteOptions::new();
+        options.sync = true;
+        self.database
+            .write(options, &Writebatch::new())
+            .map_err(LevelDbError::from)
+    }
 }
 
 #[cfg(test)]
@@ -96,6 +130,30 @@ mod test {
         assert!(database.get(10).unwrap().is_none());
     }
 
+    #[test]
+    fn should_flush_database() {
+        let tempdir = TempDir::new().expect("could not create tempfile");
+        let path = tempdir.path();
+        let database: LevelDbBridge<i32> = LevelDbBridge::init(path).unwrap();
+        assert!(database.put(30, &[0x01]).is_ok());
+        assert!(database.flush().is_ok());
+        drop(database);
+        let database: LevelDbBridge<i32> = LevelDbBridge::init(path).unwrap();
+        assert_eq!(database.get(30).unwrap().unwrap(), vec![0x01]);
+    }
+
+    #[test]
+    fn should_clear_database() {
+        let tempdir = TempDir::new().expect("could not create tempfile");
+        let path = tempdir.path();
+        let database: LevelDbBridge<i32> = LevelDbBridge::init(path).unwrap();
+        assert!(database.put(30, &[0x01]).is_ok());
+        assert!(database.put(10, &[0x02]).is_ok());
+        assert!(database.clear().is_ok());
+        assert!(database.get(30).unwrap().is_none());
+        assert!(database.get(10).unwrap().is_none());
+    }
+
     #[test]
     fn should_delete_key_from_database() {
         let tempdir = TempDir::new().expect("could not create tempfile");
@@ -0,0 +1,120 @@
+//! # Memory
+//!
+//! a bridge to an in-memory key-value store, mirroring the leveldb bridge. Useful for tests
+
+#[cfg(test)]
+use super::leveldb::LevelDbError;
+use super::leveldb::{Key, LevelDbResult};
+
+#[cfg(test)]
+use leveldb::error::Error as DbError;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+#[cfg(test)]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// An in-memory key-value store.
+/// Cloning the bridge returns a new handle to the same store
+pub struct MemoryBridge<K: Key> {
+    store: Arc<RwLock<HashMap<Vec<u8>, Vec<u8>>>>,
+    /// Amount of the next reads which fail, to simulate transient database errors
+    #[cfg(test)]
+    read_failures: Arc<AtomicUsize>,
+    key: PhantomData<K>,
+}
+
+impl<K: Key> Clone for MemoryBridge<K> {
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            #[cfg(test)]
+            read_failures: self.read_failures.clone(),
+            key: PhantomData,
+        }
+    }
+}
+
+impl<K: Key> Default for MemoryBridge<K> {
+    fn default() -> Self {
+        Self {
+            store: Arc::new(RwLock::new(HashMap::new())),
+            #[cfg(test)]
+            read_failures: Arc::new(AtomicUsize::new(0)),
+            key: PhantomData,
+        }
+    }
+}
+
+impl<K> MemoryBridge<K>
+where
+    K: Key,
+{
+    /// Put key and value into the store
+    pub fn put(&self, key: K, value: &[u8]) {
+        let key = key.as_slice(|x| x.to_vec());
+        self.store.write().unwrap().insert(key, value.to_vec());
+    }
+
+    /// Get `key` from store. Fails only if a failure has been injected with `fail_next_reads`
+    pub fn get(&self, key: K) -> LevelDbResult<Option<Vec<u8>>> {
+        #[cfg(test)]
+        if self
+            .read_failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |x| x.checked_sub(1))
+            .is_ok()
+        {
+            return Err(LevelDbError::from(DbError::new(String::from(
+                "injected read failure",
+            ))));
+        }
+        Ok(key.as_slice(|x| self.store.read().unwrap().get(x).cloned()))
+    }
+
+    #[cfg(test)]
+    /// Make the next `n` reads fail, to simulate a transient database error
+    pub fn fail_next_reads(&self, n: usize) {
+        self.read_failures.store(n, Ordering::SeqCst);
+    }
+
+    /// Delete `key` from store
+    pub fn delete(&self, key: K) {
+        key.as_slice(|x| self.store.write().unwrap().remove(x));
+    }
+
+    /// Delete all the keys from store
+    pub fn clear(&self) {
+        self.store.write().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_put_get_and_delete_keys() {
+        let store: MemoryBridge<i32> = MemoryBridge::default();
+        store.put(30, &[0x01]);
+        assert_eq!(store.clone().get(30).unwrap().unwrap(), vec![0x01]);
+        assert!(store.get(10).unwrap().is_none());
+        store.delete(30);
+        assert!(store.get(30).unwrap().is_none());
+        store.put(10, &[0x02]);
+        store.clear();
+        assert!(store.get(10).unwrap().is_none());
+    }
+
+    #[test]
+    fn should_fail_injected_reads() {
+        let store: MemoryBridge<i32> = MemoryBridge::default();
+        store.put(30, &[0x01]);
+        store.fail_next_reads(2);
+        assert!(store.get(30).is_err());
+        assert!(store.clone().get(30).is_err());
+        assert_eq!(store.get(30).unwrap().unwrap(), vec![0x01]);
+    }
+}
@@ -3,3 +3,4 @@
 //! this module exposes all the bridge to interface with external database / api
 
 pub mod leveldb;
+pub mod memory;
@@ -0,0 +1,116 @@
+//! # Location
+//!
+//! Layout of the wallets stored in a directory. Each wallet is identified by a name, and its keys are
+//! stored in `{name}.pub` and `.{name}.key`
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the wallet used when no name is provided
+pub const DEFAULT_WALLET_NAME: &str = "jab";
+const PUBLIC_KEY_EXTENSION: &str = "pub";
+const SECRET_KEY_EXTENSION: &str = "key";
+
+/// Location of a named wallet inside a directory
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct WalletLocation {
+    dir: PathBuf,
+    name: String,
+}
+
+impl WalletLocation {
+    /// Instantiate a new `WalletLocation` for the wallet `name` in `dir`.
+    /// If no name is provided, `DEFAULT_WALLET_NAME` is used
+    pub fn new(dir: &Path, name: Option<&str>) -> anyhow::Result<Self> {
+        let name = name.unwrap_or(DEFAULT_WALLET_NAME);
+        if name.is_empty() || name.starts_with('.') || name.contains(|c| c == '/' || c == '\\') {
+            anyhow::bail!("invalid wallet name `{}`", name);
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            name: name.to_string(),
+        })
+    }
+
+    /// Get the directory containing the wallet
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Get the wallet name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the path of the public key file
+    pub fn public_key_path(&self) -> PathBuf {
+        self.dir
+            .join(format!("{}.{}", self.name, PUBLIC_KEY_EXTENSION))
+    }
+
+    /// Get the path of the secret key file
+    pub fn secret_key_path(&self) -> PathBuf {
+        self.dir
+            .join(format!(".{}.{}", self.name, SECRET_KEY_EXTENSION))
+    }
+
+    /// List the names of the wallets stored in `dir`, sorted by name
+    pub fn list(dir: &Path) -> anyhow::Result<Vec<String>> {
+        let entries = fs::read_dir(dir)
+            .map_err(|e| anyhow::anyhow!("could not read directory {}: {}", dir.display(), e))?;
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_file())
+            .filter_map(|entry| {
+                entry
+                    .file_name()
+                    .to_str()
+                    .and_then(|x| x.strip_prefix('.'))
+                    .and_then(|x| x.strip_suffix(&format!(".{}", SECRET_KEY_EXTENSION)))
+                    .filter(|x| !x.is_empty())
+                    .map(String::from)
+            })
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_use_default_wallet_layout() {
+        let location = WalletLocation::new(Path::new("/tmp/wallet"), None).unwrap();
+        assert_eq!(location.name(), "jab");
+        assert_eq!(location.public_key_path(), Path::new("/tmp/wallet/jab.pub"));
+        assert_eq!(
+            location.secret_key_path(),
+            Path::new("/tmp/wallet/.jab.key")
+        );
+    }
+
+    #[test]
+    fn should_use_named_wallet_layout() {
+        let location = WalletLocation::new(Path::new("/tmp/wallet"), Some("alice")).unwrap();
+        assert_eq!(
+            location.public_key_path(),
+            Path::new("/tmp/wallet/alice.pub")
+        );
+        assert_eq!(
+            location.secret_key_path(),
+            Path::new("/tmp/wallet/.alice.key")
+        );
+    }
+
+    #[test]
+    fn should_reject_bad_wallet_names() {
+        assert!(WalletLocation::new(Path::new("/tmp"), Some("")).is_err());
+        assert!(WalletLocation::new(Path::new("/tmp"), Some("../alice")).is_err());
+        assert!(WalletLocation::new(Path::new("/tmp"), Some(".alice")).is_err());
+    }
+}
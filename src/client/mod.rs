@@ -0,0 +1,864 @@
+//! # Client application
+//!
+//! This module exposes the main client application
+
+mod location;
+mod rebroadcast;
+
+pub use location::WalletLocation;
+use rebroadcast::{Confirmation, PendingTransaction, RebroadcastPolicy};
+
+use std::path::Path;
+
+use crate::application::{AdminRequest, AdminServer, DEFAULT_ADMIN_PORT};
+use crate::mode::start_node;
+use crate::Args;
+
+use futures::StreamExt;
+use jab::blockchain::{Chain, Transaction, TransactionBuilder, TransactionVersion};
+use jab::net::{
+    message::{
+        WalletBalance, WalletQueryResult, WalletTransactions, WalletTransactionsPage,
+        WalletTransactionsPages,
+    },
+    Msg, Node, NodeConfig,
+};
+use jab::wallet::{Wallet, Zeroizing, SECRET_KEY_SIZE};
+pub use libp2p::swarm::SwarmEvent;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use std::fmt;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::debug;
+
+/// Interval between two attempts to publish a message which no peer has received yet
+const PUBLISH_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+/// Time the tips of other nodes are collected for, after the first one, to report the sync status
+const TIP_COLLECTION_WINDOW: Duration = Duration::from_secs(2);
+
+/// Defines the task to run in the client app
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Task {
+    GenerateNewWallet,
+    GenerateOfflineWallet,
+    Register,
+    SignGenesisBlock,
+    GetBalance,
+    GetBalanceFor(String),
+    Send,
+    Status,
+    Peers,
+    ListWallets,
+    #[cfg(feature = "dev")]
+    Faucet(String, Decimal),
+    None,
+}
+
+/// Node and chain summary printed by the `Status` task
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct StatusReport {
+    /// Index of the latest block known by the network
+    pub height: u64,
+    /// Merkle root hash of the latest block
+    pub tip_hash: String,
+    /// Amount of peers we're connected to
+    pub peers: usize,
+    /// Whether the nodes which replied agree on the latest block
+    pub sync: SyncStatus,
+    /// Wallet address
+    pub address: String,
+    /// Wallet balance
+    pub balance: Decimal,
+}
+
+impl fmt::Display for StatusReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "height: {}", self.height)?;
+        writeln!(f, "tip hash: {}", self.tip_hash)?;
+        writeln!(f, "peers: {}", self.peers)?;
+        writeln!(f, "sync: {}", self.sync)?;
+        writeln!(f, "wallet: {}", self.address)?;
+        write!(f, "balance: {} JAB", self.balance)
+    }
+}
+
+/// Sync status of the network, according to the tips reported by the nodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncStatus {
+    /// All the nodes report the same height
+    Synced,
+    /// `behind` out of `nodes` nodes are behind the highest tip
+    Syncing { behind: usize, nodes: usize },
+}
+
+impl SyncStatus {
+    /// Get the sync status from the heights reported by the nodes
+    pub fn from_heights(heights: &[u64]) -> Self {
+        let highest = heights.iter().copied().max().unwrap_or_default();
+        match heights.iter().filter(|x| **x < highest).count() {
+            0 => Self::Synced,
+            behind => Self::Syncing {
+                behind,
+                nodes: heights.len(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for SyncStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Synced => write!(f, "synced"),
+            Self::Syncing { behind, nodes } => {
+                write!(f, "syncing ({} of {} nodes behind)", behind, nodes)
+            }
+        }
+    }
+}
+
+pub struct App;
+
+impl App {
+    /// run client wallet
+    pub async fn run(task: Task, args: Args) -> anyhow::Result<()> {
+        let dir = args
+            .wallet
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("a wallet path must be provided with `-w`"))?;
+        let location = WalletLocation::new(dir, args.wallet_name.as_deref())?;
+        let policy = Self::rebroadcast_policy(&args);
+        match task {
+            Task::GenerateNewWallet => Self::generate_new_wallet(&location, policy).await,
+            Task::GenerateOfflineWallet => Self::generate_offline_wallet(&location),
+            Task::Register => Self::register(&location, policy).await,
+            Task::GetBalance => Self::get_balance(&location).await,
+            Task::GetBalanceFor(addr) => Self::get_balance_for(&addr).await,
+            Task::Send => Self::send(&location, policy).await,
+            Task::SignGenesisBlock => Self::sign_genesis_block(&location),
+            Task::Status => Self::status(&location).await,
+            Task::Peers => Self::peers(args.admin_port.unwrap_or(DEFAULT_ADMIN_PORT)).await,
+            Task::ListWallets => Self::list_wallets(dir),
+            #[cfg(feature = "dev")]
+            Task::Faucet(addr, amount) => Self::faucet(&location, &addr, amount, policy).await,
+            Task::None => Ok(()),
+        }
+    }
+
+    /// Get the policy used to broadcast again unconfirmed transactions
+    fn rebroadcast_policy(args: &Args) -> RebroadcastPolicy {
+        let default = RebroadcastPolicy::default();
+        RebroadcastPolicy {
+            interval: args
+                .rebroadcast_interval
+                .map(Duration::from_secs)
+                .unwrap_or(default.interval),
+            timeout: args
+                .confirmation_timeout
+                .map(Duration::from_secs)
+                .unwrap_or(default.timeout),
+        }
+    }
+
+    /// generate new wallet for client and register it on the blockchain
+    async fn generate_new_wallet(
+        location: &WalletLocation,
+        policy: RebroadcastPolicy,
+    ) -> anyhow::Result<()> {
+        let wallet = Self::create_wallet(location)?;
+        Self::publish_registration(&wallet, policy).await?;
+        println!(
+            "created new wallet {} at {}",
+            location.name(),
+            location.dir().display()
+        );
+        println!("your address is: {}", wallet.address());
+        Ok(())
+    }
+
+    /// generate new wallet for client without connecting to the network.
+    /// The wallet can be registered later with the `Register` task
+    fn generate_offline_wallet(location: &WalletLocation) -> anyhow::Result<()> {
+        let wallet = Self::create_wallet(location)?;
+        println!(
+            "created new wallet {} at {}",
+            location.name(),
+            location.dir().display()
+        );
+        println!("your address is: {}", wallet.address());
+        println!("the wallet is not registered yet; run with `--register` to register it");
+        Ok(())
+    }
+
+    /// Register the wallet at `location` on the blockchain
+    async fn register(location: &WalletLocation, policy: RebroadcastPolicy) -> anyhow::Result<()> {
+        let wallet = Self::open_wallet(location)?;
+        Self::publish_registration(&wallet, policy).await?;
+        println!("registered wallet {}", wallet.address());
+        Ok(())
+    }
+
+    /// Generate a new wallet and write its keys to `location`
+    fn create_wallet(location: &WalletLocation) -> anyhow::Result<Wallet> {
+        let wallet = Wallet::new();
+        debug!("generated new wallet with address {}", wallet.address());
+        // create directory
+        let dir = location.dir();
+        if let Err(err) = fs::create_dir_all(dir) {
+            anyhow::bail!("could not create directory at {}: {}", dir.display(), err);
+        }
+        debug!("created wallet directories");
+        // write keys; existing keys are never overwritten
+        Self::write_key(&location.secret_key_path(), wallet.secret_key().as_slice())?;
+        Self::write_key(&location.public_key_path(), wallet.public_key().as_bytes())?;
+        debug!("written keys to {}", dir.display());
+        Ok(wallet)
+    }
+
+    /// Print the names of the wallets stored in `dir`
+    fn list_wallets(dir: &Path) -> anyhow::Result<()> {
+        for name in WalletLocation::list(dir)? {
+            let location = WalletLocation::new(dir, Some(&name))?;
+            match Self::open_wallet(&location) {
+                Ok(wallet) => println!("{} {}", name, wallet.address()),
+                Err(err) => println!("{} (unreadable: {})", name, err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Publish the registration transaction for `wallet` to the blockchain
+    async fn publish_registration(
+        wallet: &Wallet,
+        policy: RebroadcastPolicy,
+    ) -> anyhow::Result<()> {
+        let transaction = Self::make_transaction(wallet, wallet.address(), Decimal::ZERO)?;
+        debug!("prepared wallet registration transaction");
+        let mut node = Self::start_p2p_node().await?;
+        Self::publish_transaction(
+            &mut node,
+            transaction,
+            Decimal::ZERO,
+            wallet.public_key(),
+            policy,
+        )
+        .await
+    }
+
+    /// Get balance for this wallet
+    async fn get_balance(location: &WalletLocation) -> anyhow::Result<()> {
+        let wallet = Self::open_wallet(location)?;
+        Self::get_balance_for(wallet.address()).await
+    }
+
+    /// Get balance for provided address
+    async fn get_balance_for(address: &str) -> anyhow::Result<()> {
+        debug!("getting balance for {}", address);
+        let mut node = Self::start_p2p_node().await?;
+        let (balance, transactions) = Self::publish_get_balance(&mut node, address).await?;
+        for transaction in transactions.into_iter() {
+            for input in transaction
+                .inputs()
+                .iter()
+                .filter(|x| x.address.as_str() == address)
+            {
+                println!("SPENT {} JAB", input.amount);
+            }
+            for output in transaction
+                .outputs()
+                .iter()
+                .filter(|x| x.address.as_str() == address)
+            {
+                println!("RECEIVED {} JAB", output.amount);
+            }
+        }
+        println!("wallet amount for {}: {}", address, balance);
+        Ok(())
+    }
+
+    /// Send money from this wallet to another
+    async fn send(location: &WalletLocation, policy: RebroadcastPolicy) -> anyhow::Result<()> {
+        let wallet = Self::open_wallet(location)?;
+        // ask for receiver wallet
+        println!("Enter recipient wallet :");
+        let mut recipient = String::new();
+        std::io::stdin().read_line(&mut recipient).unwrap();
+        // ask amount to send
+        println!("Enter amount to send :");
+        let mut amount = String::new();
+        std::io::stdin().read_line(&mut amount).unwrap();
+        let amount =
+            Decimal::from_str(amount.trim()).map_err(|e| anyhow::anyhow!("bad amount: {}", e))?;
+        debug!("sending {} to {}", amount, recipient);
+        // send
+        let transaction = Self::make_transaction(&wallet, recipient.trim(), amount)?;
+        let mut node = Self::start_p2p_node().await?;
+        Self::publish_transaction(&mut node, transaction, amount, wallet.public_key(), policy)
+            .await?;
+        println!("sent {} to {}", amount, recipient);
+        Ok(())
+    }
+
+    /// Print a summary of the chain and of this wallet
+    async fn status(location: &WalletLocation) -> anyhow::Result<()> {
+        let wallet = Self::open_wallet(location)?;
+        let mut node = Self::start_p2p_node().await?;
+        // query balance first, since it waits for a connection to be established
+        let balance = Self::publish_get_wallet_balance(&mut node, wallet.address()).await?;
+        let peers = node.swarm.connected_peers().count();
+        debug!("connected to {} peers; looking for chain tip", peers);
+        let get_tip = Msg::get_tip(node.id());
+        let tip_reply = |msg: Msg| match msg {
+            Msg::Tip(tip) => Some(tip),
+            _ => None,
+        };
+        let mut tips = vec![Self::wait_for_reply(&mut node, get_tip, tip_reply).await?];
+        // wait for the other nodes, to compare their tips
+        tips.extend(Self::collect_replies(&mut node, TIP_COLLECTION_WINDOW, tip_reply).await);
+        let heights: Vec<u64> = tips.iter().map(|x| x.index).collect();
+        let sync = SyncStatus::from_heights(&heights);
+        // unwrap is safe, since at least a tip has been received
+        let tip = tips.into_iter().max_by_key(|x| x.index).unwrap();
+        let report = StatusReport {
+            height: tip.index,
+            tip_hash: tip.hash,
+            peers,
+            sync,
+            address: wallet.address().to_string(),
+            balance,
+        };
+        println!("{}", report);
+        Ok(())
+    }
+
+    /// Print the peers connected to the local node, querying its admin endpoint at `port`
+    async fn peers(port: u16) -> anyhow::Result<()> {
+        let response =
+            AdminServer::request((Ipv4Addr::LOCALHOST, port).into(), AdminRequest::Peers)
+                .await
+                .map_err(|e| anyhow::anyhow!("could not query the local node: {}", e))?;
+        for address in response.addresses.iter() {
+            println!("node address: {}", address);
+        }
+        println!("connected peers: {}", response.peers.len());
+        for peer in response.peers.iter() {
+            println!("{}", peer);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "dev")]
+    /// Fund `address` with `amount` using the genesis wallet at `location`
+    async fn faucet(
+        location: &WalletLocation,
+        address: &str,
+        amount: Decimal,
+        policy: RebroadcastPolicy,
+    ) -> anyhow::Result<()> {
+        let genesis_wallet = Self::open_wallet(location)?;
+        debug!(
+            "funding {} with {} from {}",
+            address,
+            amount,
+            genesis_wallet.address()
+        );
+        let transaction = Self::make_transaction(&genesis_wallet, address, amount)?;
+        let mut node = Self::start_p2p_node().await?;
+        Self::publish_transaction(
+            &mut node,
+            transaction,
+            amount,
+            genesis_wallet.public_key(),
+            policy,
+        )
+        .await?;
+        println!("funded {} with {} JAB", address, amount);
+        Ok(())
+    }
+
+    /// Sign genesis block
+    fn sign_genesis_block(location: &WalletLocation) -> anyhow::Result<()> {
+        let wallet = Self::open_wallet(location)?;
+        let transaction =
+            Chain::genesis_transaction(TransactionVersion::V1, wallet.address(), dec!(50.0))
+                .sign_with_wallet(&wallet)
+                .map_err(|e| anyhow::anyhow!("failed to sign genesis transaction: {}", e))?;
+        // verify signature is correct
+        assert!(transaction.verify_signature(&wallet.public_key()).unwrap());
+        println!("genesis transaction signature: {}", transaction.signature());
+        Ok(())
+    }
+
+    /// Open wallet at `location`
+    fn open_wallet(location: &WalletLocation) -> anyhow::Result<Wallet> {
+        let secret_key = Self::read_key(&location.secret_key_path())?;
+        Wallet::try_from(secret_key.as_slice())
+            .map_err(|e| anyhow::anyhow!("failed to parse wallet: {}", e))
+    }
+
+    /// Write `key` to a new file at `p`; fails if the file already exists.
+    /// On unix the file is readable and writable by the owner only (0600);
+    /// on windows it inherits the access control list of the user directory
+    fn write_key(p: &Path, key: &[u8]) -> anyhow::Result<()> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        // create the file with restricted permissions, so it is never readable by others
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = match options.open(p) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => anyhow::bail!(
+                "key file {} already exists; choose another wallet name",
+                p.display()
+            ),
+            Err(e) => anyhow::bail!("could not open file {}: {}", p.display(), e),
+        };
+        file.write_all(key)
+            .map_err(|e| anyhow::anyhow!("failed to write key file {}: {}", p.display(), e))
+    }
+
+    /// Read the secret key from `p`. The key is zeroized once dropped
+    fn read_key(p: &Path) -> anyhow::Result<Zeroizing<Vec<u8>>> {
+        let mut file = match fs::OpenOptions::new().read(true).open(p) {
+            Ok(f) => f,
+            Err(err) => anyhow::bail!("could not open file {}: {}", p.display(), err),
+        };
+        let mut key_buffer = Zeroizing::new(vec![0; SECRET_KEY_SIZE]);
+        file.read_exact(key_buffer.as_mut_slice())
+            .map_err(|e| anyhow::anyhow!("failed to read key file {}: {}", p.display(), e))?;
+        Ok(key_buffer)
+    }
+
+    /// Start p2p jab node
+    async fn start_p2p_node() -> anyhow::Result<Node> {
+        start_node(NodeConfig::default()).await
+    }
+
+    /// Make transaction, signed with a random nonce so paying the same amount to the same wallet again is a new payment
+    fn make_transaction(
+        wallet: &Wallet,
+        output_address: &str,
+        amount: Decimal,
+    ) -> anyhow::Result<Transaction> {
+        TransactionBuilder::transfer(
+            TransactionVersion::V1,
+            wallet.address(),
+            output_address,
+            amount,
+        )
+        .nonce(rand::random())
+        .sign_with_wallet(wallet)
+        .map_err(|e| anyhow::anyhow!("failed to sign transaction: {}", e))
+    }
+
+    /// Publish transaction to network and wait for it to be confirmed.
+    /// The transaction is published again according to `policy` while no result is received
+    async fn publish_transaction(
+        node: &mut Node,
+        transaction: Transaction,
+        amount: Decimal,
+        pubkey: String,
+        policy: RebroadcastPolicy,
+    ) -> anyhow::Result<()> {
+        debug!("publishing transaction {:?}", transaction);
+        let input_address = transaction
+            .input_address()
+            .ok_or_else(|| anyhow::anyhow!("transaction has no input"))?;
+        let output_address = transaction
+            .output_address()
+            .ok_or_else(|| anyhow::anyhow!("transaction has no output"))?;
+        // Wait for transaction confirmation
+        match Self::wait_for_transaction_confirmation(
+            node,
+            &transaction.signed_id(),
+            Msg::transaction(
+                node.id(),
+                input_address,
+                output_address,
+                amount,
+                transaction.nonce(),
+                pubkey,
+                transaction.signature(),
+            ),
+            policy,
+        )
+        .await?
+        {
+            Confirmation::Confirmed(Some(accepted)) => {
+                println!(
+                    "transaction {} mined in block #{}",
+                    accepted.txid, accepted.block_index
+                );
+                Ok(())
+            }
+            Confirmation::Confirmed(None) => Ok(()),
+            Confirmation::Lookup => anyhow::bail!("transaction could not be confirmed"),
+            Confirmation::Rejected(err) => {
+                anyhow::bail!("failed to publish wallet created transaction: {}", err);
+            }
+        }
+    }
+
+    /// Wait for transaction result, publishing the transaction again every `policy.interval` until a result is
+    /// received or `policy.timeout` expires.
+    /// If the transaction turns out to have been mined already, it is looked up by its signed id `txid` to confirm it
+    async fn wait_for_transaction_confirmation(
+        node: &mut Node,
+        txid: &str,
+        msg: Msg,
+        policy: RebroadcastPolicy,
+    ) -> anyhow::Result<Confirmation> {
+        let mut pending = PendingTransaction::new(policy);
+        let mut should_publish_transaction = false;
+        let mut last_broadcast = Instant::now();
+        let mut retry = tokio::time::interval(PUBLISH_RETRY_INTERVAL);
+        loop {
+            let event = tokio::select! {
+                _ = retry.tick() => {
+                    if pending.expired() {
+                        anyhow::bail!(
+                            "transaction has not been confirmed within {} seconds",
+                            policy.timeout.as_secs()
+                        );
+                    }
+                    if pending.broadcasts() > 0 && pending.should_rebroadcast(last_broadcast) {
+                        debug!("transaction has not been confirmed yet; publishing it again");
+                        should_publish_transaction = true;
+                    }
+                    None
+                },
+                message = node.swarm.select_next_some() => {
+                    // once a peer received the transaction, only the rebroadcast interval publishes it again
+                    if matches!(message, SwarmEvent::ConnectionEstablished { .. } | SwarmEvent::ConnectionClosed { .. })
+                        && pending.should_publish_on_connection()
+                    {
+                        should_publish_transaction = true;
+                    }
+                    None
+                },
+                message = node.event_receiver.next() => {
+                    match message {
+                        Some(Ok(Msg::TransactionResult(result))) => Some(pending.on_result(result)),
+                        Some(Ok(Msg::TransactionBlock(block))) => pending.on_transaction_block(txid, block),
+                        _ => None,
+                    }
+                }
+            };
+            if should_publish_transaction {
+                match node.publish(msg.clone()).await {
+                    Err(err) => anyhow::bail!("failed to publish transaction: {}", err),
+                    Ok(outcome) if outcome.has_recipients() => {
+                        should_publish_transaction = false;
+                        pending.broadcasted();
+                        last_broadcast = Instant::now();
+                    }
+                    // retry until a peer is subscribed
+                    Ok(_) => {}
+                }
+            }
+            match event {
+                Some(Confirmation::Lookup) => {
+                    debug!(
+                        "transaction {} may have been mined already; looking it up",
+                        txid
+                    );
+                    let lookup = Msg::get_transaction_block(node.id(), txid);
+                    if let Err(err) = node.publish(lookup).await {
+                        anyhow::bail!("failed to look up transaction: {}", err);
+                    }
+                }
+                Some(confirmation) => return Ok(confirmation),
+                None => {}
+            }
+        }
+    }
+
+    /// Get balance and transactions for `address`
+    async fn publish_get_balance(
+        node: &mut Node,
+        address: &str,
+    ) -> anyhow::Result<(Decimal, Vec<Transaction>)> {
+        debug!("publishing wallet details query for {}", address);
+        // Wait for transaction result
+        let request_id = rand::random();
+        match Self::wait_for_wallet_query_result(
+            node,
+            Msg::wallet_details(node.id(), request_id, address),
+            request_id,
+        )
+        .await
+        {
+            Ok(WalletQueryResult::Ok(WalletTransactions {
+                balance,
+                transactions,
+                ..
+            })) => Ok((balance, transactions)),
+            Ok(WalletQueryResult::Balance(_) | WalletQueryResult::Page(_)) => {
+                anyhow::bail!("unexpected wallet details response");
+            }
+            Ok(WalletQueryResult::Error(err)) => {
+                anyhow::bail!("failed to get wallet details: {}", err);
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Get balance only for `address`, without its transactions
+    async fn publish_get_wallet_balance(node: &mut Node, address: &str) -> anyhow::Result<Decimal> {
+        debug!("publishing wallet balance query for {}", address);
+        let request_id = rand::random();
+        match Self::wait_for_wallet_query_result(
+            node,
+            Msg::wallet_balance(node.id(), request_id, address),
+            request_id,
+        )
+        .await
+        {
+            Ok(WalletQueryResult::Balance(WalletBalance { balance, .. })) => Ok(balance),
+            Ok(WalletQueryResult::Ok(WalletTransactions { balance, .. })) => Ok(balance),
+            Ok(WalletQueryResult::Page(WalletTransactionsPage { balance, .. })) => Ok(balance),
+            Ok(WalletQueryResult::Error(err)) => {
+                anyhow::bail!("failed to get wallet balance: {}", err);
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Wait for wallet query result. Paged results replying to `request_id` are reassembled into a single `Ok` result
+    async fn wait_for_wallet_query_result(
+        node: &mut Node,
+        msg: Msg,
+        request_id: u64,
+    ) -> anyhow::Result<WalletQueryResult> {
+        let mut pages = WalletTransactionsPages::default();
+        Self::wait_for_reply(node, msg, |msg| match msg {
+            Msg::WalletDetailsResult(WalletQueryResult::Page(page))
+                if page.request_id == request_id =>
+            {
+                debug!(
+                    "received wallet transactions page {}/{} from {}",
+                    page.page + 1,
+                    page.pages,
+                    page.responder
+                );
+                pages.push(page).map(WalletQueryResult::Ok)
+            }
+            Msg::WalletDetailsResult(WalletQueryResult::Page(_)) => None,
+            Msg::WalletDetailsResult(result) => Some(result),
+            _ => None,
+        })
+        .await
+    }
+
+    /// Publish `msg` once connected to the network, then wait for the first message accepted by `reply`
+    async fn wait_for_reply<T>(
+        node: &mut Node,
+        msg: Msg,
+        mut reply: impl FnMut(Msg) -> Option<T>,
+    ) -> anyhow::Result<T> {
+        // publish immediately if already connected
+        let mut should_publish = node.swarm.connected_peers().next().is_some();
+        let mut retry = tokio::time::interval(PUBLISH_RETRY_INTERVAL);
+        loop {
+            let event = tokio::select! {
+                _ = retry.tick(), if should_publish => None,
+                message = node.swarm.select_next_some() => {
+                    if matches!(message, SwarmEvent::ConnectionEstablished { .. } | SwarmEvent::ConnectionClosed { .. }) {
+                        should_publish = true;
+                    }
+                    None
+                },
+                message = node.event_receiver.next() => {
+                    match message {
+                        Some(Ok(message)) => reply(message),
+                        _ => None,
+                    }
+                }
+            };
+            if should_publish {
+                match node.publish(msg.clone()).await {
+                    Err(err) => anyhow::bail!("failed to publish {}: {}", msg.name(), err),
+                    // retry until a peer is subscribed
+                    Ok(outcome) => should_publish = !outcome.has_recipients(),
+                }
+            }
+            if let Some(event) = event {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Collect the messages accepted by `reply` received within `window`
+    async fn collect_replies<T>(
+        node: &mut Node,
+        window: Duration,
+        mut reply: impl FnMut(Msg) -> Option<T>,
+    ) -> Vec<T> {
+        let deadline = tokio::time::sleep(window);
+        tokio::pin!(deadline);
+        let mut replies = Vec::new();
+        loop {
+            tokio::select! {
+                _ = &mut deadline => return replies,
+                _ = node.swarm.select_next_some() => {}
+                message = node.event_receiver.next() => {
+                    if let Some(Ok(message)) = message {
+                        replies.extend(reply(message));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[cfg(unix)]
+    #[test]
+    fn should_write_key_readable_by_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tempdir = tempfile::TempDir::new().unwrap();
+        let path = tempdir.path().join(".jab.key");
+        App::write_key(&path, &[0x42; SECRET_KEY_SIZE]).unwrap();
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        assert_eq!(*App::read_key(&path).unwrap(), vec![0x42; SECRET_KEY_SIZE]);
+    }
+
+    #[test]
+    fn should_not_overwrite_existing_key() {
+        let tempdir = tempfile::TempDir::new().unwrap();
+        let path = tempdir.path().join(".jab.key");
+        App::write_key(&path, &[0x42; SECRET_KEY_SIZE]).unwrap();
+        assert!(App::write_key(&path, &[0x24; SECRET_KEY_SIZE]).is_err());
+        assert_eq!(*App::read_key(&path).unwrap(), vec![0x42; SECRET_KEY_SIZE]);
+    }
+
+    #[test]
+    fn should_not_read_truncated_key() {
+        let tempdir = tempfile::TempDir::new().unwrap();
+        let path = tempdir.path().join(".jab.key");
+        fs::write(&path, [0x42; SECRET_KEY_SIZE - 1]).unwrap();
+        assert!(App::read_key(&path).is_err());
+    }
+
+    #[test]
+    fn should_display_status_report() {
+        let report = StatusReport {
+            height: 12,
+            tip_hash: String::from("cafebabe"),
+            peers: 3,
+            sync: SyncStatus::Syncing {
+                behind: 1,
+                nodes: 3,
+            },
+            address: String::from("jabbe2cce18177f64c3eb2cc51f0bd640dec8b22668"),
+            balance: dec!(500.0),
+        };
+        let output = report.to_string();
+        assert!(output.contains("height: 12"));
+        assert!(output.contains("tip hash: cafebabe"));
+        assert!(output.contains("peers: 3"));
+        assert!(output.contains("sync: syncing (1 of 3 nodes behind)"));
+        assert!(output.contains("balance: 500.0 JAB"));
+        assert_eq!(output.lines().count(), 6);
+    }
+
+    #[test]
+    fn should_report_sync_status_from_node_heights() {
+        assert_eq!(SyncStatus::from_heights(&[12]), SyncStatus::Synced);
+        assert_eq!(SyncStatus::from_heights(&[12, 12]), SyncStatus::Synced);
+        assert_eq!(
+            SyncStatus::from_heights(&[12, 10, 12, 11]),
+            SyncStatus::Syncing {
+                behind: 2,
+                nodes: 4
+            }
+        );
+        assert_eq!(SyncStatus::Synced.to_string(), "synced");
+    }
+
+    #[test]
+    fn should_generate_wallet_offline() {
+        let tempdir = tempfile::TempDir::new().unwrap();
+        let path = tempdir.path().join("wallet");
+        let location = WalletLocation::new(&path, None).unwrap();
+        // no node is started, hence this can't perform any network activity
+        App::generate_offline_wallet(&location).unwrap();
+        let wallet = App::open_wallet(&location).unwrap();
+        assert!(wallet.address().starts_with("jab"));
+        assert_eq!(wallet.address().len(), 43);
+        let public_key = fs::read_to_string(path.join("jab.pub")).unwrap();
+        assert_eq!(public_key, wallet.public_key());
+        // keys must be usable for signing
+        let signature = wallet.sign(&[0xab; 32]).unwrap();
+        assert!(Wallet::verify(&[0xab; 32], &signature, &public_key).unwrap());
+    }
+
+    #[test]
+    fn should_create_and_list_named_wallets() {
+        let tempdir = tempfile::TempDir::new().unwrap();
+        let alice = WalletLocation::new(tempdir.path(), Some("alice")).unwrap();
+        let bob = WalletLocation::new(tempdir.path(), Some("bob")).unwrap();
+        let alice_wallet = App::create_wallet(&alice).unwrap();
+        let bob_wallet = App::create_wallet(&bob).unwrap();
+        assert!(tempdir.path().join("alice.pub").is_file());
+        assert!(tempdir.path().join(".alice.key").is_file());
+        assert!(tempdir.path().join("bob.pub").is_file());
+        assert!(tempdir.path().join(".bob.key").is_file());
+        assert_eq!(
+            WalletLocation::list(tempdir.path()).unwrap(),
+            vec![String::from("alice"), String::from("bob")]
+        );
+        assert_eq!(
+            App::open_wallet(&alice).unwrap().address(),
+            alice_wallet.address()
+        );
+        assert_eq!(
+            App::open_wallet(&bob).unwrap().address(),
+            bob_wallet.address()
+        );
+    }
+
+    #[cfg(feature = "dev")]
+    #[test]
+    fn should_make_faucet_transaction() {
+        let genesis_wallet = Wallet::new();
+        let transaction = App::make_transaction(
+            &genesis_wallet,
+            "jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1",
+            dec!(100.0),
+        )
+        .unwrap();
+        assert_eq!(transaction.input_address(), Some(genesis_wallet.address()));
+        assert_eq!(
+            transaction.output_address(),
+            Some("jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1")
+        );
+        assert_eq!(
+            transaction.amount_spent(genesis_wallet.address()),
+            dec!(-100.0)
+        );
+        assert!(Wallet::verify(
+            &transaction.digest(),
+            transaction.signature(),
+            &genesis_wallet.public_key()
+        )
+        .unwrap());
+    }
+}
@@ -0,0 +1,262 @@
+//! # Rebroadcast
+//!
+//! Tracks a transaction published by the client until it is confirmed, so it can be published again if the
+//! serving node never answers (e.g. it crashed before mining it)
+
+use jab::net::message::{
+    TransactionAccepted, TransactionBlock, TransactionError, TransactionErrorCode,
+    TransactionResult, TransactionStatus,
+};
+use tokio::time::{Duration, Instant};
+
+/// Default interval between two broadcasts of an unconfirmed transaction
+pub const DEFAULT_REBROADCAST_INTERVAL: Duration = Duration::from_secs(30);
+/// Default time to wait for a transaction to be confirmed
+pub const DEFAULT_CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Defines how often and for how long an unconfirmed transaction is broadcast
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct RebroadcastPolicy {
+    /// Interval between two broadcasts
+    pub interval: Duration,
+    /// Time after which the client stops waiting for the confirmation
+    pub timeout: Duration,
+}
+
+impl Default for RebroadcastPolicy {
+    fn default() -> Self {
+        Self {
+            interval: DEFAULT_REBROADCAST_INTERVAL,
+            timeout: DEFAULT_CONFIRMATION_TIMEOUT,
+        }
+    }
+}
+
+/// State of a published transaction after a `TransactionResult` has been received
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Confirmation {
+    /// The transaction has been mined; the node reports where, unless it doesn't support reporting it
+    Confirmed(Option<TransactionAccepted>),
+    /// A transaction with the same id has already been mined, possibly a previous broadcast: the transaction must be
+    /// looked up (see `GetTransactionBlock`) to confirm it
+    Lookup,
+    /// The transaction has been rejected
+    Rejected(TransactionError),
+}
+
+/// A transaction published by the client, which has not been confirmed yet
+#[derive(Debug)]
+pub struct PendingTransaction {
+    policy: RebroadcastPolicy,
+    broadcasts: u32,
+    deadline: Instant,
+}
+
+impl PendingTransaction {
+    /// Start tracking a transaction with `policy`
+    pub fn new(policy: RebroadcastPolicy) -> Self {
+        Self {
+            policy,
+            broadcasts: 0,
+            deadline: Instant::now() + policy.timeout,
+        }
+    }
+
+    /// Register a broadcast of the transaction, which has been received by at least a peer
+    pub fn broadcasted(&mut self) {
+        self.broadcasts += 1;
+    }
+
+    /// Amount of broadcasts of the transaction
+    pub fn broadcasts(&self) -> u32 {
+        self.broadcasts
+    }
+
+    /// Returns whether the transaction should be broadcast again: no result has been received within the interval
+    /// since the last broadcast, but the confirmation timeout has not expired yet
+    pub fn should_rebroadcast(&self, last_broadcast: Instant) -> bool {
+        !self.expired() && last_broadcast.elapsed() >= self.policy.interval
+    }
+
+    /// Returns whether the transaction must be published on a connection event: only until a peer has received it.
+    /// Once broadcast, it is published again only after the rebroadcast interval, so connection flaps don't submit
+    /// it multiple times
+    pub fn should_publish_on_connection(&self) -> bool {
+        self.broadcasts == 0
+    }
+
+    /// Returns whether the confirmation timeout has expired
+    pub fn expired(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+
+    /// Interpret `result`. Once the transaction has been broadcast more than once, a `DuplicateTransaction` rejection
+    /// may come from one of the previous broadcasts having been mined, hence the transaction must be looked up
+    pub fn on_result(&self, result: TransactionResult) -> Confirmation {
+        match (result.status, result.error) {
+            (TransactionStatus::Ok, _) => Confirmation::Confirmed(result.accepted),
+            (_, Some(err))
+                if err.code == TransactionErrorCode::DuplicateTransaction
+                    && self.broadcasts > 1 =>
+            {
+                Confirmation::Lookup
+            }
+            (_, Some(err)) => Confirmation::Rejected(err),
+            (TransactionStatus::Nok, None) => Confirmation::Rejected(TransactionError::new(
+                TransactionErrorCode::BlockchainError,
+                "transaction rejected without a reason",
+            )),
+        }
+    }
+
+    /// Interpret the `TransactionBlock` received after a lookup of the transaction with signed id `txid`.
+    /// The transaction is confirmed only if it has been mined; otherwise `None` is returned and the transaction keeps
+    /// being broadcast
+    pub fn on_transaction_block(
+        &self,
+        txid: &str,
+        block: TransactionBlock,
+    ) -> Option<Confirmation> {
+        match block.block_index {
+            Some(block_index) if block.txid == txid => Some(Confirmation::Confirmed(Some(
+                TransactionAccepted::new(txid, block_index),
+            ))),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::application::Application;
+
+    use jab::blockchain::{BlockchainDatabase, Chain, TransactionBuilder, TransactionVersion};
+    use jab::net::Msg;
+    use pretty_assertions::assert_eq;
+    use rust_decimal_macros::dec;
+
+    fn policy() -> RebroadcastPolicy {
+        RebroadcastPolicy {
+            interval: Duration::from_secs(5),
+            timeout: Duration::from_secs(60),
+        }
+    }
+
+    fn duplicate() -> TransactionResult {
+        TransactionResult::new(
+            TransactionStatus::Nok,
+            Some(TransactionError::new(
+                TransactionErrorCode::DuplicateTransaction,
+                "the transaction has already been mined",
+            )),
+        )
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn should_rebroadcast_unconfirmed_transaction_until_confirmed() {
+        let mut pending = PendingTransaction::new(policy());
+        pending.broadcasted();
+        let last_broadcast = Instant::now();
+        assert!(!pending.should_rebroadcast(last_broadcast));
+        // the serving node never answers
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert!(pending.should_rebroadcast(last_broadcast));
+        pending.broadcasted();
+        assert_eq!(pending.broadcasts(), 2);
+        // a transaction with the same id has been mined: it must be looked up to confirm it
+        assert_eq!(pending.on_result(duplicate()), Confirmation::Lookup);
+        assert_eq!(
+            pending.on_result(
+                TransactionResult::new(TransactionStatus::Ok, None).with_accepted("cafebabe", 3)
+            ),
+            Confirmation::Confirmed(Some(TransactionAccepted::new("cafebabe", 3)))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn should_confirm_duplicate_transaction_only_once_found_by_node() {
+        let mut chain = Chain::from_database(BlockchainDatabase::in_memory()).unwrap();
+        let transaction = TransactionBuilder::transfer(
+            TransactionVersion::V1,
+            chain.genesis_address(),
+            "jab0930f5dfeba62bd8929846bbf0f1a08e995e37f1",
+            dec!(1.0),
+        )
+        .nonce(7)
+        .finish("aaa");
+        let txid = transaction.signed_id();
+        let lookup = |chain: &Chain| match Application::transaction_block_response(chain, &txid) {
+            Msg::TransactionBlock(block) => block,
+            response => panic!("unexpected response {:?}", response),
+        };
+        let mut pending = PendingTransaction::new(policy());
+        pending.broadcasted();
+        pending.broadcasted();
+        assert_eq!(pending.on_result(duplicate()), Confirmation::Lookup);
+        // the node hasn't mined it (yet)
+        assert_eq!(pending.on_transaction_block(&txid, lookup(&chain)), None);
+        // the node mined it with its own fee output, so the lookup is made by signed id
+        let mined = TransactionBuilder::from_existing(&transaction)
+            .output("miner", dec!(0.5))
+            .finish(transaction.signature());
+        let block = chain.generate_next_block(mined).unwrap();
+        assert_eq!(
+            pending.on_transaction_block(&txid, lookup(&chain)),
+            Some(Confirmation::Confirmed(Some(TransactionAccepted::new(
+                &txid,
+                block.index()
+            ))))
+        );
+        // a response about another transaction doesn't confirm it
+        assert_eq!(
+            pending.on_transaction_block(&txid, TransactionBlock::new("cafebabe", Some(1))),
+            None
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn should_not_publish_again_on_connection_events() {
+        let mut pending = PendingTransaction::new(policy());
+        // no peer has received the transaction yet
+        assert!(pending.should_publish_on_connection());
+        pending.broadcasted();
+        let last_broadcast = Instant::now();
+        let mut publishes = 1;
+        // the connection flaps several times within the rebroadcast interval
+        for _ in 0..4 {
+            tokio::time::advance(Duration::from_secs(1)).await;
+            if pending.should_publish_on_connection() || pending.should_rebroadcast(last_broadcast)
+            {
+                publishes += 1;
+            }
+        }
+        assert_eq!(publishes, 1);
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(pending.should_rebroadcast(last_broadcast));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn should_reject_duplicate_transaction_broadcast_once() {
+        let mut pending = PendingTransaction::new(policy());
+        pending.broadcasted();
+        assert!(matches!(
+            pending.on_result(duplicate()),
+            Confirmation::Rejected(TransactionError {
+                code: TransactionErrorCode::DuplicateTransaction,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn should_stop_rebroadcasting_after_timeout() {
+        let mut pending = PendingTransaction::new(policy());
+        pending.broadcasted();
+        let last_broadcast = Instant::now();
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert!(pending.expired());
+        assert!(!pending.should_rebroadcast(last_broadcast));
+    }
+}
@@ -8,18 +8,165 @@ extern crate serde;
 extern crate tracing;
 // -- modules
 mod application;
+mod client;
+mod mode;
 
 use application::{Application, Config as AppConfig};
+use argh::FromArgs;
+use client::{App, Task};
 use dotenv::dotenv;
-use std::env;
+use mode::Mode;
+#[cfg(feature = "dev")]
+use rust_decimal::Decimal;
+use std::path::PathBuf;
+
+#[derive(FromArgs)]
+#[argh(
+    description = "Please, report issues to <https://github.com/veeso/just-a-blockchain>
+Please, consider supporting the author <https://ko-fi.com/veeso>"
+)]
+pub struct Args {
+    #[argh(
+        option,
+        default = "Mode::default()",
+        description = "startup mode: `full` runs a node, `wallet` runs a wallet task (default: full)"
+    )]
+    pub mode: Mode,
+    #[argh(
+        switch,
+        description = "rebuild the blockchain indexes from the blocks and exit (full mode)"
+    )]
+    pub rebuild_indexes: bool,
+    #[argh(
+        switch,
+        short = 'b',
+        description = "get balance and transactions for this wallet"
+    )]
+    pub balance: bool,
+    #[cfg(feature = "dev")]
+    #[argh(option, description = "amount to send with faucet (default: 100)")]
+    pub amount: Option<Decimal>,
+    #[argh(
+        option,
+        short = 'B',
+        description = "get balance and transactions for provided wallet"
+    )]
+    pub get_balance_for: Option<String>,
+    #[cfg(feature = "dev")]
+    #[argh(
+        option,
+        description = "fund provided address using the genesis wallet provided with `-w`"
+    )]
+    pub faucet: Option<String>,
+    #[argh(switch, short = 'g', description = "generate a new wallet")]
+    pub generate_wallet: bool,
+    #[argh(
+        switch,
+        description = "generate the new wallet without registering it; use with `-g`"
+    )]
+    pub offline: bool,
+    #[argh(switch, description = "list the wallets in the wallet directory")]
+    pub list: bool,
+    #[argh(
+        switch,
+        description = "list the peers connected to the node running on this host"
+    )]
+    pub peers: bool,
+    #[argh(
+        option,
+        description = "port of the admin endpoint of the local node, used by `--peers` (default: 4100)"
+    )]
+    pub admin_port: Option<u16>,
+    #[argh(switch, description = "register this wallet on the blockchain")]
+    pub register: bool,
+    #[argh(
+        option,
+        description = "seconds after which an unconfirmed transaction is published again (default: 30)"
+    )]
+    pub rebroadcast_interval: Option<u64>,
+    #[argh(
+        option,
+        description = "seconds to wait for a transaction to be confirmed (default: 300)"
+    )]
+    pub confirmation_timeout: Option<u64>,
+    #[argh(switch, short = 's', description = "send money")]
+    pub send: bool,
+    #[argh(
+        switch,
+        description = "show chain height, tip hash, peers and balance for this wallet"
+    )]
+    pub status: bool,
+    #[argh(switch, description = "sign genesis block")]
+    pub sign_genesis_block: bool,
+    #[argh(option, short = 'w', description = "provide wallet path (wallet mode)")]
+    pub wallet: Option<PathBuf>,
+    #[argh(
+        option,
+        short = 'n',
+        description = "name of the wallet in the wallet directory (default: jab)"
+    )]
+    pub wallet_name: Option<String>,
+}
+
+impl From<&Args> for Task {
+    fn from(args: &Args) -> Self {
+        #[cfg(feature = "dev")]
+        if let Some(addr) = args.faucet.as_ref() {
+            return Self::Faucet(
+                addr.to_string(),
+                args.amount.unwrap_or(rust_decimal_macros::dec!(100.0)),
+            );
+        }
+        if args.generate_wallet && args.offline {
+            Self::GenerateOfflineWallet
+        } else if args.generate_wallet {
+            Self::GenerateNewWallet
+        } else if args.register {
+            Self::Register
+        } else if args.sign_genesis_block {
+            Self::SignGenesisBlock
+        } else if args.balance {
+            Self::GetBalance
+        } else if let Some(addr) = args.get_balance_for.as_ref() {
+            Self::GetBalanceFor(addr.to_string())
+        } else if args.send {
+            Self::Send
+        } else if args.status {
+            Self::Status
+        } else if args.peers {
+            Self::Peers
+        } else if args.list {
+            Self::ListWallets
+        } else {
+            Self::None
+        }
+    }
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let args: Args = argh::from_env();
     dotenv().ok();
     tracing_subscriber::fmt::init();
+    debug!(
+        "starting in {} mode with components {:?}",
+        args.mode,
+        args.mode.components()
+    );
+    match args.mode {
+        Mode::Full => run_full_node(&args).await,
+        Mode::Wallet => App::run(Task::from(&args), args).await,
+    }
+}
+
+/// Run a full node
+async fn run_full_node(args: &Args) -> anyhow::Result<()> {
     info!("jab {} - developed by {}", JAB_VERSION, JAB_AUTHORS);
     let config = AppConfig::try_from_env()?;
     info!("configuration successfully loaded");
+    if args.rebuild_indexes {
+        return Application::rebuild_indexes(&config);
+    }
     let application = Application::init(config).await?;
     info!("application ready!");
     application.run().await
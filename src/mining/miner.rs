@@ -2,24 +2,63 @@
 //!
 //! This module define the structure for a miner
 
+use std::time::SystemTime;
+
 // Describe a miner in the network
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Miner {
     id: String,
+    /// Last time the miner has been seen by this node; it's local information, so it's not sent to peers.
+    /// Miners received from peers have never been seen by this node
+    #[serde(skip, default = "never_seen")]
+    last_seen: SystemTime,
 }
 
 impl Miner {
     /// instantiates a new `Miner`
     pub fn new(id: impl ToString) -> Self {
-        Self { id: id.to_string() }
+        Self {
+            id: id.to_string(),
+            last_seen: SystemTime::now(),
+        }
+    }
+
+    /// Set the last time the miner has been seen
+    pub fn with_last_seen(mut self, last_seen: SystemTime) -> Self {
+        self.last_seen = last_seen;
+        self
     }
 
     /// get `Miner` id
     pub fn id(&self) -> &str {
         &self.id
     }
+
+    /// get last time the `Miner` has been seen
+    pub fn last_seen(&self) -> SystemTime {
+        self.last_seen
+    }
+
+    /// Mark the miner as seen at `at`
+    pub fn seen(&mut self, at: SystemTime) {
+        self.last_seen = at;
+    }
+}
+
+/// Last seen time of a miner which has never been seen by this node
+fn never_seen() -> SystemTime {
+    SystemTime::UNIX_EPOCH
 }
 
+// miners are identified by their id
+impl PartialEq for Miner {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Miner {}
+
 #[cfg(test)]
 mod test {
 
@@ -32,4 +71,14 @@ mod test {
         let miner = Miner::new("foo");
         assert_eq!(miner.id(), "foo");
     }
+
+    #[test]
+    fn should_not_serialize_last_seen() {
+        let miner = Miner::new("foo");
+        let json = serde_json::to_string(&miner).unwrap();
+        assert_eq!(json, r#"{"id":"foo"}"#);
+        let decoded: Miner = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, miner);
+        assert_eq!(decoded.last_seen(), SystemTime::UNIX_EPOCH);
+    }
 }
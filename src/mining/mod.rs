@@ -6,9 +6,13 @@ mod miner;
 
 pub use miner::Miner;
 
+use std::time::{Duration, SystemTime};
+
 /// The mining database contains the current information regarding the network miners
 pub struct MiningDatabase {
     miners: Vec<Miner>,
+    /// Id of the miner running on this node, which is never pruned
+    host: String,
     last_block_mined_by: Option<String>,
 }
 
@@ -16,6 +20,7 @@ impl MiningDatabase {
     /// Instantiate a new `MiningDatabase`
     pub fn new(host_miner: Miner) -> Self {
         Self {
+            host: host_miner.id().to_string(),
             miners: vec![host_miner],
             last_block_mined_by: None,
         }
@@ -33,12 +38,59 @@ impl MiningDatabase {
             .and_then(|x| self.miner_by_id(x))
     }
 
-    /// Add miner to miners list
+    /// Add miner to miners list. If the miner is already registered, its last seen time is refreshed instead
     pub fn register_miner(&mut self, miner: Miner) {
-        if !self.miner_exists(miner.id()) {
-            info!("added new miner {}", miner.id());
-            self.miners.push(miner);
+        match self.miners.iter_mut().find(|x| x.id() == miner.id()) {
+            Some(registered) => registered.seen(miner.last_seen().max(registered.last_seen())),
+            None => {
+                info!("added new miner {}", miner.id());
+                self.miners.push(miner);
+            }
+        }
+    }
+
+    /// Mark the miner with `id`, if registered, as seen at `at`.
+    /// It must be called only when the miner itself shows up, e.g. on a connection with it
+    pub fn seen(&mut self, id: &str, at: SystemTime) {
+        if let Some(miner) = self.miners.iter_mut().find(|x| x.id() == id) {
+            miner.seen(at.max(miner.last_seen()));
+        }
+    }
+
+    /// Remove the miners which haven't been seen within `max_age`; the host miner is always retained.
+    /// Returns the amount of pruned miners
+    pub fn prune_stale(&mut self, max_age: Duration) -> usize {
+        let now = SystemTime::now();
+        let host = self.host.as_str();
+        let before = self.miners.len();
+        self.miners.retain(|miner| {
+            let fresh = miner.id() == host
+                || now
+                    .duration_since(miner.last_seen())
+                    .map(|age| age <= max_age)
+                    .unwrap_or(true);
+            if !fresh {
+                info!(
+                    "{} hasn't been seen for too long; pruning it from miners",
+                    miner.id()
+                );
+            }
+            fresh
+        });
+        before - self.miners.len()
+    }
+
+    /// Merge the miners advertised by a peer into the database. The unknown ones are added, while the known ones are
+    /// left untouched: a relayed list doesn't prove a miner is alive, so it never refreshes their last seen time.
+    /// Merging the same miners again doesn't change the database. Returns the amount of added miners
+    pub fn merge_miners(&mut self, miners: impl IntoIterator<Item = Miner>) -> usize {
+        let before = self.miners.len();
+        for miner in miners {
+            if !self.miner_exists(miner.id()) {
+                self.register_miner(miner);
+            }
         }
+        self.miners.len() - before
     }
 
     /// unregister miner
@@ -80,7 +132,7 @@ impl MiningDatabase {
     }
 
     /// returns whether a miner with `id` exists in the current database
-    fn miner_exists(&self, id: &str) -> bool {
+    pub fn miner_exists(&self, id: &str) -> bool {
         self.miners.iter().any(|x| x.id() == id)
     }
 
@@ -139,6 +191,64 @@ mod test {
         assert_eq!(database.miners().len(), 2);
     }
 
+    #[test]
+    fn should_refresh_last_seen_on_registration() {
+        let mut database = MiningDatabase::new(Miner::new("host"));
+        database.register_miner(Miner::new("omar").with_last_seen(SystemTime::UNIX_EPOCH));
+        let seen_at = SystemTime::UNIX_EPOCH + Duration::from_secs(3600);
+        database.register_miner(Miner::new("omar").with_last_seen(seen_at));
+        assert_eq!(database.miners().len(), 2);
+        assert_eq!(database.miners().get(1).unwrap().last_seen(), seen_at);
+    }
+
+    #[test]
+    fn should_merge_miners_idempotently() {
+        let mut database = MiningDatabase::new(Miner::new("host"));
+        let advertised = vec![Miner::new("alice"), Miner::new("host"), Miner::new("omar")];
+        assert_eq!(database.merge_miners(advertised.clone()), 2);
+        assert_eq!(database.merge_miners(advertised), 0);
+        let ids: Vec<&str> = database.miners().iter().map(|x| x.id()).collect();
+        assert_eq!(ids, vec!["host", "alice", "omar"]);
+    }
+
+    #[test]
+    fn should_not_refresh_known_miners_on_merge() {
+        let mut database = MiningDatabase::new(Miner::new("host"));
+        database.register_miner(Miner::new("omar").with_last_seen(SystemTime::UNIX_EPOCH));
+        assert_eq!(database.merge_miners(vec![Miner::new("omar")]), 0);
+        assert_eq!(
+            database.miners().get(1).unwrap().last_seen(),
+            SystemTime::UNIX_EPOCH
+        );
+        assert_eq!(database.prune_stale(Duration::from_secs(60)), 1);
+    }
+
+    #[test]
+    fn should_mark_miner_as_seen() {
+        let mut database = MiningDatabase::new(Miner::new("host"));
+        database.register_miner(Miner::new("omar").with_last_seen(SystemTime::UNIX_EPOCH));
+        let seen_at = SystemTime::UNIX_EPOCH + Duration::from_secs(3600);
+        database.seen("omar", seen_at);
+        database.seen("pippo", seen_at);
+        assert_eq!(database.miners().get(1).unwrap().last_seen(), seen_at);
+        assert_eq!(database.miners().len(), 2);
+    }
+
+    #[test]
+    fn should_prune_stale_miners() {
+        let mut database =
+            MiningDatabase::new(Miner::new("host").with_last_seen(SystemTime::UNIX_EPOCH));
+        database.register_miner(Miner::new("omar").with_last_seen(SystemTime::UNIX_EPOCH));
+        database.register_miner(Miner::new("pippo"));
+        assert_eq!(database.prune_stale(Duration::from_secs(60)), 1);
+        assert!(!database.miner_exists("omar"));
+        // recently seen miners and host are retained
+        assert!(database.miner_exists("pippo"));
+        assert!(database.miner_exists("host"));
+        assert_eq!(database.prune_stale(Duration::from_secs(60)), 0);
+        assert_eq!(database.miners().len(), 2);
+    }
+
     #[test]
     fn should_not_unregister_unexisting_miner() {
         let mut database = MiningDatabase::new(Miner::new("host"));
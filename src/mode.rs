@@ -0,0 +1,116 @@
+//! # Mode
+//!
+//! This module exposes the startup modes of jab and the node setup shared by them
+
+use jab::net::{Node, NodeConfig};
+use std::fmt;
+use std::str::FromStr;
+
+/// Behaviour selected at startup
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Mode {
+    /// Run a full node, which stores the blockchain and mines blocks
+    Full,
+    /// Run a wallet client task against the network
+    Wallet,
+}
+
+/// A component initialized at startup
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Component {
+    /// Node configuration loaded from the environment
+    Config,
+    /// Blockchain database
+    Blockchain,
+    /// Registered miners database
+    Miners,
+    /// P2P node
+    Node,
+    /// Wallet keys, used by nodes to collect fees and by clients to sign transactions
+    Wallet,
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Self::Full
+    }
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full" => Ok(Self::Full),
+            "wallet" => Ok(Self::Wallet),
+            _ => Err(format!("invalid mode '{}': expected 'full' or 'wallet'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full => write!(f, "full"),
+            Self::Wallet => write!(f, "wallet"),
+        }
+    }
+}
+
+impl Mode {
+    /// Get the components initialized when starting in this mode
+    pub fn components(&self) -> &'static [Component] {
+        match self {
+            Self::Full => &[
+                Component::Config,
+                Component::Blockchain,
+                Component::Miners,
+                Component::Wallet,
+                Component::Node,
+            ],
+            Self::Wallet => &[Component::Wallet, Component::Node],
+        }
+    }
+}
+
+/// Initialize a p2p node with `config` and start listening, so all the modes speak the same protocol
+pub async fn start_node(config: NodeConfig) -> anyhow::Result<Node> {
+    debug!("starting p2p node");
+    let mut node = Node::init(config)
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to start p2p node: {}", e))?;
+    debug!("starting p2p listener");
+    node.listen()
+        .map(|_| node)
+        .map_err(|e| anyhow::anyhow!("failed to start node listener: {}", e))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_parse_mode() {
+        assert_eq!(Mode::from_str("full").unwrap(), Mode::Full);
+        assert_eq!(Mode::from_str("wallet").unwrap(), Mode::Wallet);
+        assert!(Mode::from_str("miner").is_err());
+        assert_eq!(Mode::default(), Mode::Full);
+        assert_eq!(Mode::Wallet.to_string(), "wallet");
+    }
+
+    #[test]
+    fn should_initialize_mode_components() {
+        let full = Mode::Full.components();
+        assert!(full.contains(&Component::Config));
+        assert!(full.contains(&Component::Blockchain));
+        assert!(full.contains(&Component::Miners));
+        assert!(full.contains(&Component::Wallet));
+        assert!(full.contains(&Component::Node));
+        // wallet clients don't store the blockchain
+        let wallet = Mode::Wallet.components();
+        assert_eq!(wallet, &[Component::Wallet, Component::Node]);
+    }
+}
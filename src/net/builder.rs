@@ -0,0 +1,142 @@
+//! # Builder
+//!
+//! A builder to compose the transport and the behaviour options of a `Node`
+
+use super::{Node, NodeConfig, NodeResult};
+
+use libp2p::core::transport::MemoryTransport;
+use libp2p::{identity, mdns::Mdns, tcp::TokioTcpTransport, PeerId};
+use libp2p_tcp::GenTcpConfig;
+
+/// Default name of the topic messages are published to
+pub const DEFAULT_TOPIC: &str = "jab";
+
+/// Transport used by the node to reach its peers
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TransportKind {
+    /// TCP with nodelay, used to reach peers over the network
+    Tcp,
+    /// In-memory transport; peers must live in the same process. Use it for tests only
+    Memory,
+}
+
+/// Accumulates the options of a `Node` and builds it.
+/// By default, the node uses TCP, a new random identity, the `jab` topic and mDNS discovery
+pub struct NodeBuilder {
+    config: NodeConfig,
+    keypair: Option<identity::Keypair>,
+    mdns: bool,
+    topic: String,
+    transport: TransportKind,
+}
+
+impl Default for NodeBuilder {
+    fn default() -> Self {
+        Self {
+            config: NodeConfig::default(),
+            keypair: None,
+            mdns: true,
+            topic: DEFAULT_TOPIC.to_string(),
+            transport: TransportKind::Tcp,
+        }
+    }
+}
+
+impl NodeBuilder {
+    /// Set the node configuration
+    pub fn config(mut self, config: NodeConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Set the identity of the node. If unset, a new ed25519 keypair is generated
+    pub fn keypair(mut self, keypair: identity::Keypair) -> Self {
+        self.keypair = Some(keypair);
+        self
+    }
+
+    /// Set whether peers are discovered with mDNS
+    pub fn mdns(mut self, enabled: bool) -> Self {
+        self.mdns = enabled;
+        self
+    }
+
+    /// Set the topic messages are published to. Nodes only exchange messages with peers on the same topic
+    pub fn topic(mut self, topic: impl ToString) -> Self {
+        self.topic = topic.to_string();
+        self
+    }
+
+    /// Set the transport used to reach peers
+    pub fn transport(mut self, transport: TransportKind) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Build the `Node`
+    pub async fn build(self) -> NodeResult<Node> {
+        let id_keys = self
+            .keypair
+            .unwrap_or_else(identity::Keypair::generate_ed25519);
+        info!(
+            "initializing new Node with id: {}",
+            PeerId::from(id_keys.public())
+        );
+        let security = self.config.security_layer();
+        let transport = match self.transport {
+            TransportKind::Tcp => Node::upgrade(
+                TokioTcpTransport::new(GenTcpConfig::default().nodelay(true)),
+                &id_keys,
+                security,
+            )?,
+            TransportKind::Memory => Node::upgrade(MemoryTransport::default(), &id_keys, security)?,
+        };
+        debug!("{:?} transport setup ok", self.transport);
+        let mdns = if self.mdns {
+            Some(Mdns::new(Default::default()).await?)
+        } else {
+            None
+        };
+        Ok(Node::build(
+            id_keys,
+            transport,
+            mdns,
+            &self.topic,
+            &self.config,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::net::Msg;
+
+    use libp2p::floodsub::Topic;
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn should_build_node_with_options() {
+        let keypair = identity::Keypair::generate_ed25519();
+        let peer_id = PeerId::from(keypair.public());
+        let mut node = NodeBuilder::default()
+            .config(NodeConfig::default().max_clock_skew(Duration::from_secs(30)))
+            .keypair(keypair)
+            .mdns(false)
+            .topic("jab-testnet")
+            .transport(TransportKind::Memory)
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(node.id(), peer_id.to_string());
+        assert_eq!(node.topic, Topic::new("jab-testnet"));
+        assert!(!node.swarm.behaviour().mdns.is_enabled());
+        assert_eq!(
+            node.swarm.behaviour().max_clock_skew,
+            Some(Duration::from_secs(30))
+        );
+        assert!(node.publish(Msg::request_block(1)).await.is_ok());
+    }
+}
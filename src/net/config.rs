@@ -0,0 +1,109 @@
+//! # config
+//!
+//! exposes the configuration for the node
+
+use super::MessageRate;
+use crate::blockchain::{Clock, SystemClock};
+
+use libp2p::Multiaddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Security layer used to authenticate and encrypt connections
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SecurityLayer {
+    /// Noise XX handshake, with authenticated encryption
+    Noise,
+    /// No encryption at all. Use it ONLY on trusted networks and in tests
+    #[cfg(feature = "insecure-plaintext")]
+    Plaintext,
+}
+
+impl Default for SecurityLayer {
+    fn default() -> Self {
+        Self::Noise
+    }
+}
+
+/// Node configuration
+#[derive(Debug, Default, Clone)]
+pub struct NodeConfig {
+    security: SecurityLayer,
+    max_clock_skew: Option<Duration>,
+    clock: Option<Arc<dyn Clock>>,
+    external_address: Option<Multiaddr>,
+    max_message_rate: Option<MessageRate>,
+    max_penalty: Option<u32>,
+}
+
+impl NodeConfig {
+    /// Set the security layer for connections
+    pub fn security(mut self, security: SecurityLayer) -> Self {
+        self.security = security;
+        self
+    }
+
+    /// Set the maximum time the timestamp of a message may be ahead of the local clock.
+    /// Messages beyond it are dropped. If unset, timestamps are not checked
+    pub fn max_clock_skew(mut self, max_clock_skew: Duration) -> Self {
+        self.max_clock_skew = Some(max_clock_skew);
+        self
+    }
+
+    /// Set the clock the timestamps of messages are checked against. If unset, the system clock is used
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+
+    /// Set the address the node is reachable at, advertised to peers instead of the listener address.
+    /// Use this when the node is behind NAT or port forwarding
+    pub fn external_address(mut self, address: Multiaddr) -> Self {
+        self.external_address = Some(address);
+        self
+    }
+
+    /// Set the maximum rate of the messages accepted from a single peer, whatever their type.
+    /// Messages beyond it are dropped and the peer is penalized. If unset, messages are not limited
+    pub fn max_message_rate(mut self, rate: MessageRate) -> Self {
+        self.max_message_rate = Some(rate);
+        self
+    }
+
+    /// Set the penalty at which a peer is disconnected and banned.
+    /// Peers are penalized for each malformed or exceeding message. If unset, peers are never banned
+    pub fn max_penalty(mut self, penalty: u32) -> Self {
+        self.max_penalty = Some(penalty);
+        self
+    }
+
+    /// Get the penalty at which a peer is banned
+    pub fn penalty_limit(&self) -> Option<u32> {
+        self.max_penalty
+    }
+
+    /// Get the maximum rate of the messages accepted from a single peer
+    pub fn message_rate(&self) -> Option<MessageRate> {
+        self.max_message_rate
+    }
+
+    /// Get the external address of the node
+    pub fn advertised_address(&self) -> Option<&Multiaddr> {
+        self.external_address.as_ref()
+    }
+
+    /// Get the maximum clock skew allowed for messages timestamps
+    pub fn clock_skew(&self) -> Option<Duration> {
+        self.max_clock_skew
+    }
+
+    /// Get the clock the timestamps of messages are checked against
+    pub fn clock_source(&self) -> Arc<dyn Clock> {
+        self.clock.clone().unwrap_or_else(|| Arc::new(SystemClock))
+    }
+
+    /// Get the security layer for connections
+    pub fn security_layer(&self) -> SecurityLayer {
+        self.security
+    }
+}
@@ -3,6 +3,7 @@
 //! exposes result and error types for node
 
 use libp2p::{noise::NoiseError, TransportError};
+use std::time::Duration;
 use thiserror::Error;
 
 /// Node result
@@ -15,10 +16,16 @@ pub enum NodeError {
     Io(std::io::Error),
     #[error("invalid payload codec: {0}")]
     InvalidPayload(serde_json::Error),
+    #[error("unknown message type: {0}")]
+    UnknownMessage(String),
     #[error("noise error: {0}")]
     Noise(NoiseError),
     #[error("transport error: {0}")]
     TransportError(TransportError<std::io::Error>),
+    #[error("listener closed before the node became ready")]
+    ListenerClosed,
+    #[error("node not ready after {0:?}: no listen address")]
+    ReadyTimeout(Duration),
 }
 
 impl From<serde_json::Error> for NodeError {
@@ -0,0 +1,36 @@
+//! # Error
+//!
+//! This module defines the generic error response, sent back to the requester of an unserviceable request
+
+use thiserror::Error;
+
+/// Error response payload
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Error {
+    /// Type of the request message this error replies to
+    pub in_reply_to: String,
+    /// Error code
+    pub code: ErrorCode,
+    /// Error description
+    pub description: String,
+}
+
+impl Error {
+    pub fn new(in_reply_to: impl ToString, code: ErrorCode, description: impl ToString) -> Self {
+        Self {
+            in_reply_to: in_reply_to.to_string(),
+            code,
+            description: description.to_string(),
+        }
+    }
+}
+
+/// Error code for error responses
+#[derive(Error, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    #[error("block #{0} could not be found")]
+    BlockNotFound(u64),
+    #[error("blockchain error")]
+    BlockchainError,
+}
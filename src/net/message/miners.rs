@@ -4,15 +4,64 @@
 
 use crate::mining::Miner;
 
+use rand::seq::SliceRandom;
+
+/// Default maximum amount of miners advertised in a `RegisterMiners` message
+pub const DEFAULT_MAX_ADVERTISED_MINERS: usize = 64;
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct RegisteredMiners {
     pub miners: Vec<Miner>,
 }
 
 impl RegisteredMiners {
-    pub fn new(miners: &[Miner]) -> Self {
-        Self {
-            miners: miners.to_vec(),
-        }
+    /// Instantiate a new `RegisteredMiners` with at most `max_miners` of `miners`, sorted by id.
+    /// If there are more miners, a random sample is taken, so each advertisement spreads a different subset
+    pub fn new(miners: &[Miner], max_miners: usize) -> Self {
+        let mut miners: Vec<Miner> = miners
+            .choose_multiple(&mut rand::thread_rng(), max_miners)
+            .cloned()
+            .collect();
+        miners.sort_by(|a, b| a.id().cmp(b.id()));
+        Self { miners }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_cap_and_sort_advertised_miners() {
+        let miners: Vec<Miner> = ["omar", "alice", "pippo", "bob"]
+            .into_iter()
+            .map(Miner::new)
+            .collect();
+        let message = RegisteredMiners::new(&miners, 3);
+        assert_eq!(message.miners.len(), 3);
+        assert!(message.miners.windows(2).all(|x| x[0].id() < x[1].id()));
+        let ids: Vec<&str> = RegisteredMiners::new(&miners, 10)
+            .miners
+            .iter()
+            .map(|x| x.id())
+            .collect();
+        assert_eq!(ids, vec!["alice", "bob", "omar", "pippo"]);
+    }
+
+    #[test]
+    fn should_advertise_every_miner_over_time() {
+        use std::collections::HashSet;
+
+        let miners: Vec<Miner> = (0..100)
+            .map(|x| Miner::new(format!("miner-{}", x)))
+            .collect();
+        let advertised: HashSet<String> = (0..1000)
+            .flat_map(|_| RegisteredMiners::new(&miners, 10).miners)
+            .map(|x| x.id().to_string())
+            .collect();
+        assert_eq!(advertised.len(), miners.len());
     }
 }
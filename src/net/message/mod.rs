@@ -3,30 +3,91 @@
 //! This module expose the different Messages supported by the P2P network
 
 mod block;
+mod error;
 mod miners;
 mod request_block;
+#[cfg(test)]
+mod roundtrip;
+mod tip;
 mod transaction;
 mod wallet;
 
 use crate::{
     blockchain::{Block as ChainBlock, Transaction as BlockchainTransaction},
     mining::Miner,
+    net::{NodeError, NodeResult},
 };
 
 use block::Block;
+pub use error::{Error, ErrorCode};
 use miners::RegisteredMiners;
+pub use miners::DEFAULT_MAX_ADVERTISED_MINERS;
 use request_block::RequestBlock;
+pub use request_block::RequestBlockRange;
 use rust_decimal::Decimal;
+use std::time::SystemTime;
+pub use tip::{BlockId, GetBlockId, GetTip, Tip};
 pub use transaction::{
-    Transaction, TransactionError, TransactionErrorCode, TransactionResult, TransactionStatus,
+    GetTransactionBlock, Transaction, TransactionAccepted, TransactionBlock, TransactionError,
+    TransactionErrorCode, TransactionResult, TransactionStatus,
 };
-pub use wallet::{WalletQuery, WalletQueryError, WalletQueryResult, WalletTransactions};
+pub use wallet::{
+    WalletBalance, WalletQuery, WalletQueryError, WalletQueryResult, WalletTransactions,
+    WalletTransactionsPage, WalletTransactionsPages, MAX_WALLET_TRANSACTIONS_PAGES,
+};
+
+/// Declare the type tag of each `Msg` variant, along with the current schema version of its payload.
+/// Generates both `MESSAGE_TYPES` and `Msg::name`, so they can't get out of sync
+macro_rules! message_types {
+    ($($variant:ident => ($name:literal, $version:literal),)*) => {
+        /// Type tags of the messages supported by this version of the protocol, with the current schema version of
+        /// their payload
+        const MESSAGE_TYPES: &[(&str, u64)] = &[$(($name, $version),)*];
+
+        impl Msg {
+            /// Get the message type name, as serialized in the `type` tag
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Self::$variant { .. } => $name,)*
+                }
+            }
+        }
+    };
+}
+
+// Bump the version when changing the fields of a message, and migrate the older versions in `Msg::migrate`
+message_types! {
+    RequestBlock => ("REQUEST_BLOCK", 2),
+    RequestBlockRange => ("REQUEST_BLOCK_RANGE", 2),
+    Block => ("BLOCK", 1),
+    RegisterMiners => ("REGISTER_MINERS", 1),
+    RequestRegisteredMiners => ("REQUEST_REGISTERED_MINERS", 1),
+    Transaction => ("TRANSACTION", 2),
+    TransactionResult => ("TRANSACTION_RESULT", 2),
+    GetTransactionBlock => ("GET_TRANSACTION_BLOCK", 1),
+    TransactionBlock => ("TRANSACTION_BLOCK", 1),
+    WalletDetails => ("WALLET_DETAILS", 2),
+    WalletBalance => ("WALLET_BALANCE", 2),
+    WalletDetailsResult => ("WALLET_DETAILS_RESULT", 2),
+    GetTip => ("GET_TIP", 1),
+    Tip => ("TIP", 1),
+    GetBlockId => ("GET_BLOCK_ID", 1),
+    BlockId => ("BLOCK_ID", 1),
+    Error => ("ERROR", 1),
+}
+
+/// Name of the payload field carrying the schema version
+const VERSION_FIELD: &str = "v";
+/// Schema version of payloads which don't carry any, sent before versioning was introduced
+const UNVERSIONED: u64 = 1;
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 #[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum Msg {
     /// A message to request block with provided index
     RequestBlock(RequestBlock),
+    /// A message to request all the blocks in a range of indexes
+    RequestBlockRange(RequestBlockRange),
     /// A message which responds with a requested block
     Block(Block),
     /// A message which informs other peers to register the following miners
@@ -37,26 +98,136 @@ pub enum Msg {
     Transaction(Transaction),
     /// A message sent back to the client, with the result of the transaction
     TransactionResult(TransactionResult),
+    /// Request to a node the index of the block containing a transaction
+    GetTransactionBlock(GetTransactionBlock),
+    /// A message sent back to the requester of `GetTransactionBlock`
+    TransactionBlock(TransactionBlock),
     /// A message sent by the client to request the wallet amount and the list of transactions for a certain address
     WalletDetails(WalletQuery),
+    /// A message sent by the client to request only the wallet amount for a certain address
+    WalletBalance(WalletQuery),
     /// A message sent by a node to the client with the amount of the requested wallet
     WalletDetailsResult(WalletQueryResult),
+    /// Request to a node the index and hash of its latest block
+    GetTip(GetTip),
+    /// A message sent back to the requester of `GetTip` with the latest block
+    Tip(Tip),
+    /// Request to a node the id of its block at an index
+    GetBlockId(GetBlockId),
+    /// A message sent back to the requester of `GetBlockId` with the id of the block
+    BlockId(BlockId),
+    /// A message sent back to the requester of a request which couldn't be served
+    Error(Error),
 }
 
 impl Msg {
+    /// Decode a message received from the network.
+    /// Well-formed messages with a type tag or a schema version unknown to this version (e.g. sent by a newer peer)
+    /// are reported as `UnknownMessage`, while corrupt payloads are reported as `InvalidPayload`.
+    /// Payloads with an older schema version are migrated to the current one
+    pub fn decode(data: &[u8]) -> NodeResult<Self> {
+        let mut value: serde_json::Value = serde_json::from_slice(data)?;
+        if let Some(kind) = value.get("type").and_then(|x| x.as_str()) {
+            let kind = kind.to_string();
+            let current = match Self::schema_version(&kind) {
+                Some(version) => version,
+                None => return Err(NodeError::UnknownMessage(kind)),
+            };
+            let version = match value.get(VERSION_FIELD) {
+                None => UNVERSIONED,
+                Some(version) => version.as_u64().ok_or_else(|| {
+                    NodeError::from(<serde_json::Error as serde::de::Error>::custom(
+                        "bad schema version",
+                    ))
+                })?,
+            };
+            if version > current {
+                return Err(NodeError::UnknownMessage(format!("{} v{}", kind, version)));
+            }
+            Self::migrate(&kind, version, &mut value);
+        }
+        serde_json::from_value(value).map_err(NodeError::from)
+    }
+
+    /// Encode message to be sent on the network, tagged with its schema version
+    pub fn encode(&self) -> Vec<u8> {
+        let mut value = serde_json::json!(self);
+        if let (Some(payload), Some(version)) =
+            (value.as_object_mut(), Self::schema_version(self.name()))
+        {
+            payload.insert(VERSION_FIELD.to_string(), version.into());
+        }
+        value.to_string().into_bytes()
+    }
+
+    /// Get the current schema version of messages of type `kind`
+    fn schema_version(kind: &str) -> Option<u64> {
+        MESSAGE_TYPES
+            .iter()
+            .find(|(name, _)| *name == kind)
+            .map(|(_, version)| *version)
+    }
+
+    /// Migrate `payload` of a message of type `kind` from schema `version` to the current one
+    fn migrate(kind: &str, version: u64, payload: &mut serde_json::Value) {
+        let payload = match payload.as_object_mut() {
+            Some(payload) => payload,
+            None => return,
+        };
+        match (kind, version) {
+            // v2 added the id of the peer expecting a reply
+            ("REQUEST_BLOCK", 1) => {
+                payload.entry("peer_id").or_insert(serde_json::Value::Null);
+            }
+            // v2 added the id of the peer the blocks are sent to
+            ("REQUEST_BLOCK_RANGE", 1) => {
+                payload.entry("peer_id").or_insert(serde_json::Value::Null);
+            }
+            // v2 added the nonce chosen by the issuer; earlier transactions have none
+            ("TRANSACTION", 1) => {
+                payload.entry("nonce").or_insert_with(|| 0.into());
+            }
+            // v2 added the id and the block index of accepted transactions
+            ("TRANSACTION_RESULT", 1) => {
+                payload.entry("accepted").or_insert(serde_json::Value::Null);
+            }
+            // v2 added the id of the request
+            ("WALLET_DETAILS" | "WALLET_BALANCE", 1) => {
+                payload.entry("request_id").or_insert(0.into());
+            }
+            // v2 added the responder and the request id to pages
+            ("WALLET_DETAILS_RESULT", 1) if payload.get("status") == Some(&"PAGE".into()) => {
+                payload.entry("responder").or_insert("".into());
+                payload.entry("request_id").or_insert(0.into());
+            }
+            _ => {}
+        }
+    }
+
     /// Create a `RequestBlock` message
     pub fn request_block(index: u64) -> Self {
         Self::RequestBlock(RequestBlock::new(index))
     }
 
+    /// Create a `RequestBlock` message, to which nodes reply with an `Error` if they can't serve the block
+    pub fn request_block_with_reply(peer_id: impl ToString, index: u64) -> Self {
+        Self::RequestBlock(RequestBlock::new(index).with_reply(peer_id))
+    }
+
+    /// Create a `RequestBlockRange` message, requesting blocks from `from` to `to` (included) for `peer_id`
+    pub fn request_block_range(peer_id: impl ToString, from: u64, to: u64) -> Self {
+        Self::RequestBlockRange(RequestBlockRange::new(peer_id, from, to))
+    }
+
     /// Create a `Block` message
     pub fn block(block: ChainBlock) -> Self {
         Self::Block(Block::new(block))
     }
 
-    /// Create a `RegisterMiners` message
-    pub fn register_miners(miners: &[Miner]) -> Self {
-        Self::RegisterMiners(RegisteredMiners::new(miners))
+    /// Create a `RegisterMiners` message, advertising at most `max_miners` miners sorted by id, randomly sampled if
+    /// there are more
+    pub fn register_miners(miners: &[Miner], max_miners: usize) -> Self {
+        Self::RegisterMiners(RegisteredMiners::new(miners, max_miners))
     }
 
     /// Create a `RequestRegisteredMiners` message
@@ -70,6 +241,7 @@ impl Msg {
         input_address: impl ToString,
         output_address: impl ToString,
         amount: Decimal,
+        nonce: u64,
         public_key: impl ToString,
         signature: impl ToString,
     ) -> Self {
@@ -78,27 +250,33 @@ impl Msg {
             input_address,
             output_address,
             amount,
+            nonce,
             public_key,
             signature,
         ))
     }
 
-    /// Create a successful `TransactionResult` message
-    pub fn transaction_result_ok() -> Self {
-        Self::TransactionResult(TransactionResult::new(TransactionStatus::Ok, None))
+    /// Create a successful `TransactionResult` message, reporting the id of the transaction and the index of the
+    /// block it has been mined in
+    pub fn transaction_result_ok(txid: impl ToString, block_index: u64) -> Self {
+        Self::TransactionResult(
+            TransactionResult::new(TransactionStatus::Ok, None).with_accepted(txid, block_index),
+        )
     }
 
     /// Create a `TransactionResult` with error message
-    pub fn transaction_result_nok(code: TransactionErrorCode, description: impl ToString) -> Self {
-        Self::TransactionResult(TransactionResult::new(
-            TransactionStatus::Nok,
-            Some(TransactionError::new(code, description)),
-        ))
+    pub fn transaction_result_nok(error: TransactionError) -> Self {
+        Self::TransactionResult(TransactionResult::new(TransactionStatus::Nok, Some(error)))
     }
 
     /// Create a `WalletDetails` message
-    pub fn wallet_details(peer_id: impl ToString, address: impl ToString) -> Self {
-        Self::WalletDetails(WalletQuery::new(peer_id, address))
+    pub fn wallet_details(peer_id: impl ToString, request_id: u64, address: impl ToString) -> Self {
+        Self::WalletDetails(WalletQuery::new(peer_id, request_id, address))
+    }
+
+    /// Create a `WalletBalance` message
+    pub fn wallet_balance(peer_id: impl ToString, request_id: u64, address: impl ToString) -> Self {
+        Self::WalletBalance(WalletQuery::new(peer_id, request_id, address))
     }
 
     /// Create a OK variant of a `WalletDetailsResult` message
@@ -114,4 +292,189 @@ impl Msg {
     pub fn wallet_details_result_error(error: WalletQueryError) -> Self {
         Self::WalletDetailsResult(WalletQueryResult::error(error))
     }
+
+    /// Create a `GetTransactionBlock` message
+    pub fn get_transaction_block(peer_id: impl ToString, txid: impl ToString) -> Self {
+        Self::GetTransactionBlock(GetTransactionBlock::new(peer_id, txid))
+    }
+
+    /// Create a `TransactionBlock` message
+    pub fn transaction_block(txid: impl ToString, block_index: Option<u64>) -> Self {
+        Self::TransactionBlock(TransactionBlock::new(txid, block_index))
+    }
+
+    /// Create a `GetTip` message
+    pub fn get_tip(peer_id: impl ToString) -> Self {
+        Self::GetTip(GetTip::new(peer_id))
+    }
+
+    /// Create a `Tip` message
+    pub fn tip(index: u64, hash: impl ToString) -> Self {
+        Self::Tip(Tip::new(index, hash))
+    }
+
+    /// Create a `GetBlockId` message
+    pub fn get_block_id(peer_id: impl ToString, index: u64) -> Self {
+        Self::GetBlockId(GetBlockId::new(peer_id, index))
+    }
+
+    /// Create a `BlockId` message
+    pub fn block_id(index: u64, id: Option<String>) -> Self {
+        Self::BlockId(BlockId::new(index, id))
+    }
+
+    /// Create an `Error` message replying to a message of type `in_reply_to`
+    pub fn error(in_reply_to: impl ToString, code: ErrorCode, description: impl ToString) -> Self {
+        Self::Error(Error::new(in_reply_to, code, description))
+    }
+
+    /// Get the time the message payload has been created at, if it carries any
+    pub fn timestamp(&self) -> Option<SystemTime> {
+        match self {
+            Self::Block(block) => Some(block.block.header().created_at()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn should_decode_messages() {
+        assert_eq!(
+            Msg::decode(br#"{"type":"REQUEST_BLOCK","index":3}"#).unwrap(),
+            Msg::request_block(3)
+        );
+        for (kind, _) in MESSAGE_TYPES {
+            assert!(!matches!(
+                Msg::decode(format!(r#"{{"type":"{}"}}"#, kind).as_bytes()),
+                Err(NodeError::UnknownMessage(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn should_distinguish_unknown_messages_from_malformed_payloads() {
+        assert!(matches!(
+            Msg::decode(br#"{"type":"FROM_THE_FUTURE","foo":1}"#),
+            Err(NodeError::UnknownMessage(kind)) if kind == "FROM_THE_FUTURE"
+        ));
+        assert!(matches!(
+            Msg::decode(b"{\"type\":\"REQUEST_BL"),
+            Err(NodeError::InvalidPayload(_))
+        ));
+        assert!(matches!(
+            Msg::decode(br#"{"type":"REQUEST_BLOCK","index":"three"}"#),
+            Err(NodeError::InvalidPayload(_))
+        ));
+        assert!(matches!(
+            Msg::decode(br#"{"index":3}"#),
+            Err(NodeError::InvalidPayload(_))
+        ));
+    }
+
+    #[test]
+    fn should_encode_messages_with_schema_version() {
+        let message = Msg::get_tip("peer");
+        let json: serde_json::Value = serde_json::from_slice(&message.encode()).unwrap();
+        assert_eq!(json["v"].as_u64(), Some(1));
+        assert_eq!(Msg::decode(&message.encode()).unwrap(), message);
+        let message = Msg::request_block_with_reply("peer", 3);
+        let json: serde_json::Value = serde_json::from_slice(&message.encode()).unwrap();
+        assert_eq!(json["v"].as_u64(), Some(2));
+        assert_eq!(Msg::decode(&message.encode()).unwrap(), message);
+        // unit variants carry the version too
+        assert_eq!(
+            Msg::decode(&Msg::request_registered_miners().encode()).unwrap(),
+            Msg::request_registered_miners()
+        );
+    }
+
+    #[test]
+    fn should_migrate_prior_version_payloads() {
+        // payloads sent before versioning have the first version
+        assert_eq!(
+            Msg::decode(br#"{"type":"REQUEST_BLOCK","index":3}"#).unwrap(),
+            Msg::request_block(3)
+        );
+        assert_eq!(
+            Msg::decode(br#"{"type":"TRANSACTION_RESULT","status":"OK","error":null}"#).unwrap(),
+            Msg::TransactionResult(TransactionResult::new(TransactionStatus::Ok, None))
+        );
+        assert_eq!(
+            Msg::decode(br#"{"type":"WALLET_DETAILS","peer_id":"peer","address":"jab"}"#).unwrap(),
+            Msg::wallet_details("peer", 0, "jab")
+        );
+        assert_eq!(
+            Msg::decode(br#"{"type":"TRANSACTION","peer_id":"peer","input_address":"alice","output_address":"bob","amount":"10","public_key":"pk","signature":"sig"}"#).unwrap(),
+            Msg::transaction("peer", "alice", "bob", Decimal::from(10), 0, "pk", "sig")
+        );
+        assert_eq!(
+            Msg::decode(br#"{"type":"REQUEST_BLOCK_RANGE","from":1,"to":16}"#).unwrap(),
+            Msg::RequestBlockRange(RequestBlockRange {
+                from: 1,
+                to: 16,
+                peer_id: None
+            })
+        );
+    }
+
+    #[test]
+    fn should_report_accepted_transaction_in_result() {
+        let message = Msg::transaction_result_ok("cafebabe", 7);
+        let json: serde_json::Value = serde_json::from_slice(&message.encode()).unwrap();
+        assert_eq!(json["v"].as_u64(), Some(2));
+        assert_eq!(json["accepted"]["txid"].as_str(), Some("cafebabe"));
+        assert_eq!(json["accepted"]["block_index"].as_u64(), Some(7));
+        assert_eq!(Msg::decode(&message.encode()).unwrap(), message);
+    }
+
+    #[test]
+    fn should_ignore_newer_schema_versions() {
+        assert!(matches!(
+            Msg::decode(br#"{"type":"TIP","v":3,"height":1}"#),
+            Err(NodeError::UnknownMessage(kind)) if kind == "TIP v3"
+        ));
+        assert!(matches!(
+            Msg::decode(br#"{"type":"TIP","v":"two","index":1,"hash":"cafebabe"}"#),
+            Err(NodeError::InvalidPayload(_))
+        ));
+    }
+
+    #[test]
+    fn should_serialize_message_name_as_type_tag() {
+        let messages = vec![
+            Msg::request_block(1),
+            Msg::request_block_with_reply("peer", 1),
+            Msg::request_block_range("peer", 1, 16),
+            Msg::request_registered_miners(),
+            Msg::register_miners(&[Miner::new("peer")], DEFAULT_MAX_ADVERTISED_MINERS),
+            Msg::transaction_result_ok("txid", 1),
+            Msg::get_transaction_block("peer", "txid"),
+            Msg::transaction_block("txid", Some(1)),
+            Msg::wallet_details("peer", 1, "address"),
+            Msg::wallet_balance("peer", 1, "address"),
+            Msg::wallet_details_result_error(WalletQueryError::WalletNotFound),
+            Msg::get_tip("peer"),
+            Msg::tip(3, "cafebabe"),
+            Msg::get_block_id("peer", 3),
+            Msg::block_id(3, Some(String::from("cafebabe"))),
+            Msg::error("REQUEST_BLOCK", ErrorCode::BlockNotFound(1), "not found"),
+        ];
+        for message in messages {
+            let json = serde_json::json!(message);
+            assert_eq!(json["type"].as_str().unwrap(), message.name());
+        }
+    }
+
+    #[test]
+    fn should_decode_request_block_without_peer_id() {
+        let message: Msg = serde_json::from_str(r#"{"type":"REQUEST_BLOCK","index":3}"#).unwrap();
+        assert_eq!(message, Msg::request_block(3));
+    }
 }
@@ -5,10 +5,42 @@
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct RequestBlock {
     pub index: u64,
+    /// Id of the requesting peer. If set, the peer expects an error response if the block can't be served
+    #[serde(default)]
+    pub peer_id: Option<String>,
 }
 
 impl RequestBlock {
     pub fn new(index: u64) -> Self {
-        Self { index }
+        Self {
+            index,
+            peer_id: None,
+        }
+    }
+
+    /// Set the id of the peer which expects a reply
+    pub fn with_reply(mut self, peer_id: impl ToString) -> Self {
+        self.peer_id = Some(peer_id.to_string());
+        self
+    }
+}
+
+/// A request for all the blocks with index in `from..=to`
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct RequestBlockRange {
+    pub from: u64,
+    pub to: u64,
+    /// Id of the requesting peer, which the blocks are sent to. Requests without it are not served
+    #[serde(default)]
+    pub peer_id: Option<String>,
+}
+
+impl RequestBlockRange {
+    pub fn new(peer_id: impl ToString, from: u64, to: u64) -> Self {
+        Self {
+            from,
+            to,
+            peer_id: Some(peer_id.to_string()),
+        }
     }
 }
@@ -0,0 +1,204 @@
+//! # Round trip
+//!
+//! Property tests ensuring every `Msg` variant survives serialization, so wire format breakages are caught early
+
+use super::*;
+use crate::blockchain::{Header, TransactionBuilder, TransactionVersion, Version};
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+use std::time::{Duration, UNIX_EPOCH};
+
+fn decimal() -> impl Strategy<Value = Decimal> {
+    (any::<i64>(), 0u32..=28).prop_map(|(num, scale)| Decimal::new(num, scale))
+}
+
+fn chain_transaction() -> impl Strategy<Value = BlockchainTransaction> {
+    (
+        vec((any::<String>(), decimal()), 0..4),
+        vec((any::<String>(), decimal()), 0..4),
+        any::<String>(),
+    )
+        .prop_map(|(inputs, outputs, signature)| {
+            let builder = inputs.into_iter().fold(
+                TransactionBuilder::new(TransactionVersion::V1),
+                |builder, (addr, amount)| builder.input(addr, amount),
+            );
+            outputs
+                .into_iter()
+                .fold(builder, |builder, (addr, amount)| {
+                    builder.output(addr, amount)
+                })
+                .finish(signature)
+        })
+}
+
+fn chain_block() -> impl Strategy<Value = ChainBlock> {
+    (
+        any::<u64>(),
+        proptest::option::of(any::<String>()),
+        any::<String>(),
+        any::<u32>(),
+        0u32..1_000_000_000,
+        any::<u32>(),
+        any::<u64>(),
+        chain_transaction(),
+    )
+        .prop_map(
+            |(index, previous_hash, merkle_root, secs, nanos, difficulty, nonce, transaction)| {
+                let created_at = UNIX_EPOCH + Duration::new(u64::from(secs), nanos);
+                let header = Header::new(Version::V010, previous_hash, merkle_root, created_at)
+                    .with_difficulty(difficulty)
+                    .with_nonce(nonce);
+                ChainBlock::new(index, header, transaction)
+            },
+        )
+}
+
+fn transaction_error_code() -> impl Strategy<Value = TransactionErrorCode> {
+    prop_oneof![
+        Just(TransactionErrorCode::InputWalletNotFound),
+        Just(TransactionErrorCode::OutputWalletNotFound),
+        Just(TransactionErrorCode::InsufficientBalance),
+        Just(TransactionErrorCode::InvalidSignature),
+        Just(TransactionErrorCode::InvalidPublicKey),
+        Just(TransactionErrorCode::AmountTooLarge),
+        Just(TransactionErrorCode::DuplicateTransaction),
+        Just(TransactionErrorCode::BlockchainError),
+    ]
+}
+
+fn transaction_result() -> impl Strategy<Value = TransactionResult> {
+    prop_oneof![
+        (any::<String>(), any::<u64>()).prop_map(|(txid, block_index)| {
+            TransactionResult::new(TransactionStatus::Ok, None).with_accepted(txid, block_index)
+        }),
+        (
+            transaction_error_code(),
+            any::<String>(),
+            vec((any::<String>(), any::<String>()), 0..4)
+        )
+            .prop_map(|(code, description, details)| {
+                let error = details.into_iter().fold(
+                    TransactionError::new(code, description),
+                    |error, (key, value)| error.with_detail(key, value),
+                );
+                TransactionResult::new(TransactionStatus::Nok, Some(error))
+            }),
+    ]
+}
+
+fn wallet_query_error() -> impl Strategy<Value = WalletQueryError> {
+    prop_oneof![
+        Just(WalletQueryError::BlockchainError),
+        Just(WalletQueryError::WalletNotFound),
+        Just(WalletQueryError::Overloaded),
+    ]
+}
+
+fn wallet_query_result() -> impl Strategy<Value = WalletQueryResult> {
+    prop_oneof![
+        (any::<String>(), vec(chain_transaction(), 0..4), decimal()).prop_map(
+            |(address, transactions, balance)| WalletQueryResult::ok(
+                address,
+                transactions,
+                balance
+            )
+        ),
+        (any::<String>(), decimal(), any::<u64>()).prop_map(|(address, balance, height)| {
+            WalletQueryResult::balance(address, balance, height)
+        }),
+        (
+            any::<String>(),
+            vec(chain_transaction(), 0..4),
+            decimal(),
+            any::<String>(),
+            any::<u64>(),
+            any::<usize>(),
+            any::<usize>()
+        )
+            .prop_map(
+                |(address, transactions, balance, responder, request_id, page, pages)| {
+                    WalletQueryResult::Page(WalletTransactionsPage {
+                        address,
+                        transactions,
+                        balance,
+                        responder,
+                        request_id,
+                        page,
+                        pages,
+                    })
+                }
+            ),
+        wallet_query_error().prop_map(WalletQueryResult::error),
+    ]
+}
+
+fn error_code() -> impl Strategy<Value = ErrorCode> {
+    prop_oneof![
+        any::<u64>().prop_map(ErrorCode::BlockNotFound),
+        Just(ErrorCode::BlockchainError),
+    ]
+}
+
+fn message() -> impl Strategy<Value = Msg> {
+    prop_oneof![
+        (any::<u64>(), proptest::option::of(any::<String>()))
+            .prop_map(|(index, peer_id)| Msg::RequestBlock(RequestBlock { index, peer_id })),
+        (any::<String>(), any::<u64>(), any::<u64>())
+            .prop_map(|(peer_id, from, to)| Msg::request_block_range(peer_id, from, to)),
+        chain_block().prop_map(Msg::block),
+        vec(any::<String>().prop_map(Miner::new), 0..4)
+            .prop_map(|miners| Msg::register_miners(&miners, DEFAULT_MAX_ADVERTISED_MINERS)),
+        Just(Msg::request_registered_miners()),
+        (
+            any::<String>(),
+            any::<String>(),
+            any::<String>(),
+            decimal(),
+            any::<u64>(),
+            any::<String>(),
+            any::<String>()
+        )
+            .prop_map(
+                |(peer_id, input, output, amount, nonce, public_key, signature)| {
+                    Msg::transaction(peer_id, input, output, amount, nonce, public_key, signature)
+                }
+            ),
+        transaction_result().prop_map(Msg::TransactionResult),
+        (any::<String>(), any::<String>())
+            .prop_map(|(peer_id, txid)| Msg::get_transaction_block(peer_id, txid)),
+        (any::<String>(), proptest::option::of(any::<u64>()))
+            .prop_map(|(txid, block_index)| Msg::transaction_block(txid, block_index)),
+        (any::<String>(), any::<u64>(), any::<String>()).prop_map(
+            |(peer_id, request_id, address)| Msg::wallet_details(peer_id, request_id, address)
+        ),
+        (any::<String>(), any::<u64>(), any::<String>()).prop_map(
+            |(peer_id, request_id, address)| Msg::wallet_balance(peer_id, request_id, address)
+        ),
+        wallet_query_result().prop_map(Msg::WalletDetailsResult),
+        any::<String>().prop_map(Msg::get_tip),
+        (any::<u64>(), any::<String>()).prop_map(|(index, hash)| Msg::tip(index, hash)),
+        (any::<String>(), any::<u64>())
+            .prop_map(|(peer_id, index)| Msg::get_block_id(peer_id, index)),
+        (any::<u64>(), proptest::option::of(any::<String>()))
+            .prop_map(|(index, id)| Msg::block_id(index, id)),
+        (any::<String>(), error_code(), any::<String>()).prop_map(
+            |(in_reply_to, code, description)| Msg::error(in_reply_to, code, description)
+        ),
+    ]
+}
+
+proptest! {
+
+    #[test]
+    fn should_round_trip_json(message in message()) {
+        let json = serde_json::to_string(&message).unwrap();
+        prop_assert_eq!(serde_json::from_slice::<Msg>(json.as_bytes()).unwrap(), message);
+    }
+
+    #[test]
+    fn should_round_trip_wire_encoding(message in message()) {
+        prop_assert_eq!(Msg::decode(&message.encode()).unwrap(), message);
+    }
+}
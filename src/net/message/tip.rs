@@ -0,0 +1,69 @@
+//! # Tip
+//!
+//! Messages to query the latest block of the chain and the blocks to compare it with the chain of a peer
+
+/// A request for the latest block known by a node
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct GetTip {
+    /// Id of the requesting peer
+    pub peer_id: String,
+}
+
+impl GetTip {
+    pub fn new(peer_id: impl ToString) -> Self {
+        Self {
+            peer_id: peer_id.to_string(),
+        }
+    }
+}
+
+/// The latest block known by a node
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct Tip {
+    /// Index of the latest block
+    pub index: u64,
+    /// Merkle root hash of the latest block
+    pub hash: String,
+}
+
+impl Tip {
+    pub fn new(index: u64, hash: impl ToString) -> Self {
+        Self {
+            index,
+            hash: hash.to_string(),
+        }
+    }
+}
+
+/// A request for the id of the block at an index, used to find the fork point with a peer (see `Chain::diff`)
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct GetBlockId {
+    /// Id of the requesting peer
+    pub peer_id: String,
+    /// Index of the block
+    pub index: u64,
+}
+
+impl GetBlockId {
+    pub fn new(peer_id: impl ToString, index: u64) -> Self {
+        Self {
+            peer_id: peer_id.to_string(),
+            index,
+        }
+    }
+}
+
+/// The id of the block at an index
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct BlockId {
+    /// Index of the block
+    pub index: u64,
+    /// Merkle root hash of the block; `None` if the node doesn't have the block
+    pub id: Option<String>,
+}
+
+impl BlockId {
+    pub fn new(index: u64, id: Option<String>) -> Self {
+        Self { index, id }
+    }
+}
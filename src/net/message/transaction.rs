@@ -3,6 +3,7 @@
 //! This module defines the payload for a transaction
 
 use rust_decimal::Decimal;
+use std::collections::BTreeMap;
 use thiserror::Error;
 
 /// Transaction payload. Used to send money from a wallet to another
@@ -16,6 +17,8 @@ pub struct Transaction {
     pub output_address: String,
     /// Amount to send
     pub amount: Decimal,
+    /// Nonce chosen by the issuer and covered by the signature
+    pub nonce: u64,
     /// Wallte public key
     pub public_key: String,
     /// Transaction signature
@@ -29,6 +32,7 @@ impl Transaction {
         input_address: impl ToString,
         output_address: impl ToString,
         amount: Decimal,
+        nonce: u64,
         public_key: impl ToString,
         signature: impl ToString,
     ) -> Self {
@@ -37,6 +41,7 @@ impl Transaction {
             input_address: input_address.to_string(),
             output_address: output_address.to_string(),
             amount,
+            nonce,
             public_key: public_key.to_string(),
             signature: signature.to_string(),
         }
@@ -48,11 +53,77 @@ impl Transaction {
 pub struct TransactionResult {
     pub status: TransactionStatus,
     pub error: Option<TransactionError>,
+    /// Where the transaction has been mined; set only if the status is `Ok`
+    pub accepted: Option<TransactionAccepted>,
 }
 
 impl TransactionResult {
     pub fn new(status: TransactionStatus, error: Option<TransactionError>) -> Self {
-        Self { status, error }
+        Self {
+            status,
+            error,
+            accepted: None,
+        }
+    }
+
+    /// Set the id and the block index of the mined transaction
+    pub fn with_accepted(mut self, txid: impl ToString, block_index: u64) -> Self {
+        self.accepted = Some(TransactionAccepted::new(txid, block_index));
+        self
+    }
+}
+
+/// Details of a transaction accepted by a node
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct TransactionAccepted {
+    /// Id of the transaction, as mined by the node
+    pub txid: String,
+    /// Index of the block containing the transaction
+    pub block_index: u64,
+}
+
+impl TransactionAccepted {
+    pub fn new(txid: impl ToString, block_index: u64) -> Self {
+        Self {
+            txid: txid.to_string(),
+            block_index,
+        }
+    }
+}
+
+/// A request for the block containing a transaction, used by clients to confirm a transaction mined by another node
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct GetTransactionBlock {
+    /// Id of the requesting peer
+    pub peer_id: String,
+    /// Id or signed id of the transaction
+    pub txid: String,
+}
+
+impl GetTransactionBlock {
+    pub fn new(peer_id: impl ToString, txid: impl ToString) -> Self {
+        Self {
+            peer_id: peer_id.to_string(),
+            txid: txid.to_string(),
+        }
+    }
+}
+
+/// The block containing a transaction
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct TransactionBlock {
+    /// Id or signed id of the transaction, as requested
+    pub txid: String,
+    /// Index of the block containing the transaction; `None` if it hasn't been mined
+    pub block_index: Option<u64>,
+}
+
+impl TransactionBlock {
+    pub fn new(txid: impl ToString, block_index: Option<u64>) -> Self {
+        Self {
+            txid: txid.to_string(),
+            block_index,
+        }
     }
 }
 
@@ -70,6 +141,9 @@ pub enum TransactionStatus {
 pub struct TransactionError {
     pub code: TransactionErrorCode,
     pub description: String,
+    /// Machine-readable details of the rejection (e.g. the `required` and `available` amounts), so clients can render precise messages
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub details: BTreeMap<String, String>,
 }
 
 impl TransactionError {
@@ -77,8 +151,20 @@ impl TransactionError {
         Self {
             code,
             description: description.to_string(),
+            details: BTreeMap::new(),
         }
     }
+
+    /// Add a detail to the error
+    pub fn with_detail(mut self, key: impl ToString, value: impl ToString) -> Self {
+        self.details.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Get detail with `key`
+    pub fn detail(&self, key: &str) -> Option<&str> {
+        self.details.get(key).map(|x| x.as_str())
+    }
 }
 
 /// Transaction error code
@@ -93,6 +179,12 @@ pub enum TransactionErrorCode {
     InsufficientBalance,
     #[error("the transaction signature is invalid")]
     InvalidSignature,
+    #[error("the public key is malformed")]
+    InvalidPublicKey,
+    #[error("the transaction amount exceeds the maximum allowed")]
+    AmountTooLarge,
+    #[error("the transaction has already been mined")]
+    DuplicateTransaction,
     #[error("blockchain error")]
     BlockchainError,
 }
@@ -5,21 +5,28 @@
 use crate::blockchain::Transaction;
 
 use rust_decimal::Decimal;
+use std::collections::HashMap;
 use thiserror::Error;
 
+/// Maximum amount of pages a wallet query response may be split into
+pub const MAX_WALLET_TRANSACTIONS_PAGES: usize = 1024;
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct WalletQuery {
     /// Id of the requesting peer
     pub peer_id: String,
+    /// Id of the request, chosen by the requesting peer and echoed in paged responses
+    pub request_id: u64,
     /// Address of the wallet to query
     pub address: String,
 }
 
 impl WalletQuery {
     /// Instantiate a new `WalletQuery`
-    pub fn new(peer_id: impl ToString, address: impl ToString) -> Self {
+    pub fn new(peer_id: impl ToString, request_id: u64, address: impl ToString) -> Self {
         Self {
             peer_id: peer_id.to_string(),
+            request_id,
             address: address.to_string(),
         }
     }
@@ -30,6 +37,10 @@ impl WalletQuery {
 /// Wallet query result
 pub enum WalletQueryResult {
     Ok(WalletTransactions),
+    /// Balance-only response, sent in reply to a `WalletBalance` query
+    Balance(WalletBalance),
+    /// A page of a `Ok` response too large to be sent in a single message
+    Page(WalletTransactionsPage),
     Error(WalletQueryError),
 }
 
@@ -43,6 +54,15 @@ impl WalletQueryResult {
         })
     }
 
+    /// Instantiate a `Balance` variant of `WalletQueryResult`
+    pub fn balance(address: impl ToString, balance: Decimal, height: u64) -> Self {
+        Self::Balance(WalletBalance {
+            address: address.to_string(),
+            balance,
+            height,
+        })
+    }
+
     /// Instantiate a `Error` variant of `WalletQueryResult`
     pub fn error(err: WalletQueryError) -> Self {
         Self::Error(err)
@@ -60,6 +80,113 @@ pub struct WalletTransactions {
     pub balance: Decimal,
 }
 
+impl WalletTransactions {
+    /// Split into `WalletQueryResult`s carrying at most `page_size` transactions each, tagged with the id of the
+    /// `responder` and with `request_id`. If all the transactions fit a single page, a single `Ok` result is returned.
+    /// Pages grow beyond `page_size` rather than exceeding `MAX_WALLET_TRANSACTIONS_PAGES`
+    pub fn into_pages(
+        self,
+        page_size: usize,
+        responder: &str,
+        request_id: u64,
+    ) -> Vec<WalletQueryResult> {
+        let page_size = page_size.max(1).max(
+            (self.transactions.len() + MAX_WALLET_TRANSACTIONS_PAGES - 1)
+                / MAX_WALLET_TRANSACTIONS_PAGES,
+        );
+        if self.transactions.len() <= page_size {
+            return vec![WalletQueryResult::Ok(self)];
+        }
+        let chunks: Vec<Vec<Transaction>> = self
+            .transactions
+            .chunks(page_size)
+            .map(|x| x.to_vec())
+            .collect();
+        let pages = chunks.len();
+        chunks
+            .into_iter()
+            .enumerate()
+            .map(|(page, transactions)| {
+                WalletQueryResult::Page(WalletTransactionsPage {
+                    address: self.address.clone(),
+                    transactions,
+                    balance: self.balance,
+                    responder: responder.to_string(),
+                    request_id,
+                    page,
+                    pages,
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+/// A page of transactions for a certain wallet (PAGE response for `WalletQueryResult`)
+pub struct WalletTransactionsPage {
+    /// Wallet address
+    pub address: String,
+    /// Transactions in this page
+    pub transactions: Vec<Transaction>,
+    /// Current wallet balance
+    pub balance: Decimal,
+    /// Id of the responding peer
+    pub responder: String,
+    /// Id of the request this page replies to
+    pub request_id: u64,
+    /// Index of this page, starting from 0
+    pub page: usize,
+    /// Total amount of pages, at most `MAX_WALLET_TRANSACTIONS_PAGES`
+    pub pages: usize,
+}
+
+/// Reassembles `WalletTransactionsPage`s into `WalletTransactions`.
+/// Pages are grouped by responder and request id, so responses from different peers never mix
+#[derive(Debug, Default)]
+pub struct WalletTransactionsPages {
+    responses: HashMap<(String, u64), Vec<Option<WalletTransactionsPage>>>,
+}
+
+impl WalletTransactionsPages {
+    /// Collect `page`. Once all the pages of its response have been collected, the reassembled `WalletTransactions`
+    /// is returned. Pages can be received in any order; pages of a response with a different amount of pages reset
+    /// the state of that response. Pages declaring more than `MAX_WALLET_TRANSACTIONS_PAGES` pages are rejected
+    pub fn push(&mut self, page: WalletTransactionsPage) -> Option<WalletTransactions> {
+        if page.page >= page.pages || page.pages > MAX_WALLET_TRANSACTIONS_PAGES {
+            return None;
+        }
+        let key = (page.responder.clone(), page.request_id);
+        let response = self.responses.entry(key.clone()).or_default();
+        if response.len() != page.pages {
+            *response = vec![None; page.pages];
+        }
+        let index = page.page;
+        response[index] = Some(page);
+        if response.iter().any(Option::is_none) {
+            return None;
+        }
+        let pages: Vec<WalletTransactionsPage> =
+            self.responses.remove(&key)?.into_iter().flatten().collect();
+        let first = pages.first()?;
+        Some(WalletTransactions {
+            address: first.address.clone(),
+            balance: first.balance,
+            transactions: pages.into_iter().flat_map(|x| x.transactions).collect(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+/// Balance for a certain wallet, without its transactions (BALANCE response for `WalletQueryResult`)
+pub struct WalletBalance {
+    /// Wallet address
+    pub address: String,
+    /// Current wallet balance
+    pub balance: Decimal,
+    /// Index of the latest block on the chain when the balance was computed
+    pub height: u64,
+}
+
 #[derive(Error, Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 #[serde(tag = "error", rename_all = "SCREAMING_SNAKE_CASE")]
 /// Error type for query error
@@ -68,4 +195,114 @@ pub enum WalletQueryError {
     BlockchainError,
     #[error("requested wallet could not be found")]
     WalletNotFound,
+    #[error("the node is serving too many wallet queries; try again later")]
+    Overloaded,
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::blockchain::{TransactionBuilder, TransactionVersion};
+
+    use pretty_assertions::assert_eq;
+    use rust_decimal_macros::dec;
+
+    fn wallet_transactions(amount: usize) -> WalletTransactions {
+        WalletTransactions {
+            address: String::from("alice"),
+            transactions: (0..amount)
+                .map(|x| {
+                    TransactionBuilder::transfer(
+                        TransactionVersion::V1,
+                        "alice",
+                        "bob",
+                        Decimal::from(x),
+                    )
+                    .finish("aaa")
+                })
+                .collect(),
+            balance: dec!(500.0),
+        }
+    }
+
+    #[test]
+    fn should_not_page_small_results() {
+        let transactions = wallet_transactions(3);
+        assert_eq!(
+            transactions.clone().into_pages(3, "node", 1),
+            vec![WalletQueryResult::Ok(transactions)]
+        );
+    }
+
+    #[test]
+    fn should_page_and_reassemble_large_results() {
+        let transactions = wallet_transactions(10);
+        let pages = transactions.clone().into_pages(4, "node", 1);
+        assert_eq!(pages.len(), 3);
+        let mut reassembler = WalletTransactionsPages::default();
+        let mut reassembled = None;
+        // receive pages out of order
+        for result in pages.into_iter().rev() {
+            match result {
+                WalletQueryResult::Page(page) => {
+                    assert_eq!(page.pages, 3);
+                    assert_eq!(page.responder.as_str(), "node");
+                    assert_eq!(page.request_id, 1);
+                    assert!(page.transactions.len() <= 4);
+                    assert!(reassembled.is_none());
+                    reassembled = reassembler.push(page);
+                }
+                _ => panic!("expected a page"),
+            }
+        }
+        assert_eq!(reassembled, Some(transactions));
+    }
+
+    fn pages(result: Vec<WalletQueryResult>) -> Vec<WalletTransactionsPage> {
+        result
+            .into_iter()
+            .map(|result| match result {
+                WalletQueryResult::Page(page) => page,
+                _ => panic!("expected a page"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn should_not_mix_pages_of_different_responders() {
+        let transactions = wallet_transactions(4);
+        let mut alice = pages(transactions.clone().into_pages(2, "alice", 1)).into_iter();
+        let mut bob = pages(wallet_transactions(6).into_pages(3, "bob", 1)).into_iter();
+        let mut reassembler = WalletTransactionsPages::default();
+        assert!(reassembler.push(alice.next().unwrap()).is_none());
+        assert!(reassembler.push(bob.next().unwrap()).is_none());
+        assert_eq!(reassembler.push(alice.next().unwrap()), Some(transactions));
+    }
+
+    #[test]
+    fn should_reject_pages_above_protocol_limit() {
+        let mut reassembler = WalletTransactionsPages::default();
+        let page = WalletTransactionsPage {
+            address: String::from("alice"),
+            transactions: vec![],
+            balance: dec!(0.0),
+            responder: String::from("mallory"),
+            request_id: 1,
+            page: 0,
+            pages: usize::MAX,
+        };
+        assert!(reassembler.push(page).is_none());
+        assert!(reassembler.responses.is_empty());
+    }
+
+    #[test]
+    fn should_not_exceed_max_pages() {
+        let transactions = wallet_transactions(MAX_WALLET_TRANSACTIONS_PAGES * 3);
+        let pages = pages(transactions.into_pages(1, "node", 1));
+        assert_eq!(pages.len(), MAX_WALLET_TRANSACTIONS_PAGES);
+        assert!(pages
+            .iter()
+            .all(|x| x.pages == MAX_WALLET_TRANSACTIONS_PAGES));
+    }
 }
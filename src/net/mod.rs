@@ -2,65 +2,143 @@
 //!
 //! The network module provides the types to setup the P2P network of the jab blockchain
 
+mod builder;
+mod config;
 mod error;
 pub mod message;
+mod peer_floodsub;
+mod rate_limit;
+
+use crate::blockchain::Clock;
 
 use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::{AsyncRead, AsyncWrite, StreamExt};
 pub use libp2p::swarm::SwarmEvent as InnerSwarmEvent;
 use libp2p::{
-    core::{either::EitherError, upgrade},
-    floodsub::{self, Floodsub, FloodsubEvent, Topic},
+    core::{either::EitherError, muxing::StreamMuxerBox, transport::Boxed, upgrade},
+    floodsub::{self, FloodsubEvent, Topic},
     identity,
     mdns::{Mdns, MdnsEvent},
     mplex, noise,
-    swarm::{ConnectionHandlerUpgrErr, NetworkBehaviourEventProcess, Swarm, SwarmBuilder},
-    tcp::TokioTcpTransport,
-    NetworkBehaviour, PeerId, Transport,
+    swarm::{
+        behaviour::toggle::Toggle, AddressScore, ConnectionHandlerUpgrErr,
+        NetworkBehaviourEventProcess, Swarm, SwarmBuilder,
+    },
+    Multiaddr, NetworkBehaviour, PeerId, Transport,
 };
-use libp2p_tcp::GenTcpConfig;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
+pub use builder::{NodeBuilder, TransportKind, DEFAULT_TOPIC};
+pub use config::{NodeConfig, SecurityLayer};
 pub use error::{NodeError, NodeResult};
 pub use message::Msg;
+use peer_floodsub::{PeerFloodsub, PeerFloodsubEvent};
+pub use rate_limit::MessageRate;
+use rate_limit::RateLimiter;
 
 pub type SwarmEvent =
     InnerSwarmEvent<(), EitherError<ConnectionHandlerUpgrErr<std::io::Error>, void::Void>>;
 
+/// Outcome of publishing a message to the network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PublishOutcome {
+    /// No connected peer is subscribed to the topic, so the message went nowhere
+    NoRecipients,
+    /// The message has been sent to the provided amount of connected peers subscribed to the topic
+    Sent(usize),
+}
+
+impl PublishOutcome {
+    /// Returns whether at least a peer has been sent the message
+    pub fn has_recipients(&self) -> bool {
+        matches!(self, Self::Sent(_))
+    }
+}
+
 /// Represents the client node in the p2p network
 pub struct Node {
     id: PeerId,
     pub swarm: Swarm<JabBehaviour>,
     topic: Topic,
     pub event_receiver: UnboundedReceiver<NodeResult<Msg>>,
+    /// Swarm events received while waiting for the node to be ready, returned first by `next_event`
+    pending_events: VecDeque<SwarmEvent>,
 }
 
 impl Node {
-    /// Initialize a new `Node`
-    pub async fn init() -> NodeResult<Self> {
-        // generate keys
-        let id_keys = identity::Keypair::generate_ed25519();
+    /// Initialize a new `Node` with the provided configuration, on TCP and with mDNS discovery.
+    /// Use `NodeBuilder` to change the other options
+    pub async fn init(config: NodeConfig) -> NodeResult<Self> {
+        NodeBuilder::default().config(config).build().await
+    }
+
+    /// Upgrade `transport` with the provided security layer and mplex
+    fn upgrade<T>(
+        transport: T,
+        id_keys: &identity::Keypair,
+        security: SecurityLayer,
+    ) -> NodeResult<Boxed<(PeerId, StreamMuxerBox)>>
+    where
+        T: Transport + Send + Unpin + 'static,
+        T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        T::Error: Send + Sync + 'static,
+        T::Dial: Send + 'static,
+        T::ListenerUpgrade: Send + 'static,
+    {
+        let transport = transport.upgrade(upgrade::Version::V1);
+        match security {
+            SecurityLayer::Noise => {
+                // Create a keypair for authenticated encryption of the transport.
+                let noise_keys =
+                    noise::Keypair::<noise::X25519Spec>::new().into_authentic(id_keys)?;
+                debug!("generated noise keys");
+                Ok(transport
+                    .authenticate(noise::NoiseConfig::xx(noise_keys).into_authenticated())
+                    .multiplex(mplex::MplexConfig::new())
+                    .boxed())
+            }
+            #[cfg(feature = "insecure-plaintext")]
+            SecurityLayer::Plaintext => {
+                warn!("connections are NOT encrypted; use plaintext only on trusted networks");
+                Ok(transport
+                    .authenticate(libp2p::plaintext::PlainText2Config {
+                        local_public_key: id_keys.public(),
+                    })
+                    .multiplex(mplex::MplexConfig::new())
+                    .boxed())
+            }
+        }
+    }
+
+    /// Build a `Node` on top of `transport`, publishing messages to `topic`. mDNS discovery is enabled only if `mdns`
+    /// is provided
+    fn build(
+        id_keys: identity::Keypair,
+        transport: Boxed<(PeerId, StreamMuxerBox)>,
+        mdns: Option<Mdns>,
+        topic: &str,
+        config: &NodeConfig,
+    ) -> Self {
         let id = PeerId::from(id_keys.public());
-        info!("initializing new Node with id: {}", id);
-        // Create a keypair for authenticated encryption of the transport.
-        let noise_keys = noise::Keypair::<noise::X25519Spec>::new().into_authentic(&id_keys)?;
-        debug!("generated noise keys");
-        // Create a tokio-based TCP transport use noise for authenticated
-        // encryption and Mplex for multiplexing of substreams on a TCP stream.
-        let transport = TokioTcpTransport::new(GenTcpConfig::default().nodelay(true))
-            .upgrade(upgrade::Version::V1)
-            .authenticate(noise::NoiseConfig::xx(noise_keys).into_authenticated())
-            .multiplex(mplex::MplexConfig::new())
-            .boxed();
-        debug!("tcp transport setup ok");
         // setup topic
-        let topic = floodsub::Topic::new("jab");
+        let topic = floodsub::Topic::new(topic);
         let self_topic = floodsub::Topic::new(id.to_string());
         let (event_sender, event_receiver) = mpsc::unbounded();
         // Create a Swarm to manage peers and events.
-        let swarm = {
+        let mut swarm = {
             let mut behaviour = JabBehaviour {
-                floodsub: Floodsub::new(id),
-                mdns: Mdns::new(Default::default()).await?,
+                floodsub: PeerFloodsub::new(id),
+                mdns: Toggle::from(mdns),
                 event_sender,
+                clock: config.clock_source(),
+                max_clock_skew: config.clock_skew(),
+                max_penalty: config.penalty_limit(),
+                penalties: HashMap::new(),
+                penalized: Vec::new(),
+                rate_limiter: config.message_rate().map(RateLimiter::new),
+                subscriptions: HashMap::new(),
             };
 
             // subscribe to both topic
@@ -75,12 +153,106 @@ impl Node {
                 }))
                 .build()
         };
-        Ok(Node {
+        if let Some(address) = config.advertised_address() {
+            info!("advertising external address {}", address);
+            swarm.add_external_address(address.clone(), AddressScore::Infinite);
+        }
+        Node {
             id,
             swarm,
             topic,
             event_receiver,
-        })
+            pending_events: VecDeque::new(),
+        }
+    }
+
+    #[cfg(test)]
+    /// Build a pair of connected nodes, communicating through an in-memory transport and with mDNS disabled
+    pub async fn test_pair() -> (Self, Self) {
+        Self::test_pair_with_security(SecurityLayer::Noise).await
+    }
+
+    #[cfg(test)]
+    /// Build a pair of connected nodes as `test_pair`, using the provided security layer
+    pub async fn test_pair_with_security(security: SecurityLayer) -> (Self, Self) {
+        let mut a = Self::test_node(security);
+        let mut b = Self::test_node(security);
+        Self::test_connect(&mut a, &mut b).await;
+        (a, b)
+    }
+
+    #[cfg(test)]
+    /// Build a node communicating through an in-memory transport, with mDNS disabled
+    pub fn test_node(security: SecurityLayer) -> Self {
+        Self::test_node_with_config(&NodeConfig::default().security(security))
+    }
+
+    #[cfg(test)]
+    /// Build a node as `test_node`, using the provided configuration
+    pub fn test_node_with_config(config: &NodeConfig) -> Self {
+        use libp2p::core::transport::MemoryTransport;
+
+        let id_keys = identity::Keypair::generate_ed25519();
+        let transport = Self::upgrade(
+            MemoryTransport::default(),
+            &id_keys,
+            config.security_layer(),
+        )
+        .unwrap();
+        Self::build(id_keys, transport, None, DEFAULT_TOPIC, config)
+    }
+
+    #[cfg(test)]
+    /// Make `b` connect to `a` and wait until the connection is established on both sides
+    pub async fn test_connect(a: &mut Self, b: &mut Self) {
+        a.swarm.listen_on("/memory/0".parse().unwrap()).unwrap();
+        let address = a.ready(Duration::from_secs(30)).await.unwrap();
+        b.swarm.dial(address).unwrap();
+        // wait for both nodes to be connected
+        let (mut a_connected, mut b_connected) = (false, false);
+        while !(a_connected && b_connected) {
+            tokio::select! {
+                event = a.swarm.select_next_some() => {
+                    a_connected |= matches!(event, InnerSwarmEvent::ConnectionEstablished { .. });
+                }
+                event = b.swarm.select_next_some() => {
+                    b_connected |= matches!(event, InnerSwarmEvent::ConnectionEstablished { .. });
+                }
+            }
+        }
+        a.swarm
+            .behaviour_mut()
+            .floodsub
+            .add_node_to_partial_view(b.id);
+        b.swarm
+            .behaviour_mut()
+            .floodsub
+            .add_node_to_partial_view(a.id);
+    }
+
+    /// Get the amount of malformed messages received from `peer`
+    pub fn penalty(&self, peer: &PeerId) -> u32 {
+        self.swarm
+            .behaviour()
+            .penalties
+            .get(peer)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Get the addresses this node can be dialed at by peers.
+    /// External addresses are preferred; listener addresses are returned if there's none
+    pub fn advertised_addresses(&self) -> Vec<Multiaddr> {
+        let external: Vec<Multiaddr> = self
+            .swarm
+            .external_addresses()
+            .map(|record| record.addr.clone())
+            .collect();
+        if external.is_empty() {
+            self.swarm.listeners().cloned().collect()
+        } else {
+            external
+        }
     }
 
     /// Get peer id as string
@@ -96,24 +268,108 @@ impl Node {
             .map_err(NodeError::from)
     }
 
-    /// Publish a message to the newtwork
-    pub async fn publish(&mut self, message: Msg) -> NodeResult<()> {
+    /// Wait until the node has bound a listener and is ready to accept peers, returning the first listen address.
+    /// Returns immediately if the node is already listening. Fails if the listener fails or closes, or if no listen
+    /// address is reported within `timeout`. Other swarm events received meanwhile are returned later by `next_event`
+    pub async fn ready(&mut self, timeout: Duration) -> NodeResult<Multiaddr> {
+        if let Some(address) = self.swarm.listeners().next() {
+            return Ok(address.clone());
+        }
+        let swarm = &mut self.swarm;
+        let pending_events = &mut self.pending_events;
+        tokio::time::timeout(timeout, async {
+            loop {
+                match swarm.select_next_some().await {
+                    InnerSwarmEvent::NewListenAddr { address, .. } => return Ok(address),
+                    InnerSwarmEvent::ListenerError { error, .. } => {
+                        return Err(NodeError::from(error))
+                    }
+                    InnerSwarmEvent::ListenerClosed {
+                        reason: Err(error), ..
+                    } => return Err(NodeError::from(error)),
+                    InnerSwarmEvent::ListenerClosed { .. } => {
+                        return Err(NodeError::ListenerClosed)
+                    }
+                    event => pending_events.push_back(event),
+                }
+            }
+        })
+        .await
+        .map_err(|_| NodeError::ReadyTimeout(timeout))?
+    }
+
+    /// Wait for the next swarm event, then disconnect and ban the peers which have reached the maximum penalty.
+    /// Events received while waiting for the node to be ready are returned first
+    pub async fn next_event(&mut self) -> SwarmEvent {
+        let event = match self.pending_events.pop_front() {
+            Some(event) => event,
+            None => self.swarm.select_next_some().await,
+        };
+        self.ban_penalized();
+        event
+    }
+
+    /// Ban the peers which have reached the maximum penalty. Banned peers are disconnected and can't connect again
+    fn ban_penalized(&mut self) {
+        let penalized = std::mem::take(&mut self.swarm.behaviour_mut().penalized);
+        for peer in penalized {
+            warn!("banning {}: maximum penalty reached", peer);
+            self.swarm.ban_peer_id(peer);
+        }
+    }
+
+    /// Publish a message to the newtwork.
+    /// The outcome tells whether any connected peer was subscribed to the topic, and so could receive the message
+    pub async fn publish(&mut self, message: Msg) -> NodeResult<PublishOutcome> {
         debug!("publishing {:?}", message);
-        self.swarm.behaviour_mut().floodsub.publish(
-            self.topic.clone(),
-            serde_json::json!(message).to_string().as_bytes(),
-        );
-        Ok(())
+        let topic = self.topic.clone();
+        let recipients = self.subscribers(&topic);
+        self.swarm
+            .behaviour_mut()
+            .floodsub
+            .publish(topic, message.encode());
+        if recipients == 0 {
+            debug!(
+                "no peer is subscribed to the topic; {} went nowhere",
+                message.name()
+            );
+            Ok(PublishOutcome::NoRecipients)
+        } else {
+            Ok(PublishOutcome::Sent(recipients))
+        }
+    }
+
+    /// Count the connected peers subscribed to `topic`, forgetting the subscriptions and penalties of disconnected
+    /// peers
+    fn subscribers(&mut self, topic: &Topic) -> usize {
+        let behaviour = self.swarm.behaviour();
+        let disconnected: Vec<PeerId> = behaviour
+            .subscriptions
+            .keys()
+            .chain(behaviour.penalties.keys())
+            .filter(|peer| !self.swarm.is_connected(peer))
+            .copied()
+            .collect();
+        let behaviour = self.swarm.behaviour_mut();
+        for peer in disconnected.iter() {
+            behaviour.subscriptions.remove(peer);
+            behaviour.penalties.remove(peer);
+        }
+        behaviour
+            .subscriptions
+            .values()
+            .filter(|topics| topics.contains(topic))
+            .count()
     }
 
     /// Send a message to a certain peer
     pub async fn send(&mut self, peer_id: &str, message: Msg) -> NodeResult<()> {
         debug!("sending {:?} to {}", message, peer_id);
         let peer_topic = floodsub::Topic::new(peer_id);
-        self.swarm.behaviour_mut().floodsub.publish_any(
-            peer_topic,
-            serde_json::json!(message).to_string().as_bytes(),
-        );
+        self.swarm
+            .behaviour_mut()
+            .floodsub
+            .publish_any(peer_topic, message.encode());
         Ok(())
     }
 }
@@ -125,30 +381,135 @@ impl Node {
 #[derive(NetworkBehaviour)]
 #[behaviour(event_process = true)]
 pub struct JabBehaviour {
-    floodsub: Floodsub,
-    mdns: Mdns,
+    floodsub: PeerFloodsub,
+    mdns: Toggle<Mdns>,
     #[behaviour(ignore)]
     event_sender: UnboundedSender<NodeResult<Msg>>,
+    /// Clock the timestamps of messages are checked against
+    #[behaviour(ignore)]
+    clock: Arc<dyn Clock>,
+    #[behaviour(ignore)]
+    max_clock_skew: Option<Duration>,
+    /// Penalty at which a peer is banned
+    #[behaviour(ignore)]
+    max_penalty: Option<u32>,
+    /// Amount of malformed or exceeding messages received from each connected peer
+    #[behaviour(ignore)]
+    penalties: HashMap<PeerId, u32>,
+    /// Peers which have reached the maximum penalty and must be banned by the node
+    #[behaviour(ignore)]
+    penalized: Vec<PeerId>,
+    /// Limits the rate of the messages received from each connected peer
+    #[behaviour(ignore)]
+    rate_limiter: Option<RateLimiter>,
+    /// Topics each peer is subscribed to
+    #[behaviour(ignore)]
+    subscriptions: HashMap<PeerId, HashSet<Topic>>,
 }
 
-impl NetworkBehaviourEventProcess<FloodsubEvent> for JabBehaviour {
+/// Check whether the timestamp of `message` is ahead of `now` by more than `max_skew`.
+/// Timestamps in the past are always accepted, since peers may send old blocks.
+/// Returns the skew if it exceeds the maximum
+fn check_clock_skew(message: &Msg, now: SystemTime, max_skew: Duration) -> Result<(), Duration> {
+    match message
+        .timestamp()
+        .and_then(|timestamp| timestamp.duration_since(now).ok())
+    {
+        Some(skew) if skew > max_skew => Err(skew),
+        _ => Ok(()),
+    }
+}
+
+impl JabBehaviour {
+    /// Increase the penalty of `peer`, queueing it to be banned once it reaches the maximum penalty.
+    /// Returns the new penalty
+    fn penalize(&mut self, peer: PeerId) -> u32 {
+        let penalty = self.penalties.entry(peer).or_default();
+        *penalty += 1;
+        let penalty = *penalty;
+        if self.max_penalty == Some(penalty) {
+            self.penalized.push(peer);
+        }
+        penalty
+    }
+}
+
+impl NetworkBehaviourEventProcess<PeerFloodsubEvent> for JabBehaviour {
     // Called when `floodsub` produces an event.
-    fn inject_event(&mut self, message: FloodsubEvent) {
-        if let FloodsubEvent::Message(message) = message {
-            debug!(
-                "Received: message from {} {}",
-                message.source,
-                String::from_utf8_lossy(&message.data)
-            );
-            // decode message
-            let ev_sender = self.event_sender.clone();
-            let message = serde_json::from_slice(&message.data).map_err(NodeError::from);
-            tokio::spawn(async move {
-                if let Err(err) = ev_sender.unbounded_send(message) {
-                    error!("failed to send to receiver (thread): {}", err);
+    fn inject_event(&mut self, event: PeerFloodsubEvent) {
+        let message = match event.event {
+            FloodsubEvent::Message(message) => message,
+            FloodsubEvent::Subscribed { peer_id, topic } => {
+                self.subscriptions.entry(peer_id).or_default().insert(topic);
+                return;
+            }
+            FloodsubEvent::Unsubscribed { peer_id, topic } => {
+                if let Some(topics) = self.subscriptions.get_mut(&peer_id) {
+                    topics.remove(&topic);
                 }
-            });
+                return;
+            }
+        };
+        debug!(
+            "Received: message from {} {}",
+            message.source,
+            String::from_utf8_lossy(&message.data)
+        );
+        // the declared source can be spoofed: limits and penalties apply to the peer which delivered the message
+        let source = match event.propagation_source {
+            Some(source) => source,
+            None => {
+                warn!(
+                    "dropping message declaring source {}: the peer which delivered it is unknown",
+                    message.source
+                );
+                return;
+            }
+        };
+        if let Some(rate_limiter) = self.rate_limiter.as_mut() {
+            if !rate_limiter.try_acquire(source, Instant::now()) {
+                let penalty = self.penalize(source);
+                warn!(
+                    "dropping message from {} (penalty: {}): message rate exceeded",
+                    source, penalty
+                );
+                return;
+            }
+        }
+        // decode message
+        let ev_sender = self.event_sender.clone();
+        let message = match Msg::decode(&message.data) {
+            Err(NodeError::UnknownMessage(kind)) => {
+                // probably sent by a peer running a newer version of the protocol
+                debug!("ignoring unknown message {} from {}", kind, source);
+                return;
+            }
+            Err(err) => {
+                let penalty = self.penalize(source);
+                warn!(
+                    "malformed message from {} (penalty: {}): {}",
+                    source, penalty, err
+                );
+                Err(err)
+            }
+            Ok(message) => Ok(message),
+        };
+        if let (Ok(message), Some(max_skew)) = (message.as_ref(), self.max_clock_skew) {
+            if let Err(skew) = check_clock_skew(message, self.clock.now(), max_skew) {
+                warn!(
+                    "dropping {} from {}: timestamp is {}s ahead of the local clock",
+                    message.name(),
+                    source,
+                    skew.as_secs()
+                );
+                return;
+            }
         }
+        tokio::spawn(async move {
+            if let Err(err) = ev_sender.unbounded_send(message) {
+                error!("failed to send to receiver (thread): {}", err);
+            }
+        });
     }
 }
 
@@ -163,7 +524,12 @@ impl NetworkBehaviourEventProcess<MdnsEvent> for JabBehaviour {
             }
             MdnsEvent::Expired(list) => {
                 for (peer, _) in list {
-                    if !self.mdns.has_node(&peer) {
+                    if !self
+                        .mdns
+                        .as_ref()
+                        .map(|mdns| mdns.has_node(&peer))
+                        .unwrap_or_default()
+                    {
                         self.floodsub.remove_node_from_partial_view(&peer);
                     }
                 }
@@ -171,3 +537,346 @@ impl NetworkBehaviourEventProcess<MdnsEvent> for JabBehaviour {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use futures::StreamExt;
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn should_exchange_messages_between_nodes() {
+        let (a, b) = Node::test_pair().await;
+        exchange_message(a, b).await;
+    }
+
+    #[cfg(feature = "insecure-plaintext")]
+    #[tokio::test]
+    async fn should_exchange_messages_between_plaintext_nodes() {
+        let (a, b) = Node::test_pair_with_security(SecurityLayer::Plaintext).await;
+        exchange_message(a, b).await;
+    }
+
+    #[tokio::test]
+    async fn should_resolve_ready_once_listening() {
+        let mut node = Node::test_node(SecurityLayer::Noise);
+        node.swarm.listen_on("/memory/0".parse().unwrap()).unwrap();
+        let address = node.ready(Duration::from_secs(5)).await.unwrap();
+        assert_eq!(node.swarm.listeners().collect::<Vec<_>>(), vec![&address]);
+        // already listening: resolves immediately
+        assert_eq!(
+            node.ready(Duration::from_millis(10)).await.unwrap(),
+            address
+        );
+    }
+
+    #[tokio::test]
+    async fn should_fail_ready_after_timeout() {
+        let mut node = Node::test_node(SecurityLayer::Noise);
+        assert!(matches!(
+            node.ready(Duration::from_millis(100)).await,
+            Err(NodeError::ReadyTimeout(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn should_keep_events_received_while_waiting_to_be_ready() {
+        let mut a = Node::test_node(SecurityLayer::Noise);
+        let mut b = Node::test_node(SecurityLayer::Noise);
+        b.swarm.listen_on("/memory/0".parse().unwrap()).unwrap();
+        let address = b.ready(Duration::from_secs(5)).await.unwrap();
+        let b_id = b.id;
+        tokio::spawn(async move {
+            loop {
+                b.swarm.select_next_some().await;
+            }
+        });
+        // a never listens, so it gets only the events of the connection with b
+        a.swarm.dial(address).unwrap();
+        assert!(a.ready(Duration::from_secs(2)).await.is_err());
+        assert!(!a.pending_events.is_empty());
+        let connected = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let InnerSwarmEvent::ConnectionEstablished { peer_id, .. } = a.next_event().await
+                {
+                    break peer_id;
+                }
+            }
+        })
+        .await
+        .expect("connection was not reported");
+        assert_eq!(connected, b_id);
+    }
+
+    #[tokio::test]
+    async fn should_advertise_external_address() {
+        use libp2p::core::transport::MemoryTransport;
+
+        let external: Multiaddr = "/ip4/203.0.113.7/tcp/4000".parse().unwrap();
+        let id_keys = identity::Keypair::generate_ed25519();
+        let transport =
+            Node::upgrade(MemoryTransport::default(), &id_keys, SecurityLayer::Noise).unwrap();
+        let mut node = Node::build(
+            id_keys,
+            transport,
+            None,
+            DEFAULT_TOPIC,
+            &NodeConfig::default().external_address(external.clone()),
+        );
+        node.swarm.listen_on("/memory/0".parse().unwrap()).unwrap();
+        node.ready(Duration::from_secs(5)).await.unwrap();
+        assert_eq!(node.advertised_addresses(), vec![external.clone()]);
+        // without an external address, the listener address is advertised
+        let mut node = Node::test_node(SecurityLayer::Noise);
+        node.swarm.listen_on("/memory/0".parse().unwrap()).unwrap();
+        let listener = node.ready(Duration::from_secs(5)).await.unwrap();
+        assert_eq!(node.advertised_addresses(), vec![listener]);
+    }
+
+    #[test]
+    fn should_reject_messages_with_excessive_clock_skew() {
+        use crate::blockchain::{Block, Header, TransactionBuilder, TransactionVersion, Version};
+
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        let block_at = |secs: u64| {
+            Msg::block(Block::new(
+                1,
+                Header::new(
+                    Version::V010,
+                    Some(String::from("cafebabe")),
+                    String::from("deadbeef"),
+                    SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+                ),
+                TransactionBuilder::new(TransactionVersion::V1)
+                    .output("alice", rust_decimal::Decimal::ONE)
+                    .finish("aaa"),
+            ))
+        };
+        let max_skew = Duration::from_secs(60);
+        assert!(check_clock_skew(&block_at(1060), now, max_skew).is_ok());
+        assert_eq!(
+            check_clock_skew(&block_at(1061), now, max_skew),
+            Err(Duration::from_secs(61))
+        );
+        // old blocks and messages without timestamp are accepted
+        assert!(check_clock_skew(&block_at(0), now, max_skew).is_ok());
+        assert!(check_clock_skew(&Msg::request_block(1), now, max_skew).is_ok());
+    }
+
+    #[tokio::test]
+    async fn should_check_message_timestamps_against_node_clock() {
+        use crate::blockchain::{
+            Block, Header, MockClock, TransactionBuilder, TransactionVersion, Version,
+        };
+        use libp2p::floodsub::FloodsubMessage;
+
+        let clock = MockClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1000));
+        let mut node = Node::test_node_with_config(
+            &NodeConfig::default()
+                .max_clock_skew(Duration::from_secs(60))
+                .clock(clock.clone()),
+        );
+        let block = Msg::block(Block::new(
+            1,
+            Header::new(
+                Version::V010,
+                Some(String::from("cafebabe")),
+                String::from("deadbeef"),
+                SystemTime::UNIX_EPOCH + Duration::from_secs(1100),
+            ),
+            TransactionBuilder::new(TransactionVersion::V1)
+                .output("alice", rust_decimal::Decimal::ONE)
+                .finish("aaa"),
+        ));
+        let source = PeerId::random();
+        let receive = |node: &mut Node| {
+            node.swarm
+                .behaviour_mut()
+                .inject_event(PeerFloodsubEvent::new(
+                    source,
+                    FloodsubEvent::Message(FloodsubMessage {
+                        source,
+                        data: block.encode(),
+                        sequence_number: vec![0],
+                        topics: vec![Topic::new("jab")],
+                    }),
+                ));
+        };
+        receive(&mut node);
+        assert!(node.event_receiver.try_next().is_err());
+        clock.set(SystemTime::UNIX_EPOCH + Duration::from_secs(1040));
+        receive(&mut node);
+        assert_eq!(node.event_receiver.next().await.unwrap().unwrap(), block);
+    }
+
+    #[tokio::test]
+    async fn should_ignore_unknown_messages_and_penalize_malformed_ones() {
+        use libp2p::floodsub::FloodsubMessage;
+
+        let mut node = Node::test_node(SecurityLayer::Noise);
+        let source = PeerId::random();
+        let receive = |node: &mut Node, data: &[u8]| {
+            node.swarm
+                .behaviour_mut()
+                .inject_event(PeerFloodsubEvent::new(
+                    source,
+                    FloodsubEvent::Message(FloodsubMessage {
+                        source,
+                        data: data.to_vec(),
+                        sequence_number: vec![0],
+                        topics: vec![Topic::new("jab")],
+                    }),
+                ));
+        };
+        receive(&mut node, br#"{"type":"FROM_THE_FUTURE"}"#);
+        assert_eq!(node.penalty(&source), 0);
+        receive(&mut node, b"\x00\xffnot json");
+        assert_eq!(node.penalty(&source), 1);
+        // only the malformed message is forwarded, as an error
+        assert!(matches!(
+            node.event_receiver.next().await,
+            Some(Err(NodeError::InvalidPayload(_)))
+        ));
+        assert!(node.event_receiver.try_next().is_err());
+    }
+
+    #[tokio::test]
+    async fn should_drop_messages_exceeding_peer_rate() {
+        use libp2p::floodsub::FloodsubMessage;
+
+        let mut node = Node::test_node_with_config(
+            &NodeConfig::default().max_message_rate(MessageRate::new(1, 3)),
+        );
+        let relay = PeerId::random();
+        // a mix of message types, all counted against the same rate, even if each declares a different source
+        for message in [
+            Msg::request_block(1),
+            Msg::get_tip("peer"),
+            Msg::request_registered_miners(),
+            Msg::request_block(2),
+            Msg::get_tip("peer"),
+        ] {
+            node.swarm
+                .behaviour_mut()
+                .inject_event(PeerFloodsubEvent::new(
+                    relay,
+                    FloodsubEvent::Message(FloodsubMessage {
+                        source: PeerId::random(),
+                        data: message.encode(),
+                        sequence_number: vec![0],
+                        topics: vec![Topic::new("jab")],
+                    }),
+                ));
+        }
+        assert_eq!(node.penalty(&relay), 2);
+        for _ in 0..3 {
+            assert!(matches!(node.event_receiver.next().await, Some(Ok(_))));
+        }
+        assert!(node.event_receiver.try_next().is_err());
+    }
+
+    #[tokio::test]
+    async fn should_drop_messages_without_propagation_source() {
+        use libp2p::floodsub::FloodsubMessage;
+
+        let mut node = Node::test_node(SecurityLayer::Noise);
+        let source = PeerId::random();
+        node.swarm.behaviour_mut().inject_event(PeerFloodsubEvent {
+            propagation_source: None,
+            event: FloodsubEvent::Message(FloodsubMessage {
+                source,
+                data: b"\x00\xffnot json".to_vec(),
+                sequence_number: vec![0],
+                topics: vec![Topic::new("jab")],
+            }),
+        });
+        assert_eq!(node.penalty(&source), 0);
+        assert!(node.event_receiver.try_next().is_err());
+    }
+
+    #[tokio::test]
+    async fn should_ban_peers_reaching_max_penalty() {
+        use libp2p::floodsub::FloodsubMessage;
+
+        let config = NodeConfig::default().max_penalty(2);
+        let mut a = Node::test_node_with_config(&config);
+        let mut b = Node::test_node_with_config(&config);
+        Node::test_connect(&mut a, &mut b).await;
+        for _ in 0..2 {
+            a.swarm.behaviour_mut().inject_event(PeerFloodsubEvent::new(
+                b.id,
+                FloodsubEvent::Message(FloodsubMessage {
+                    source: b.id,
+                    data: b"\x00\xffnot json".to_vec(),
+                    sequence_number: vec![0],
+                    topics: vec![Topic::new("jab")],
+                }),
+            ));
+        }
+        assert_eq!(a.swarm.behaviour().penalized, vec![b.id]);
+        tokio::time::timeout(Duration::from_secs(30), async {
+            while a.swarm.is_connected(&b.id) {
+                tokio::select! {
+                    _ = a.next_event() => {}
+                    _ = b.swarm.select_next_some() => {}
+                }
+            }
+        })
+        .await
+        .expect("peer was not disconnected in time");
+        assert!(a.swarm.behaviour().penalized.is_empty());
+    }
+
+    /// Publish a message from `a` and assert it is received by `b`
+    async fn exchange_message(mut a: Node, mut b: Node) {
+        let message = Msg::request_block(1);
+        // keep publishing until b has received a's subscriptions and the message gets through
+        let received = tokio::time::timeout(Duration::from_secs(30), async {
+            loop {
+                a.publish(message.clone()).await.unwrap();
+                tokio::select! {
+                    _ = a.swarm.select_next_some() => {}
+                    _ = b.swarm.select_next_some() => {}
+                    received = b.event_receiver.next() => {
+                        if let Some(Ok(received)) = received {
+                            break received;
+                        }
+                    }
+                }
+            }
+        })
+        .await
+        .expect("message was not received in time");
+        assert_eq!(received, message);
+    }
+
+    #[tokio::test]
+    async fn should_report_publish_recipients() {
+        let mut node = Node::test_node(SecurityLayer::Noise);
+        assert_eq!(
+            node.publish(Msg::request_block(1)).await.unwrap(),
+            PublishOutcome::NoRecipients
+        );
+        let (mut a, mut b) = Node::test_pair().await;
+        // keep publishing until a has received b's subscriptions
+        let outcome = tokio::time::timeout(Duration::from_secs(30), async {
+            loop {
+                let outcome = a.publish(Msg::request_block(1)).await.unwrap();
+                if outcome.has_recipients() {
+                    break outcome;
+                }
+                tokio::select! {
+                    _ = a.swarm.select_next_some() => {}
+                    _ = b.swarm.select_next_some() => {}
+                    _ = tokio::time::sleep(Duration::from_millis(100)) => {}
+                }
+            }
+        })
+        .await
+        .expect("subscriptions were not exchanged in time");
+        assert_eq!(outcome, PublishOutcome::Sent(1));
+    }
+}
@@ -0,0 +1,140 @@
+//! # Peer floodsub
+//!
+//! Floodsub relays messages unchanged, so their `source` is whatever the author declared and can't be trusted.
+//! This module wraps `Floodsub` to report the connected peer each event has been received from
+
+use libp2p::{
+    core::{connection::ConnectionId, ConnectedPoint},
+    floodsub::{Floodsub, FloodsubEvent},
+    swarm::{
+        ConnectionHandler, IntoConnectionHandler, NetworkBehaviour, NetworkBehaviourAction,
+        PollParameters,
+    },
+    Multiaddr, PeerId,
+};
+use std::ops::{Deref, DerefMut};
+use std::task::{Context, Poll};
+
+/// A floodsub event, along with the connected peer it has been received from
+#[derive(Debug)]
+pub struct PeerFloodsubEvent {
+    /// Peer which delivered the event on its connection. It differs from the message source when relayed
+    pub propagation_source: Option<PeerId>,
+    pub event: FloodsubEvent,
+}
+
+impl PeerFloodsubEvent {
+    pub fn new(propagation_source: PeerId, event: FloodsubEvent) -> Self {
+        Self {
+            propagation_source: Some(propagation_source),
+            event,
+        }
+    }
+}
+
+/// `Floodsub` behaviour emitting `PeerFloodsubEvent`s.
+///
+/// The swarm drains the behaviour events after each event injected by a connection, so the events emitted by the
+/// inner behaviour until it returns `Pending` come from the last connection which injected an event
+pub struct PeerFloodsub {
+    inner: Floodsub,
+    propagation_source: Option<PeerId>,
+}
+
+impl PeerFloodsub {
+    pub fn new(local_peer_id: PeerId) -> Self {
+        Self {
+            inner: Floodsub::new(local_peer_id),
+            propagation_source: None,
+        }
+    }
+}
+
+impl Deref for PeerFloodsub {
+    type Target = Floodsub;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl DerefMut for PeerFloodsub {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+impl NetworkBehaviour for PeerFloodsub {
+    type ConnectionHandler = <Floodsub as NetworkBehaviour>::ConnectionHandler;
+    type OutEvent = PeerFloodsubEvent;
+
+    fn new_handler(&mut self) -> Self::ConnectionHandler {
+        self.inner.new_handler()
+    }
+
+    fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        self.inner.addresses_of_peer(peer_id)
+    }
+
+    fn inject_connection_established(
+        &mut self,
+        peer_id: &PeerId,
+        connection_id: &ConnectionId,
+        endpoint: &ConnectedPoint,
+        failed_addresses: Option<&Vec<Multiaddr>>,
+        other_established: usize,
+    ) {
+        self.inner.inject_connection_established(
+            peer_id,
+            connection_id,
+            endpoint,
+            failed_addresses,
+            other_established,
+        )
+    }
+
+    fn inject_connection_closed(
+        &mut self,
+        peer_id: &PeerId,
+        connection_id: &ConnectionId,
+        endpoint: &ConnectedPoint,
+        handler: <Self::ConnectionHandler as IntoConnectionHandler>::Handler,
+        remaining_established: usize,
+    ) {
+        self.inner.inject_connection_closed(
+            peer_id,
+            connection_id,
+            endpoint,
+            handler,
+            remaining_established,
+        )
+    }
+
+    fn inject_event(
+        &mut self,
+        peer_id: PeerId,
+        connection: ConnectionId,
+        event: <<Self::ConnectionHandler as IntoConnectionHandler>::Handler as ConnectionHandler>::OutEvent,
+    ) {
+        self.propagation_source = Some(peer_id);
+        self.inner.inject_event(peer_id, connection, event)
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+        params: &mut impl PollParameters,
+    ) -> Poll<NetworkBehaviourAction<Self::OutEvent, Self::ConnectionHandler>> {
+        let propagation_source = self.propagation_source;
+        match self.inner.poll(cx, params) {
+            Poll::Ready(action) => Poll::Ready(action.map_out(|event| PeerFloodsubEvent {
+                propagation_source,
+                event,
+            })),
+            Poll::Pending => {
+                self.propagation_source = None;
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! # Rate limit
+//!
+//! Limits the rate of the messages received from each connected peer, whatever their type
+
+use libp2p::PeerId;
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Maximum rate of the messages accepted from a single peer
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MessageRate {
+    /// Messages per second refilled to each peer
+    pub per_second: u32,
+    /// Maximum amount of messages a peer may send at once
+    pub burst: u32,
+}
+
+impl MessageRate {
+    pub fn new(per_second: u32, burst: u32) -> Self {
+        Self { per_second, burst }
+    }
+}
+
+/// Tokens left to a peer
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// A token bucket for each peer: each message takes a token, and tokens are refilled at the configured rate,
+/// up to the burst. Buckets refilled to the burst are forgotten, since they're equal to the one of a new peer
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: MessageRate,
+    buckets: HashMap<PeerId, Bucket>,
+}
+
+impl RateLimiter {
+    /// Instantiate a new `RateLimiter` accepting messages at `rate`
+    pub fn new(rate: MessageRate) -> Self {
+        Self {
+            rate,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Take a token from the bucket of `peer` at `now`. Returns whether the message must be accepted
+    pub fn try_acquire(&mut self, peer: PeerId, now: Instant) -> bool {
+        let burst = f64::from(self.rate.burst);
+        if !self.buckets.contains_key(&peer) {
+            self.evict_refilled(now);
+        }
+        let bucket = self.buckets.entry(peer).or_insert(Bucket {
+            tokens: burst,
+            refilled_at: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.refilled_at);
+        bucket.tokens =
+            (bucket.tokens + elapsed.as_secs_f64() * f64::from(self.rate.per_second)).min(burst);
+        bucket.refilled_at = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Forget the buckets which have been refilled to the burst at `now`
+    fn evict_refilled(&mut self, now: Instant) {
+        let rate = self.rate;
+        self.buckets.retain(|_, bucket| {
+            let elapsed = now.saturating_duration_since(bucket.refilled_at);
+            bucket.tokens + elapsed.as_secs_f64() * f64::from(rate.per_second)
+                < f64::from(rate.burst)
+        });
+    }
+
+    #[cfg(test)]
+    /// Amount of peers with a bucket
+    fn peers(&self) -> usize {
+        self.buckets.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+
+    #[test]
+    fn should_drop_messages_exceeding_rate_until_refilled() {
+        let mut limiter = RateLimiter::new(MessageRate::new(2, 4));
+        let peer = PeerId::random();
+        let now = Instant::now();
+        let accepted = (0..10).filter(|_| limiter.try_acquire(peer, now)).count();
+        assert_eq!(accepted, 4);
+        // still within the window
+        assert!(!limiter.try_acquire(peer, now + Duration::from_millis(400)));
+        assert!(limiter.try_acquire(peer, now + Duration::from_millis(600)));
+        // never refilled beyond the burst
+        let later = now + Duration::from_secs(60);
+        let accepted = (0..10).filter(|_| limiter.try_acquire(peer, later)).count();
+        assert_eq!(accepted, 4);
+    }
+
+    #[test]
+    fn should_limit_each_peer_independently() {
+        let mut limiter = RateLimiter::new(MessageRate::new(1, 1));
+        let (alice, bob) = (PeerId::random(), PeerId::random());
+        let now = Instant::now();
+        assert!(limiter.try_acquire(alice, now));
+        assert!(!limiter.try_acquire(alice, now));
+        assert!(limiter.try_acquire(bob, now));
+    }
+
+    #[test]
+    fn should_evict_refilled_buckets() {
+        let mut limiter = RateLimiter::new(MessageRate::new(1, 2));
+        let now = Instant::now();
+        for _ in 0..100 {
+            assert!(limiter.try_acquire(PeerId::random(), now));
+        }
+        assert_eq!(limiter.peers(), 100);
+        // a second later, a single token has been refilled to each bucket, which is full again
+        let alice = PeerId::random();
+        assert!(limiter.try_acquire(alice, now + Duration::from_secs(1)));
+        assert_eq!(limiter.peers(), 1);
+        // buckets not refilled yet are kept
+        let bob = PeerId::random();
+        assert!(limiter.try_acquire(bob, now + Duration::from_millis(1500)));
+        assert_eq!(limiter.peers(), 2);
+    }
+}
@@ -0,0 +1,194 @@
+//! # Cache
+//!
+//! A bounded cache for signature verification results
+
+use super::WalletResult;
+use crate::blockchain::Transaction;
+
+use std::collections::{HashMap, VecDeque};
+
+/// Default amount of verification results kept by `VerificationCache`
+pub const DEFAULT_VERIFICATION_CACHE_SIZE: usize = 1024;
+
+/// Identifies a verification: the signed id of the transaction (see `Transaction::signed_id`) and the signer public
+/// key. The signed id covers the signed digest and the signature, so tampering with any signed field produces a
+/// different key and never hits a stale entry
+type VerificationKey = (String, String);
+
+/// Least-recently-used cache for signature verification results, to avoid running the secp256k1 verification
+/// again for transactions which have already been verified
+#[derive(Debug)]
+pub struct VerificationCache {
+    capacity: usize,
+    entries: HashMap<VerificationKey, bool>,
+    /// Keys ordered from the least to the most recently used
+    recency: VecDeque<VerificationKey>,
+    hits: u64,
+}
+
+impl Default for VerificationCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_VERIFICATION_CACHE_SIZE)
+    }
+}
+
+impl VerificationCache {
+    /// Instantiate a new `VerificationCache` holding at most `capacity` results
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::with_capacity(capacity),
+            recency: VecDeque::with_capacity(capacity),
+            hits: 0,
+        }
+    }
+
+    /// Verify whether `transaction` has been signed by the owner of `pubkey` (see `Transaction::verify_signature`).
+    /// The cached result is returned if this verification has already been performed
+    pub fn verify_transaction(
+        &mut self,
+        transaction: &Transaction,
+        pubkey: &str,
+    ) -> WalletResult<bool> {
+        let key = (transaction.signed_id(), pubkey.to_string());
+        if let Some(valid) = self.entries.get(&key).copied() {
+            self.hits += 1;
+            self.touch(&key);
+            return Ok(valid);
+        }
+        // errors are not cached, since they're caused by malformed input
+        let valid = transaction.verify_signature(pubkey)?;
+        self.insert(key, valid);
+        Ok(valid)
+    }
+
+    /// Amount of verifications served from the cache
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Amount of cached results
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Mark `key` as the most recently used
+    fn touch(&mut self, key: &VerificationKey) {
+        if let Some(pos) = self.recency.iter().position(|x| x == key) {
+            if let Some(key) = self.recency.remove(pos) {
+                self.recency.push_back(key);
+            }
+        }
+    }
+
+    /// Insert a new result, evicting the least recently used entry if the cache is full
+    fn insert(&mut self, key: VerificationKey, valid: bool) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity {
+            if let Some(evicted) = self.recency.pop_front() {
+                self.entries.remove(&evicted);
+            }
+        }
+        self.recency.push_back(key.clone());
+        self.entries.insert(key, valid);
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::blockchain::{TransactionBuilder, TransactionVersion};
+    use crate::wallet::Wallet;
+
+    use pretty_assertions::assert_eq;
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    /// Make a transfer of `amount` from `wallet` to bob, signed by `wallet`
+    fn signed_transfer(wallet: &Wallet, amount: Decimal) -> Transaction {
+        TransactionBuilder::transfer(TransactionVersion::V1, wallet.address(), "bob", amount)
+            .sign_with_wallet(wallet)
+            .unwrap()
+    }
+
+    #[test]
+    fn should_reuse_cached_verification() {
+        let wallet = Wallet::new();
+        let transaction = signed_transfer(&wallet, dec!(10.0));
+        let mut cache = VerificationCache::new(8);
+        assert_eq!(
+            cache
+                .verify_transaction(&transaction, &wallet.public_key())
+                .unwrap(),
+            true
+        );
+        assert_eq!(cache.hits(), 0);
+        // the same transaction received again, e.g. rebuilt with another fee output, hits the cache
+        let rebuilt = TransactionBuilder::from_existing(&transaction)
+            .output("miner", dec!(1.0))
+            .finish(transaction.signature());
+        assert_eq!(
+            cache
+                .verify_transaction(&rebuilt, &wallet.public_key())
+                .unwrap(),
+            true
+        );
+        assert_eq!(cache.hits(), 1);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn should_not_serve_cached_result_for_tampered_transaction() {
+        let wallet = Wallet::new();
+        let transaction = signed_transfer(&wallet, dec!(10.0));
+        let mut cache = VerificationCache::new(8);
+        assert!(cache
+            .verify_transaction(&transaction, &wallet.public_key())
+            .unwrap());
+        // same signature on a different amount
+        let tampered = TransactionBuilder::transfer(
+            TransactionVersion::V1,
+            wallet.address(),
+            "bob",
+            dec!(1000.0),
+        )
+        .finish(transaction.signature());
+        assert_eq!(
+            cache
+                .verify_transaction(&tampered, &wallet.public_key())
+                .unwrap(),
+            false
+        );
+        assert_eq!(cache.hits(), 0);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn should_evict_least_recently_used_verification() {
+        let wallet = Wallet::new();
+        let mut cache = VerificationCache::new(2);
+        let first = signed_transfer(&wallet, dec!(1.0));
+        let second = signed_transfer(&wallet, dec!(2.0));
+        let third = signed_transfer(&wallet, dec!(3.0));
+        let pubkey = wallet.public_key();
+        cache.verify_transaction(&first, &pubkey).unwrap();
+        cache.verify_transaction(&second, &pubkey).unwrap();
+        // use first again, so second becomes the least recently used
+        cache.verify_transaction(&first, &pubkey).unwrap();
+        cache.verify_transaction(&third, &pubkey).unwrap();
+        assert_eq!(cache.len(), 2);
+        cache.verify_transaction(&first, &pubkey).unwrap();
+        assert_eq!(cache.hits(), 2);
+        cache.verify_transaction(&second, &pubkey).unwrap();
+        assert_eq!(cache.hits(), 2);
+    }
+}
@@ -2,17 +2,23 @@
 //!
 //! This module exposes all the datatype related to jab wallets
 
+mod cache;
 mod errors;
+mod signer;
 
-pub use errors::WalletError;
-use errors::WalletResult;
+pub use cache::{VerificationCache, DEFAULT_VERIFICATION_CACHE_SIZE};
+pub use errors::{WalletError, WalletResult};
+pub use signer::Signer;
 
 use data_encoding::HEXLOWER;
 use ring::digest::{Context, SHA256};
 use ripemd::{Digest, Ripemd160};
 pub use secp256k1::constants::SECRET_KEY_SIZE;
-use secp256k1::{ecdsa::Signature, rand::rngs::OsRng, Message, PublicKey, Secp256k1, SecretKey};
+use secp256k1::{
+    ecdsa::Signature, rand::rngs::OsRng, Message, PublicKey, Secp256k1, SecretKey, ONE_KEY,
+};
 use std::str::FromStr;
+pub use zeroize::Zeroizing;
 
 /// Jab wallet type
 pub struct Wallet {
@@ -20,7 +26,7 @@ pub struct Wallet {
     address: String,
     /// Wallet public key
     public_key: PublicKey,
-    /// Wallet secret key (DON'T SHARE WITH ANYBODY)
+    /// Wallet secret key (DON'T SHARE WITH ANYBODY). It's erased when the wallet is dropped
     secret_key: SecretKey,
 }
 
@@ -47,9 +53,19 @@ impl Wallet {
         self.public_key.to_string()
     }
 
-    /// Get secret key
-    pub fn secret_key(&self) -> [u8; SECRET_KEY_SIZE] {
-        self.secret_key.secret_bytes()
+    /// Get public key as bytes, either in the compressed (33 bytes) or in the uncompressed (65 bytes) form.
+    /// Addresses are always derived from the compressed form
+    pub fn public_key_bytes(&self, compressed: bool) -> Vec<u8> {
+        if compressed {
+            self.public_key.serialize().to_vec()
+        } else {
+            self.public_key.serialize_uncompressed().to_vec()
+        }
+    }
+
+    /// Get a copy of the secret key, which is zeroized once dropped
+    pub fn secret_key(&self) -> Zeroizing<[u8; SECRET_KEY_SIZE]> {
+        Zeroizing::new(self.secret_key.secret_bytes())
     }
 
     /// Return wallet address
@@ -73,6 +89,19 @@ impl Wallet {
         Ok(secp.sign_ecdsa(&message, &self.secret_key).to_string())
     }
 
+    /// Check whether `pubkey` is a well-formed public key
+    pub fn validate_public_key(pubkey: &str) -> WalletResult<()> {
+        PublicKey::from_str(pubkey)
+            .map(|_| ())
+            .map_err(WalletError::from)
+    }
+
+    /// Get the address of the wallet owning `pubkey`
+    pub fn address_from_public_key(pubkey: &str) -> WalletResult<String> {
+        let pubkey = PublicKey::from_str(pubkey)?;
+        Ok(Self::calc_address(&pubkey))
+    }
+
     /// Calculate the wallet address
     ///
     /// The address format is `jab{RIPEMD160(SHA256(pubkey))}`
@@ -85,6 +114,19 @@ impl Wallet {
         let result = ripe_hasher.finalize();
         format!("jab{}", HEXLOWER.encode(&result))
     }
+
+    /// Overwrite the secret key with a constant, valid key, as `SecretKey::non_secure_erase` does in later secp256k1
+    /// releases. The write is volatile, so it isn't optimized away
+    fn erase_secret_key(&mut self) {
+        // SAFETY: `self.secret_key` is a valid, aligned and exclusively borrowed `SecretKey`
+        unsafe { std::ptr::write_volatile(&mut self.secret_key, ONE_KEY) };
+    }
+}
+
+impl Drop for Wallet {
+    fn drop(&mut self) {
+        self.erase_secret_key();
+    }
 }
 
 impl TryFrom<&[u8]> for Wallet {
@@ -120,6 +162,21 @@ mod test {
         assert!(wallet.address().starts_with("jab"));
     }
 
+    #[test]
+    fn should_get_address_from_public_key() {
+        let wallet = Wallet::new();
+        assert_eq!(
+            Wallet::address_from_public_key(&wallet.public_key()).unwrap(),
+            wallet.address()
+        );
+        assert_ne!(
+            Wallet::address_from_public_key(&Wallet::new().public_key()).unwrap(),
+            wallet.address()
+        );
+        assert!(Wallet::address_from_public_key("cafebabe").is_err());
+        assert!(Wallet::address_from_public_key("").is_err());
+    }
+
     #[test]
     fn should_fail_wallet_verify() {
         let wallet = Wallet::new();
@@ -132,10 +189,44 @@ mod test {
         );
     }
 
+    #[test]
+    fn should_get_public_key_bytes() {
+        let wallet = Wallet::new();
+        let compressed = wallet.public_key_bytes(true);
+        let uncompressed = wallet.public_key_bytes(false);
+        assert_eq!(compressed.len(), 33);
+        assert_eq!(uncompressed.len(), 65);
+        assert_eq!(HEXLOWER.encode(&compressed), wallet.public_key());
+        assert_eq!(
+            PublicKey::from_slice(&uncompressed).unwrap(),
+            PublicKey::from_slice(&compressed).unwrap()
+        );
+    }
+
     #[test]
     fn should_generate_wallet_from_keys() {
         let wallet = Wallet::new();
         let copy_wallet = Wallet::try_from(wallet.secret_key().as_slice()).unwrap();
         assert_eq!(copy_wallet.public_key(), wallet.public_key());
     }
+
+    #[test]
+    fn should_erase_secret_key() {
+        let mut wallet = Wallet::new();
+        let secret_key = wallet.secret_key();
+        wallet.erase_secret_key();
+        assert_ne!(*wallet.secret_key(), *secret_key);
+        assert_eq!(*wallet.secret_key(), ONE_KEY.secret_bytes());
+    }
+
+    #[test]
+    fn should_zeroize_secret_key_copy() {
+        use zeroize::Zeroize;
+
+        let wallet = Wallet::new();
+        let mut secret_key = wallet.secret_key();
+        assert!(secret_key.iter().any(|x| *x != 0));
+        secret_key.zeroize();
+        assert!(secret_key.iter().all(|x| *x == 0));
+    }
 }
@@ -0,0 +1,26 @@
+//! # Signer
+//!
+//! This module exposes the trait used to sign transactions, which decouples signing from key storage
+
+use super::{Wallet, WalletResult};
+
+/// Signs message digests on behalf of the owner of a public key.
+/// It is implemented by the software `Wallet`, but it can be implemented by external signers too (e.g. an HSM),
+/// so the secret key never needs to be loaded by jab
+pub trait Signer {
+    /// Sign `message` digest, returning the signature as string
+    fn sign(&self, message: &[u8]) -> WalletResult<String>;
+
+    /// Get the public key of the signer as string
+    fn public_key(&self) -> String;
+}
+
+impl Signer for Wallet {
+    fn sign(&self, message: &[u8]) -> WalletResult<String> {
+        Wallet::sign(self, message)
+    }
+
+    fn public_key(&self) -> String {
+        Wallet::public_key(self)
+    }
+}